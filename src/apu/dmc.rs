@@ -0,0 +1,255 @@
+use crate::mapper::Mapper;
+use crate::state::{StateError, StateReader, StateWriter};
+
+// output timer periods in CPU cycles (NTSC)
+const RATES: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+// cycles the CPU loses while the memory reader takes the bus
+pub(super) const FETCH_STALL: u16 = 4;
+
+// delta modulation channel at 0x4010-0x4013: plays 1-bit deltas fetched
+// straight from PRG by its own DMA
+pub(super) struct Dmc {
+    irq_enabled: bool,
+    looping: bool,
+    period: u16,
+    timer: u16,
+    level: u8,
+    sample_address: u16,
+    sample_length: u16,
+    address: u16,
+    bytes_remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits_remaining: u8,
+    silence: bool,
+    pub(super) irq: bool,
+}
+
+impl Dmc {
+    pub(super) fn new() -> Self {
+        Dmc {
+            irq_enabled: false,
+            looping: false,
+            period: RATES[0],
+            timer: 0,
+            level: 0,
+            sample_address: 0xC000,
+            sample_length: 1,
+            address: 0xC000,
+            bytes_remaining: 0,
+            buffer: None,
+            shift: 0,
+            bits_remaining: 8,
+            silence: true,
+            irq: false,
+        }
+    }
+
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.irq_enabled = data & 0b1000_0000 != 0;
+                self.looping = data & 0b0100_0000 != 0;
+                self.period = RATES[(data & 0b1111) as usize];
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+            }
+            1 => self.level = data & 0b0111_1111,
+            2 => self.sample_address = 0xC000 + data as u16 * 64,
+            _ => self.sample_length = data as u16 * 16 + 1,
+        }
+    }
+
+    // bit 4 of 0x4015, which restarts a finished sample
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.irq = false;
+        if !enabled {
+            self.bytes_remaining = 0;
+        } else if self.bytes_remaining == 0 {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.address = self.sample_address;
+        self.bytes_remaining = self.sample_length;
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.bytes_remaining > 0
+    }
+
+    // clocked every CPU cycle, returns the address and byte of a sample
+    // fetch, which stalls the CPU for FETCH_STALL cycles
+    pub(super) fn clock(&mut self, mapper: &dyn Mapper) -> Option<(u16, u8)> {
+        let fetch = self.fetch(mapper);
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            self.clock_output();
+        } else {
+            self.timer -= 1;
+        }
+        fetch
+    }
+
+    // the memory reader refills the buffer as soon as it empties
+    fn fetch(&mut self, mapper: &dyn Mapper) -> Option<(u16, u8)> {
+        if self.buffer.is_some() || self.bytes_remaining == 0 {
+            return None;
+        }
+        let addr = self.address;
+        let byte = mapper.read_prg(addr);
+        self.buffer = Some(byte);
+        // the address wraps from 0xFFFF back to 0x8000
+        self.address = self.address.checked_add(1).unwrap_or(0x8000);
+        self.bytes_remaining -= 1;
+        if self.bytes_remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+        Some((addr, byte))
+    }
+
+    fn clock_output(&mut self) {
+        if !self.silence {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+        self.bits_remaining -= 1;
+        if self.bits_remaining == 0 {
+            self.bits_remaining = 8;
+            match self.buffer.take() {
+                Some(byte) => {
+                    self.silence = false;
+                    self.shift = byte;
+                }
+                None => self.silence = true,
+            }
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        self.level
+    }
+}
+
+impl Dmc {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.irq_enabled);
+        state.bool(self.looping);
+        state.u16(self.period);
+        state.u16(self.timer);
+        state.u8(self.level);
+        state.u16(self.sample_address);
+        state.u16(self.sample_length);
+        state.u16(self.address);
+        state.u16(self.bytes_remaining);
+        state.bool(self.buffer.is_some());
+        state.u8(self.buffer.unwrap_or(0));
+        state.u8(self.shift);
+        state.u8(self.bits_remaining);
+        state.bool(self.silence);
+        state.bool(self.irq);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.irq_enabled = state.bool()?;
+        self.looping = state.bool()?;
+        self.period = state.u16_in(1..=u16::MAX, "DMC period")?;
+        self.timer = state.u16()?;
+        self.level = state.u8_in(0..=127, "DMC level")?;
+        self.sample_address = state.u16()?;
+        self.sample_length = state.u16()?;
+        self.address = state.u16()?;
+        self.bytes_remaining = state.u16()?;
+        let buffered = state.bool()?;
+        let byte = state.u8()?;
+        self.buffer = buffered.then_some(byte);
+        self.shift = state.u8()?;
+        self.bits_remaining = state.u8_in(1..=8, "DMC bits remaining")?;
+        self.silence = state.bool()?;
+        self.irq = state.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::Nrom;
+
+    // the mappers here have every PRG byte at 0xFF, so each delta steps up
+    #[test]
+    fn fetches_sample_and_raises_irq() {
+        let mapper = Nrom::filled(0xFF);
+        let mut dmc = Dmc::new();
+        // IRQ on, fastest rate, one byte from 0xC000
+        dmc.write(0, 0b1000_1111);
+        dmc.write(3, 0);
+        dmc.set_enabled(true);
+        assert!(dmc.active());
+        assert_eq!(dmc.clock(&mapper), Some((0xC000, 0xFF)));
+        assert!(!dmc.active());
+        assert!(dmc.irq);
+        assert_eq!(dmc.clock(&mapper), None);
+    }
+
+    #[test]
+    fn loops_without_irq() {
+        let mapper = Nrom::filled(0xFF);
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0b1100_1111);
+        dmc.write(3, 0);
+        dmc.set_enabled(true);
+        dmc.clock(&mapper);
+        assert!(dmc.active());
+        assert!(!dmc.irq);
+    }
+
+    #[test]
+    fn steps_level_by_two_per_bit() {
+        let mapper = Nrom::filled(0xFF);
+        let mut dmc = Dmc::new();
+        dmc.write(0, 0b0000_1111);
+        dmc.write(1, 10);
+        dmc.write(3, 0);
+        dmc.set_enabled(true);
+        // the first output cycle is silent and loads the buffered byte
+        for _ in 0..54 * 8 {
+            dmc.clock(&mapper);
+        }
+        assert_eq!(dmc.output(), 10);
+        for _ in 0..54 * 8 {
+            dmc.clock(&mapper);
+        }
+        assert_eq!(dmc.output(), 26);
+    }
+
+    #[test]
+    fn address_wraps_to_8000() {
+        let mapper = Nrom::filled(0xFF);
+        let mut dmc = Dmc::new();
+        // 65 bytes from 0xFFC0
+        dmc.write(2, 0xFF);
+        dmc.write(3, 4);
+        dmc.set_enabled(true);
+        for _ in 0..0x40 {
+            dmc.buffer = None;
+            dmc.fetch(&mapper);
+        }
+        assert_eq!(dmc.address, 0x8000);
+    }
+}
@@ -0,0 +1,137 @@
+use std::f32::consts::PI;
+
+use crate::state::{StateError, StateReader, StateWriter};
+
+// the console's output stage: two high-pass filters at 90 Hz and 440 Hz
+// and a low-pass at 14 kHz, all first order
+const CHAIN: [(Kind, f32); 3] = [
+    (Kind::HighPass, 90.0),
+    (Kind::HighPass, 440.0),
+    (Kind::LowPass, 14_000.0),
+];
+
+#[derive(Clone, Copy)]
+enum Kind {
+    HighPass,
+    LowPass,
+}
+
+struct Filter {
+    kind: Kind,
+    alpha: f32,
+    prev_in: f32,
+    prev_out: f32,
+}
+
+impl Filter {
+    fn new(kind: Kind, cutoff: f32, sample_rate: u32) -> Self {
+        let rc = 1.0 / (2.0 * PI * cutoff);
+        let dt = 1.0 / sample_rate as f32;
+        let alpha = match kind {
+            Kind::HighPass => rc / (rc + dt),
+            Kind::LowPass => dt / (rc + dt),
+        };
+        Filter {
+            kind,
+            alpha,
+            prev_in: 0.0,
+            prev_out: 0.0,
+        }
+    }
+
+    fn process(&mut self, sample: f32) -> f32 {
+        let out = match self.kind {
+            Kind::HighPass => self.alpha * (self.prev_out + sample - self.prev_in),
+            Kind::LowPass => self.prev_out + self.alpha * (sample - self.prev_out),
+        };
+        self.prev_in = sample;
+        self.prev_out = out;
+        out
+    }
+}
+
+// one chain per output channel, run at the output sample rate
+pub(super) struct FilterChain {
+    filters: [Filter; 3],
+}
+
+impl FilterChain {
+    pub(super) fn new(sample_rate: u32) -> Self {
+        FilterChain {
+            filters: CHAIN.map(|(kind, cutoff)| Filter::new(kind, cutoff, sample_rate)),
+        }
+    }
+
+    pub(super) fn process(&mut self, sample: f32) -> f32 {
+        self.filters
+            .iter_mut()
+            .fold(sample, |sample, filter| filter.process(sample))
+    }
+}
+
+impl FilterChain {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        for filter in &self.filters {
+            state.u32(filter.prev_in.to_bits());
+            state.u32(filter.prev_out.to_bits());
+        }
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        for filter in &mut self.filters {
+            filter.prev_in = finite(state.u32()?)?;
+            filter.prev_out = finite(state.u32()?)?;
+        }
+        Ok(())
+    }
+}
+
+fn finite(bits: u32) -> Result<f32, StateError> {
+    let value = f32::from_bits(bits);
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(StateError::InvalidValue("filter memory"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // peak output for a sine at freq once the filters have settled
+    fn response(freq: f32) -> f32 {
+        let rate = 48_000;
+        let mut chain = FilterChain::new(rate);
+        (0..rate)
+            .map(|n| chain.process((2.0 * PI * freq * n as f32 / rate as f32).sin()))
+            .skip(rate as usize / 2)
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn passes_midrange_and_cuts_the_extremes() {
+        assert!(response(2_000.0) > 0.85);
+        assert!(response(20.0) < 0.1);
+        assert!(response(22_000.0) < 0.6);
+    }
+
+    #[test]
+    fn removes_dc_offset() {
+        let mut chain = FilterChain::new(44_100);
+        let last = (0..44_100).map(|_| chain.process(0.5)).last().unwrap();
+        assert!(last.abs() < 1e-3);
+    }
+
+    #[test]
+    fn rejects_non_finite_memory() {
+        let mut state = StateWriter::new(0);
+        state.u32(f32::NAN.to_bits());
+        let data = state.finish();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(
+            FilterChain::new(44_100).load_state(&mut reader),
+            Err(StateError::InvalidValue("filter memory"))
+        );
+    }
+}
@@ -0,0 +1,773 @@
+mod dmc;
+mod filter;
+mod noise;
+mod pulse;
+mod resample;
+mod triangle;
+mod units;
+
+use crate::mapper::Mapper;
+use crate::state::{StateError, StateReader, StateWriter};
+use dmc::Dmc;
+use filter::FilterChain;
+use noise::Noise;
+use pulse::Pulse;
+use resample::Resampler;
+use triangle::Triangle;
+
+pub const CPU_CLOCK_RATE: u32 = 1_789_773;
+
+// receives each output sample, one value per channel, as it is made
+pub type SampleSink = Box<dyn FnMut(&[f32]) + Send>;
+pub const DEFAULT_SAMPLE_RATE: u32 = 44_100;
+
+const STATUS: u16 = 0x4015;
+const FRAME_COUNTER: u16 = 0x4017;
+
+pub const CHANNELS: usize = 5;
+
+const DMC_ACTIVE: u8 = 0b0001_0000;
+const FRAME_IRQ: u8 = 0b0100_0000;
+const DMC_IRQ: u8 = 0b1000_0000;
+
+// frame counter steps in CPU cycles, the last one also wraps the sequence
+const QUARTER_FRAMES: [u32; 4] = [7457, 14913, 22371, 29829];
+const FOUR_STEP_LENGTH: u32 = 29830;
+const FIVE_STEP_LENGTH: u32 = 37282;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Pulse1,
+    Pulse2,
+    Triangle,
+    Noise,
+    Dmc,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelLayout {
+    Mono,
+    // samples come in left/right pairs
+    Stereo,
+}
+
+impl ChannelLayout {
+    pub fn channels(self) -> usize {
+        match self {
+            ChannelLayout::Mono => 1,
+            ChannelLayout::Stereo => 2,
+        }
+    }
+}
+
+// levels applied in the mixer, arrays are indexed by Channel
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    // 0.0..=1.0, scales the mixed output
+    pub master_volume: f32,
+    // 0.0..=1.0 per channel, before the non-linear mix
+    pub per_channel_volume: [f32; CHANNELS],
+    // -1.0 is left only, 1.0 right only; ignored in mono. Centered
+    // channels play at full level on both sides, so centered stereo
+    // matches mono
+    pub per_channel_pan: [f32; CHANNELS],
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            master_volume: 1.0,
+            per_channel_volume: [1.0; CHANNELS],
+            per_channel_pan: [0.0; CHANNELS],
+        }
+    }
+}
+
+impl AudioConfig {
+    // out of range values are pulled to the nearest bound, NaN to the lower
+    fn clamped(self) -> Self {
+        let clamp = |v: f32, min, max| if v.is_nan() { min } else { v.clamp(min, max) };
+        AudioConfig {
+            master_volume: clamp(self.master_volume, 0.0, 1.0),
+            per_channel_volume: self.per_channel_volume.map(|v| clamp(v, 0.0, 1.0)),
+            per_channel_pan: self.per_channel_pan.map(|p| clamp(p, -1.0, 1.0)),
+        }
+    }
+
+    // each channel's level in mono, then on the left and on the right
+    fn gains(&self) -> [[f32; CHANNELS]; 3] {
+        let mut gains = [self.per_channel_volume; 3];
+        for (channel, pan) in self.per_channel_pan.iter().enumerate() {
+            gains[1][channel] *= (1.0 - pan).min(1.0);
+            gains[2][channel] *= (1.0 + pan).min(1.0);
+        }
+        gains
+    }
+}
+
+// the sound chip at 0x4000-0x4017: two pulse channels, a triangle, noise
+// and the DMC, all clocked from the CPU and mixed into samples at the output rate
+pub struct Apu {
+    pulse_1: Pulse,
+    pulse_2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    // CPU cycles lost to DMC fetches, not yet charged to the CPU
+    dmc_stall: u16,
+    // (cycle within the last tick, address, byte) of each sample fetch,
+    // kept only for bus traces
+    record_fetches: bool,
+    dmc_fetches: Vec<(u16, u16, u8)>,
+    cycle: u64,
+    frame_cycle: u32,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    resampler: Resampler,
+    layout: ChannelLayout,
+    config: AudioConfig,
+    // mono, left and right level of each channel, worked out from config
+    gains: [[f32; CHANNELS]; 3],
+    // off by default, when on the output is centered on 0.0
+    filters_enabled: bool,
+    // left and right, mono only uses the first
+    filters: [FilterChain; 2],
+    output: Vec<f32>,
+    sample_sink: Option<SampleSink>,
+    // frames run only to be rolled back make no sound
+    muted: bool,
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            pulse_1: Pulse::new(true),
+            pulse_2: Pulse::new(false),
+            triangle: Triangle::new(),
+            noise: Noise::new(),
+            dmc: Dmc::new(),
+            dmc_stall: 0,
+            record_fetches: false,
+            dmc_fetches: Vec::new(),
+            cycle: 0,
+            frame_cycle: 0,
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            resampler: Resampler::new(CPU_CLOCK_RATE, DEFAULT_SAMPLE_RATE),
+            layout: ChannelLayout::Mono,
+            config: AudioConfig::default(),
+            gains: AudioConfig::default().gains(),
+            filters_enabled: false,
+            filters: [
+                FilterChain::new(DEFAULT_SAMPLE_RATE),
+                FilterChain::new(DEFAULT_SAMPLE_RATE),
+            ],
+            output: Vec::new(),
+            sample_sink: None,
+            muted: false,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.resampler.output_rate()
+    }
+
+    // the host's rate, usually 44100 or 48000. At most one sample comes out
+    // per CPU cycle, so faster rates are capped at the CPU clock rate
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        assert!(sample_rate > 0, "sample rate must be nonzero");
+        self.resampler
+            .set_output_rate(sample_rate.min(CPU_CLOCK_RATE));
+        self.reset_filters();
+    }
+
+    pub fn filters_enabled(&self) -> bool {
+        self.filters_enabled
+    }
+
+    // the high- and low-pass filters of the console's output stage, which
+    // take out the DC offset the mixer leaves, so samples then fall in
+    // -1.0..=1.0
+    pub fn set_filters_enabled(&mut self, enabled: bool) {
+        self.filters_enabled = enabled;
+        self.reset_filters();
+    }
+
+    fn reset_filters(&mut self) {
+        let rate = self.sample_rate();
+        self.filters = [FilterChain::new(rate), FilterChain::new(rate)];
+    }
+
+    pub fn channel_layout(&self) -> ChannelLayout {
+        self.layout
+    }
+
+    // drops samples already generated, which are in the old layout
+    pub fn set_channel_layout(&mut self, layout: ChannelLayout) {
+        self.layout = layout;
+        self.output.clear();
+    }
+
+    pub fn audio_config(&self) -> AudioConfig {
+        self.config
+    }
+
+    pub fn set_audio_config(&mut self, config: AudioConfig) {
+        self.config = config.clamped();
+        self.gains = self.config.gains();
+    }
+
+    // samples in 0.0..=1.0, or -1.0..=1.0 with the filters on, generated
+    // since the last clear_output, left and right interleaved in stereo, at
+    // most two seconds' worth once nothing takes them
+    pub fn output(&self) -> &[f32] {
+        &self.output
+    }
+
+    // for headless runs: every sample goes to the sink instead of the
+    // bounded output buffer, so none is dropped when nothing drains it.
+    // Samples depend only on emulated cycles, so the stream is the same on
+    // every run
+    pub fn set_sample_sink(&mut self, sink: impl FnMut(&[f32]) + Send + 'static) {
+        self.sample_sink = Some(Box::new(sink));
+    }
+
+    pub fn clear_sample_sink(&mut self) {
+        self.sample_sink = None;
+    }
+
+    pub(crate) fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn clear_output(&mut self) {
+        self.output.clear();
+    }
+
+    pub fn take_output(&mut self) -> Vec<f32> {
+        std::mem::take(&mut self.output)
+    }
+
+    // pulls the oldest samples into buf for an audio callback, padding with
+    // silence when emulation has fallen behind. Returns the samples written
+    pub fn fill(&mut self, buf: &mut [f32]) -> usize {
+        let count = buf.len().min(self.output.len());
+        for (dst, src) in buf.iter_mut().zip(self.output.drain(..count)) {
+            *dst = src;
+        }
+        buf[count..].fill(0.0);
+        count
+    }
+
+    pub(crate) fn write_register(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse_1.write(addr & 3, data),
+            0x4004..=0x4007 => self.pulse_2.write(addr & 3, data),
+            0x4008..=0x400B => self.triangle.write(addr & 3, data),
+            0x400C..=0x400F => self.noise.write(addr & 3, data),
+            0x4010..=0x4013 => self.dmc.write(addr & 3, data),
+            STATUS => {
+                self.pulse_1.length.set_enabled(data & 0b0001 != 0);
+                self.pulse_2.length.set_enabled(data & 0b0010 != 0);
+                self.triangle.length.set_enabled(data & 0b0100 != 0);
+                self.noise.length.set_enabled(data & 0b1000 != 0);
+                self.dmc.set_enabled(data & 0b1_0000 != 0);
+            }
+            FRAME_COUNTER => {
+                self.five_step = data & 0b1000_0000 != 0;
+                self.irq_inhibit = data & 0b0100_0000 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                // the five-step sequence clocks everything straight away
+                if self.five_step {
+                    self.clock_quarter_frame();
+                    self.clock_half_frame();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // reading 0x4015 acknowledges the frame IRQ
+    pub(crate) fn read_status(&mut self) -> u8 {
+        let status = self.peek_status();
+        self.frame_irq = false;
+        status
+    }
+
+    pub(crate) fn peek_status(&self) -> u8 {
+        let mut status = 0;
+        status |= self.pulse_1.length.active() as u8;
+        status |= (self.pulse_2.length.active() as u8) << 1;
+        status |= (self.triangle.length.active() as u8) << 2;
+        status |= (self.noise.length.active() as u8) << 3;
+        if self.dmc.active() {
+            status |= DMC_ACTIVE;
+        }
+        if self.frame_irq {
+            status |= FRAME_IRQ;
+        }
+        if self.dmc.irq {
+            status |= DMC_IRQ;
+        }
+        status
+    }
+
+    pub(crate) fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    pub(crate) fn take_dmc_stall(&mut self) -> u16 {
+        std::mem::take(&mut self.dmc_stall)
+    }
+
+    pub(crate) fn set_record_fetches(&mut self, enabled: bool) {
+        self.record_fetches = enabled;
+        self.dmc_fetches.clear();
+    }
+
+    pub(crate) fn take_dmc_fetches(&mut self) -> Vec<(u16, u16, u8)> {
+        std::mem::take(&mut self.dmc_fetches)
+    }
+
+    // the DMC reads its samples from PRG through the mapper
+    pub(crate) fn tick(&mut self, cycles: u16, mapper: &dyn Mapper) {
+        for cycle in 0..cycles {
+            if let Some((addr, byte)) = self.dmc.clock(mapper) {
+                self.dmc_stall += dmc::FETCH_STALL;
+                if self.record_fetches {
+                    self.dmc_fetches.push((cycle, addr, byte));
+                }
+            }
+            self.clock();
+        }
+    }
+
+    fn clock(&mut self) {
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        // the pulse timers run at half the CPU rate
+        if self.cycle % 2 == 1 {
+            self.pulse_1.clock_timer();
+            self.pulse_2.clock_timer();
+        }
+        self.cycle += 1;
+        self.clock_frame_counter();
+
+        let master = self.config.master_volume;
+        let sample = match self.layout {
+            ChannelLayout::Mono => [self.mix(&self.gains[0]) * master, 0.0],
+            ChannelLayout::Stereo => [
+                self.mix(&self.gains[1]) * master,
+                self.mix(&self.gains[2]) * master,
+            ],
+        };
+        if let Some(mut sample) = self.resampler.push(sample).filter(|_| !self.muted) {
+            let channels = self.layout.channels();
+            if self.filters_enabled {
+                for (sample, filter) in sample.iter_mut().zip(&mut self.filters) {
+                    *sample = filter.process(*sample);
+                }
+            }
+            if let Some(sink) = &mut self.sample_sink {
+                sink(&sample[..channels]);
+            } else {
+                self.output.extend_from_slice(&sample[..channels]);
+                // nobody is draining the output, keep only the last second or so
+                let max = self.sample_rate() as usize * channels;
+                if self.output.len() >= 2 * max {
+                    self.output.drain(..self.output.len() - max);
+                }
+            }
+        }
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        let step = QUARTER_FRAMES.iter().position(|&c| c == self.frame_cycle);
+        match step {
+            Some(0) | Some(2) => self.clock_quarter_frame(),
+            Some(1) => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+            }
+            Some(3) if !self.five_step => {
+                self.clock_quarter_frame();
+                self.clock_half_frame();
+                if !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+            }
+            _ => {}
+        }
+        if self.frame_cycle == FIVE_STEP_LENGTH - 1 && self.five_step {
+            self.clock_quarter_frame();
+            self.clock_half_frame();
+        }
+        let length = if self.five_step {
+            FIVE_STEP_LENGTH
+        } else {
+            FOUR_STEP_LENGTH
+        };
+        if self.frame_cycle == length {
+            self.frame_cycle = 0;
+        }
+    }
+
+    fn clock_quarter_frame(&mut self) {
+        self.pulse_1.envelope.clock();
+        self.pulse_2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_linear();
+    }
+
+    fn clock_half_frame(&mut self) {
+        self.pulse_1.length.clock();
+        self.pulse_2.length.clock();
+        self.triangle.length.clock();
+        self.noise.length.clock();
+        self.pulse_1.clock_sweep();
+        self.pulse_2.clock_sweep();
+    }
+
+    // the non-linear mixer of the real DAC, with each channel scaled by
+    // its gain on the side being mixed
+    fn mix(&self, gains: &[f32; CHANNELS]) -> f32 {
+        let level = |channel: Channel, output: u8| output as f32 * gains[channel as usize];
+        let pulse = level(Channel::Pulse1, self.pulse_1.output())
+            + level(Channel::Pulse2, self.pulse_2.output());
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+        let tnd = level(Channel::Triangle, self.triangle.output()) / 8227.0
+            + level(Channel::Noise, self.noise.output()) / 12241.0
+            + level(Channel::Dmc, self.dmc.output()) / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+        pulse_out + tnd_out
+    }
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        // the output buffer is left to the frontend
+        self.pulse_1.save_state(state);
+        self.pulse_2.save_state(state);
+        self.triangle.save_state(state);
+        self.noise.save_state(state);
+        self.dmc.save_state(state);
+        state.u16(self.dmc_stall);
+        state.u64(self.cycle);
+        state.u32(self.frame_cycle);
+        state.bool(self.five_step);
+        state.bool(self.irq_inhibit);
+        state.bool(self.frame_irq);
+        self.resampler.save_state(state);
+        for filter in &self.filters {
+            filter.save_state(state);
+        }
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.pulse_1.load_state(state)?;
+        self.pulse_2.load_state(state)?;
+        self.triangle.load_state(state)?;
+        self.noise.load_state(state)?;
+        self.dmc.load_state(state)?;
+        self.dmc_stall = state.u16()?;
+        self.cycle = state.u64()?;
+        self.frame_cycle = state.u32_in(0..=FIVE_STEP_LENGTH, "frame counter cycle")?;
+        self.five_step = state.bool()?;
+        self.irq_inhibit = state.bool()?;
+        self.frame_irq = state.bool()?;
+        self.resampler.load_state(state)?;
+        for filter in &mut self.filters {
+            filter.load_state(state)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::Nrom;
+
+    #[test]
+    fn generates_samples_at_output_rate() {
+        let mut apu = Apu::new();
+        apu.tick(29830, &Nrom::filled(0));
+        // 1/60th of a second
+        assert_eq!(apu.output().len(), 735);
+        assert_eq!(apu.take_output().len(), 735);
+        assert!(apu.output().is_empty());
+
+        apu.set_sample_rate(48_000);
+        apu.tick(29830, &Nrom::filled(0));
+        assert_eq!(apu.output().len(), 800);
+    }
+
+    #[test]
+    fn drops_oldest_samples_when_not_drained() {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(1000);
+        // three seconds of frames
+        for _ in 0..180 {
+            apu.tick(29830, &Nrom::filled(0));
+        }
+        assert!(apu.output().len() >= 1000);
+        assert!(apu.output().len() < 2000);
+    }
+
+    #[test]
+    fn caps_sample_rate_at_cpu_clock() {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(u32::MAX);
+        assert_eq!(apu.sample_rate(), CPU_CLOCK_RATE);
+        apu.tick(1000, &Nrom::filled(0));
+        assert_eq!(apu.output().len(), 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "sample rate must be nonzero")]
+    fn rejects_zero_sample_rate() {
+        Apu::new().set_sample_rate(0);
+    }
+
+    #[test]
+    fn fills_oldest_samples_first() {
+        let mut apu = Apu::new();
+        apu.output = vec![0.1, 0.2, 0.3];
+        let mut buf = [1.0; 2];
+        assert_eq!(apu.fill(&mut buf), 2);
+        assert_eq!(buf, [0.1, 0.2]);
+        let mut buf = [1.0; 3];
+        assert_eq!(apu.fill(&mut buf), 1);
+        assert_eq!(buf, [0.3, 0.0, 0.0]);
+        assert!(apu.output().is_empty());
+    }
+
+    #[test]
+    fn frame_irq_on_four_step_sequence() {
+        let mut apu = Apu::new();
+        apu.tick(29828, &Nrom::filled(0));
+        assert!(!apu.irq());
+        apu.tick(1, &Nrom::filled(0));
+        assert!(apu.irq());
+        assert_eq!(apu.peek_status(), FRAME_IRQ);
+        assert_eq!(apu.read_status(), FRAME_IRQ);
+        assert!(!apu.irq());
+
+        // inhibited, and five-step mode never raises it
+        apu.write_register(FRAME_COUNTER, 0b0100_0000);
+        apu.tick(30000, &Nrom::filled(0));
+        assert!(!apu.irq());
+        apu.write_register(FRAME_COUNTER, 0b1000_0000);
+        apu.tick(40000, &Nrom::filled(0));
+        assert!(!apu.irq());
+    }
+
+    #[test]
+    fn status_reports_running_length_counters() {
+        let mut apu = Apu::new();
+        apu.write_register(STATUS, 0b1111);
+        apu.write_register(0x4003, 0b0001_1000);
+        apu.write_register(0x400F, 0b0001_1000);
+        assert_eq!(apu.peek_status(), 0b1001);
+        apu.write_register(STATUS, 0b0001);
+        assert_eq!(apu.peek_status(), 0b0001);
+    }
+
+    #[test]
+    fn length_counter_runs_out_over_frames() {
+        let mut apu = Apu::new();
+        apu.write_register(STATUS, 0b0001);
+        // length index 3 is 2 half frames
+        apu.write_register(0x4003, 0b0001_1000);
+        apu.tick(14913, &Nrom::filled(0));
+        assert_eq!(apu.peek_status() & 1, 1);
+        apu.tick(29829 - 14913, &Nrom::filled(0));
+        assert_eq!(apu.peek_status() & 1, 0);
+    }
+
+    #[test]
+    fn mixes_pulse_output() {
+        let mut apu = Apu::new();
+        apu.write_register(STATUS, 0b0001);
+        // 12.5% duty at constant volume 15, with a period of 0x100
+        apu.write_register(0x4000, 0b0011_1111);
+        apu.write_register(0x4002, 0x00);
+        apu.write_register(0x4003, 0b1111_1001);
+        apu.tick(5000, &Nrom::filled(0));
+        // the idle triangle holds its first level, a constant offset
+        let loudest = apu.output().iter().cloned().fold(0.0, f32::max);
+        let quietest = apu.output().iter().cloned().fold(1.0, f32::min);
+        assert!((loudest - quietest - 95.88 / (8128.0 / 15.0 + 100.0)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn offers_common_output_rates() {
+        for (rate, per_frame) in [(44_100, 735), (48_000, 800), (96_000, 1600)] {
+            let mut apu = Apu::new();
+            apu.set_sample_rate(rate);
+            apu.tick(29830, &Nrom::filled(0));
+            assert_eq!(apu.output().len(), per_frame);
+        }
+    }
+
+    #[test]
+    fn pans_channels_in_stereo() {
+        let mut apu = Apu::new();
+        apu.set_channel_layout(ChannelLayout::Stereo);
+        let mut config = AudioConfig::default();
+        config.per_channel_pan[Channel::Pulse1 as usize] = -1.0;
+        apu.set_audio_config(config);
+        apu.write_register(STATUS, 0b0001);
+        apu.write_register(0x4000, 0b0011_1111);
+        apu.write_register(0x4002, 0x00);
+        apu.write_register(0x4003, 0b1111_1001);
+        apu.tick(29830, &Nrom::filled(0));
+        assert_eq!(apu.output().len(), 2 * 735);
+        let (left, right): (Vec<f32>, Vec<f32>) = apu
+            .output()
+            .chunks_exact(2)
+            .map(|pair| (pair[0], pair[1]))
+            .unzip();
+        let spread = |side: &[f32]| {
+            side.iter().cloned().fold(0.0, f32::max) - side.iter().cloned().fold(1.0, f32::min)
+        };
+        // the pulse only reaches the left side, the triangle offset both
+        assert!(spread(&left) > 0.1);
+        assert!(spread(&right) < 1e-6);
+    }
+
+    #[test]
+    fn applies_master_and_channel_volume() {
+        let run = |config| {
+            let mut apu = Apu::new();
+            apu.set_audio_config(config);
+            apu.write_register(STATUS, 0b0001);
+            apu.write_register(0x4000, 0b0011_1111);
+            apu.write_register(0x4003, 0b1111_1001);
+            apu.tick(5000, &Nrom::filled(0));
+            apu.take_output()
+        };
+        let full = run(AudioConfig::default());
+        let half = run(AudioConfig {
+            master_volume: 0.5,
+            ..AudioConfig::default()
+        });
+        for (full, half) in full.iter().zip(&half) {
+            assert!((full * 0.5 - half).abs() < 1e-6);
+        }
+
+        // muting every channel leaves silence
+        let muted = run(AudioConfig {
+            per_channel_volume: [0.0; CHANNELS],
+            ..AudioConfig::default()
+        });
+        assert!(muted.iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn filters_center_the_output() {
+        let mut apu = Apu::new();
+        apu.set_filters_enabled(true);
+        apu.write_register(STATUS, 0b0001);
+        apu.write_register(0x4000, 0b1011_1111);
+        apu.write_register(0x4003, 0b1111_1001);
+        for _ in 0..30 {
+            apu.tick(29830, &Nrom::filled(0));
+        }
+        let output = apu.take_output();
+        let mean = output.iter().sum::<f32>() / output.len() as f32;
+        assert!(mean.abs() < 0.01, "{mean}");
+        assert!(output.iter().any(|&sample| sample < 0.0));
+    }
+
+    #[test]
+    fn sink_gets_every_sample() {
+        use std::sync::{Arc, Mutex};
+
+        let samples = Arc::new(Mutex::new(Vec::new()));
+        let mut apu = Apu::new();
+        apu.set_sample_rate(1000);
+        let sink = Arc::clone(&samples);
+        apu.set_sample_sink(move |sample| sink.lock().unwrap().extend_from_slice(sample));
+        // three seconds, more than the output buffer keeps
+        for _ in 0..180 {
+            apu.tick(29830, &Nrom::filled(0));
+        }
+        assert!(apu.output().is_empty());
+        assert_eq!(samples.lock().unwrap().len(), 3000);
+    }
+
+    #[test]
+    fn clamps_audio_config() {
+        let mut apu = Apu::new();
+        apu.set_audio_config(AudioConfig {
+            master_volume: f32::NAN,
+            per_channel_volume: [2.0; CHANNELS],
+            per_channel_pan: [-3.0; CHANNELS],
+        });
+        let config = apu.audio_config();
+        assert_eq!(config.master_volume, 0.0);
+        assert_eq!(config.per_channel_volume, [1.0; CHANNELS]);
+        assert_eq!(config.per_channel_pan, [-1.0; CHANNELS]);
+    }
+
+    #[test]
+    fn centered_stereo_matches_mono() {
+        let run = |layout| {
+            let mut apu = Apu::new();
+            apu.set_channel_layout(layout);
+            apu.write_register(STATUS, 0b0001);
+            apu.write_register(0x4000, 0b1011_1111);
+            apu.write_register(0x4003, 0b1111_1001);
+            apu.tick(5000, &Nrom::filled(0));
+            apu.take_output()
+        };
+        let mono = run(ChannelLayout::Mono);
+        let stereo = run(ChannelLayout::Stereo);
+        let left: Vec<f32> = stereo.iter().step_by(2).cloned().collect();
+        assert_eq!(left, mono);
+    }
+
+    #[test]
+    fn dmc_fetches_stall_the_cpu_and_raise_irq() {
+        let mut apu = Apu::new();
+        apu.write_register(FRAME_COUNTER, 0b0100_0000);
+        apu.write_register(0x4010, 0b1000_1111);
+        // 17 bytes, one fetch every 8 output bits of 54 cycles
+        apu.write_register(0x4013, 1);
+        apu.write_register(STATUS, 0b1_0000);
+        assert_eq!(apu.peek_status(), DMC_ACTIVE);
+        apu.tick(1, &Nrom::filled(0));
+        assert_eq!(apu.take_dmc_stall(), 4);
+        apu.tick(16 * 54 * 8, &Nrom::filled(0));
+        assert_eq!(apu.take_dmc_stall(), 16 * 4);
+        assert!(apu.irq());
+        assert_eq!(apu.peek_status(), DMC_IRQ);
+
+        // writing 0x4015 acknowledges it, reading does not
+        apu.read_status();
+        assert!(apu.irq());
+        apu.write_register(STATUS, 0);
+        assert!(!apu.irq());
+    }
+}
@@ -0,0 +1,125 @@
+use super::units::{Envelope, LengthCounter};
+use crate::state::{StateError, StateReader, StateWriter};
+
+// timer periods in CPU cycles (NTSC)
+const PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+// pseudo-random noise at 0x400C-0x400F from a 15-bit shift register
+pub(super) struct Noise {
+    // short mode taps bit 6 instead of bit 1, giving a metallic tone
+    short_mode: bool,
+    period: u16,
+    timer: u16,
+    shift: u16,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+}
+
+impl Noise {
+    pub(super) fn new() -> Self {
+        Noise {
+            short_mode: false,
+            period: PERIODS[0],
+            timer: 0,
+            shift: 1,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.length.set_halted(data & 0b0010_0000 != 0);
+                self.envelope.write(data);
+            }
+            2 => {
+                self.short_mode = data & 0b1000_0000 != 0;
+                self.period = PERIODS[(data & 0b1111) as usize];
+            }
+            3 => {
+                self.length.load(data);
+                self.envelope.restart();
+            }
+            _ => {}
+        }
+    }
+
+    // clocked every CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active() || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Noise {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.short_mode);
+        state.u16(self.period);
+        state.u16(self.timer);
+        state.u16(self.shift);
+        self.envelope.save_state(state);
+        self.length.save_state(state);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.short_mode = state.bool()?;
+        self.period = state.u16_in(1..=u16::MAX, "noise period")?;
+        self.timer = state.u16()?;
+        self.shift = state.u16()?;
+        self.envelope.load_state(state)?;
+        self.length.load_state(state)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sequence(short_mode: bool) -> Vec<u16> {
+        let mut noise = Noise::new();
+        noise.write(2, if short_mode { 0x80 } else { 0 });
+        let mut states = vec![noise.shift];
+        for _ in 0..1000 {
+            for _ in 0..4 {
+                noise.clock_timer();
+            }
+            states.push(noise.shift);
+        }
+        states
+    }
+
+    #[test]
+    fn short_mode_repeats_after_93_steps() {
+        let states = sequence(true);
+        assert_eq!(states[93], states[0]);
+        assert_ne!(sequence(false)[93], states[0]);
+    }
+
+    #[test]
+    fn silent_while_bit_0_is_set() {
+        let mut noise = Noise::new();
+        noise.length.set_enabled(true);
+        noise.write(0, 0b0001_1111);
+        noise.write(3, 0b0000_1000);
+        assert_eq!(noise.shift & 1, 1);
+        assert_eq!(noise.output(), 0);
+    }
+}
@@ -0,0 +1,229 @@
+use super::units::{Envelope, LengthCounter};
+use crate::state::{StateError, StateReader, StateWriter};
+
+const DUTY_CYCLES: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+// square wave at 0x4000-0x4003 (pulse 1) or 0x4004-0x4007 (pulse 2)
+pub(super) struct Pulse {
+    // pulse 1 negates with ones' complement, so it sweeps one lower
+    ones_complement: bool,
+    duty: u8,
+    step: u8,
+    period: u16,
+    timer: u16,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+    pub(super) envelope: Envelope,
+    pub(super) length: LengthCounter,
+}
+
+impl Pulse {
+    pub(super) fn new(ones_complement: bool) -> Self {
+        Pulse {
+            ones_complement,
+            duty: 0,
+            step: 0,
+            period: 0,
+            timer: 0,
+            sweep_enabled: false,
+            sweep_period: 0,
+            sweep_negate: false,
+            sweep_shift: 0,
+            sweep_divider: 0,
+            sweep_reload: false,
+            envelope: Envelope::default(),
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.duty = data >> 6;
+                self.length.set_halted(data & 0b0010_0000 != 0);
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep_enabled = data & 0b1000_0000 != 0;
+                self.sweep_period = (data >> 4) & 0b111;
+                self.sweep_negate = data & 0b1000 != 0;
+                self.sweep_shift = data & 0b111;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x0700) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.step = 0;
+                self.envelope.restart();
+            }
+        }
+    }
+
+    // clocked every other CPU cycle
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            let change = change + self.ones_complement as u16;
+            self.period.saturating_sub(change)
+        } else {
+            self.period + change
+        }
+    }
+
+    // the sweep silences the channel even while disabled
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    // half-frame clock
+    pub(super) fn clock_sweep(&mut self) {
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if !self.length.active()
+            || self.muted()
+            || DUTY_CYCLES[self.duty as usize][self.step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.output()
+        }
+    }
+}
+
+impl Pulse {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.duty);
+        state.u8(self.step);
+        state.u16(self.period);
+        state.u16(self.timer);
+        state.bool(self.sweep_enabled);
+        state.u8(self.sweep_period);
+        state.bool(self.sweep_negate);
+        state.u8(self.sweep_shift);
+        state.u8(self.sweep_divider);
+        state.bool(self.sweep_reload);
+        self.envelope.save_state(state);
+        self.length.save_state(state);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.duty = state.u8_in(0..=3, "pulse duty")?;
+        self.step = state.u8_in(0..=7, "pulse step")?;
+        self.period = state.u16_in(0..=0x7FF, "pulse period")?;
+        self.timer = state.u16()?;
+        self.sweep_enabled = state.bool()?;
+        self.sweep_period = state.u8()?;
+        self.sweep_negate = state.bool()?;
+        self.sweep_shift = state.u8_in(0..=7, "pulse sweep shift")?;
+        self.sweep_divider = state.u8()?;
+        self.sweep_reload = state.bool()?;
+        self.envelope.load_state(state)?;
+        self.length.load_state(state)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn playing(ones_complement: bool) -> Pulse {
+        let mut pulse = Pulse::new(ones_complement);
+        pulse.length.set_enabled(true);
+        // 50% duty, constant volume 9
+        pulse.write(0, 0b1011_1001);
+        pulse.write(2, 0x00);
+        pulse.write(3, 0b0000_1001);
+        pulse
+    }
+
+    #[test]
+    fn steps_through_duty_cycle() {
+        let mut pulse = playing(false);
+        let mut wave = Vec::new();
+        for _ in 0..8 {
+            wave.push(pulse.output());
+            for _ in 0..=0x100 {
+                pulse.clock_timer();
+            }
+        }
+        assert_eq!(wave, vec![0, 9, 9, 9, 9, 0, 0, 0]);
+    }
+
+    #[test]
+    fn sweeps_period() {
+        let mut pulse = playing(false);
+        // enabled, period 0, shift 1: adds half the period each half frame
+        pulse.write(1, 0b1000_0001);
+        pulse.clock_sweep();
+        assert_eq!(pulse.period, 0x180);
+        pulse.clock_sweep();
+        assert_eq!(pulse.period, 0x240);
+    }
+
+    #[test]
+    fn pulse_1_negates_one_further() {
+        let mut pulse_1 = playing(true);
+        let mut pulse_2 = playing(false);
+        for pulse in [&mut pulse_1, &mut pulse_2] {
+            pulse.write(1, 0b1000_1001);
+            pulse.clock_sweep();
+        }
+        assert_eq!((pulse_1.period, pulse_2.period), (0x7F, 0x80));
+    }
+
+    #[test]
+    fn mutes_on_sweep_overflow_and_low_period() {
+        let mut pulse = playing(false);
+        pulse.write(3, 0b0000_1111);
+        // a 0x7FF period with shift 1 would overflow
+        pulse.write(2, 0xFF);
+        pulse.write(1, 0b0000_0001);
+        assert!(pulse.muted());
+        pulse.write(1, 0);
+        pulse.write(3, 0);
+        pulse.write(2, 7);
+        assert!(pulse.muted());
+    }
+
+    #[test]
+    fn rejects_snapshot_with_bad_duty() {
+        let mut state = StateWriter::new(0);
+        state.u8(4);
+        let data = state.finish();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(
+            Pulse::new(true).load_state(&mut reader),
+            Err(StateError::InvalidValue("pulse duty"))
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+// takes the mixer's output once per CPU cycle and averages every cycle
+// that falls within an output sample, a box filter that keeps the pulse
+// channels' ultrasonic harmonics from aliasing down into the audible range.
+// Works on left/right pairs, mono output only uses the first
+pub(super) struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    // gains output_rate per input sample, one output per input_rate
+    clock: u32,
+    sum: [f32; 2],
+    count: u32,
+}
+
+impl Resampler {
+    pub(super) fn new(input_rate: u32, output_rate: u32) -> Self {
+        Resampler {
+            input_rate,
+            output_rate,
+            clock: 0,
+            sum: [0.0; 2],
+            count: 0,
+        }
+    }
+
+    pub(super) fn output_rate(&self) -> u32 {
+        self.output_rate
+    }
+
+    pub(super) fn set_output_rate(&mut self, output_rate: u32) {
+        self.output_rate = output_rate;
+        self.clock = 0;
+    }
+
+    pub(super) fn push(&mut self, sample: [f32; 2]) -> Option<[f32; 2]> {
+        self.sum[0] += sample[0];
+        self.sum[1] += sample[1];
+        self.count += 1;
+        self.clock += self.output_rate;
+        if self.clock < self.input_rate {
+            return None;
+        }
+        self.clock -= self.input_rate;
+        let count = self.count as f32;
+        let average = [self.sum[0] / count, self.sum[1] / count];
+        self.sum = [0.0; 2];
+        self.count = 0;
+        Some(average)
+    }
+}
+
+impl Resampler {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.u32(self.clock);
+        state.u32(self.sum[0].to_bits());
+        state.u32(self.sum[1].to_bits());
+        state.u32(self.count);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        // the clock stays below input_rate between samples
+        self.clock = state.u32_in(0..=self.input_rate - 1, "resampler clock")?;
+        self.sum = [f32::from_bits(state.u32()?), f32::from_bits(state.u32()?)];
+        self.count = state.u32_in(0..=self.input_rate, "resampler count")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn averages_each_window() {
+        let mut resampler = Resampler::new(4, 1);
+        let out: Vec<_> = [1.0, 0.0, 1.0, 0.0, 0.5, 0.5, 0.5, 0.5]
+            .into_iter()
+            .filter_map(|sample| resampler.push([sample, 1.0 - sample]))
+            .collect();
+        assert_eq!(out, vec![[0.5, 0.5], [0.5, 0.5]]);
+    }
+
+    #[test]
+    fn keeps_fractional_rate_over_time() {
+        let mut resampler = Resampler::new(1_789_773, 48_000);
+        let count = (0..1_789_773)
+            .filter_map(|_| resampler.push([0.0; 2]))
+            .count();
+        assert_eq!(count, 48_000);
+    }
+
+    #[test]
+    fn rejects_snapshot_with_clock_past_input_rate() {
+        let mut state = StateWriter::new(0);
+        state.u32(u32::MAX);
+        let data = state.finish();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(
+            Resampler::new(1_789_773, 48_000).load_state(&mut reader),
+            Err(StateError::InvalidValue("resampler clock"))
+        );
+    }
+}
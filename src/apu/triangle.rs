@@ -0,0 +1,148 @@
+use super::units::LengthCounter;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const SEQUENCE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12,
+    13, 14, 15,
+];
+
+// triangle wave at 0x4008-0x400B, with no volume control but a second,
+// finer-grained linear counter
+pub(super) struct Triangle {
+    period: u16,
+    timer: u16,
+    step: u8,
+    // also the length counter halt flag
+    control: bool,
+    linear_reload_value: u8,
+    linear_counter: u8,
+    linear_reload: bool,
+    pub(super) length: LengthCounter,
+}
+
+impl Triangle {
+    pub(super) fn new() -> Self {
+        Triangle {
+            period: 0,
+            timer: 0,
+            step: 0,
+            control: false,
+            linear_reload_value: 0,
+            linear_counter: 0,
+            linear_reload: false,
+            length: LengthCounter::default(),
+        }
+    }
+
+    pub(super) fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => {
+                self.control = data & 0b1000_0000 != 0;
+                self.length.set_halted(self.control);
+                self.linear_reload_value = data & 0b0111_1111;
+            }
+            2 => self.period = (self.period & 0x0700) | data as u16,
+            3 => {
+                self.period = (self.period & 0x00FF) | ((data as u16 & 0b111) << 8);
+                self.length.load(data);
+                self.linear_reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    // clocked every CPU cycle, the sequencer only moves while both
+    // counters are running
+    pub(super) fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length.active() && self.linear_counter > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    // quarter-frame clock
+    pub(super) fn clock_linear(&mut self) {
+        if self.linear_reload {
+            self.linear_counter = self.linear_reload_value;
+        } else if self.linear_counter > 0 {
+            self.linear_counter -= 1;
+        }
+        if !self.control {
+            self.linear_reload = false;
+        }
+    }
+
+    // a stopped triangle holds its level rather than dropping to 0
+    pub(super) fn output(&self) -> u8 {
+        SEQUENCE[self.step as usize]
+    }
+}
+
+impl Triangle {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.u16(self.period);
+        state.u16(self.timer);
+        state.u8(self.step);
+        state.bool(self.control);
+        state.u8(self.linear_reload_value);
+        state.u8(self.linear_counter);
+        state.bool(self.linear_reload);
+        self.length.save_state(state);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.period = state.u16_in(0..=0x7FF, "triangle period")?;
+        self.timer = state.u16()?;
+        self.step = state.u8_in(0..=31, "triangle step")?;
+        self.control = state.bool()?;
+        self.linear_reload_value = state.u8()?;
+        self.linear_counter = state.u8()?;
+        self.linear_reload = state.bool()?;
+        self.length.load_state(state)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn runs_only_while_linear_counter_is_loaded() {
+        let mut triangle = Triangle::new();
+        triangle.length.set_enabled(true);
+        // linear counter 2, period 0
+        triangle.write(0, 0b0000_0010);
+        triangle.write(3, 0b0000_1000);
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 15);
+
+        triangle.clock_linear();
+        triangle.clock_timer();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 13);
+
+        triangle.clock_linear();
+        triangle.clock_linear();
+        triangle.clock_timer();
+        assert_eq!(triangle.output(), 13);
+    }
+
+    #[test]
+    fn rejects_snapshot_with_bad_step() {
+        let mut state = StateWriter::new(0);
+        state.u16(0);
+        state.u16(0);
+        state.u8(32);
+        let data = state.finish();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(
+            Triangle::new().load_state(&mut reader),
+            Err(StateError::InvalidValue("triangle step"))
+        );
+    }
+}
@@ -0,0 +1,188 @@
+use crate::state::{StateError, StateReader, StateWriter};
+
+// shared by pulse, triangle and noise: how many half frames a note lasts
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14, 12, 16, 24, 18, 48, 20, 96, 22,
+    192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+#[derive(Default)]
+pub(super) struct LengthCounter {
+    enabled: bool,
+    halted: bool,
+    counter: u8,
+}
+
+impl LengthCounter {
+    // disabling a channel through 0x4015 silences it at once
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if !enabled {
+            self.counter = 0;
+        }
+    }
+
+    pub(super) fn set_halted(&mut self, halted: bool) {
+        self.halted = halted;
+    }
+
+    // takes the top five bits of the channel's last register
+    pub(super) fn load(&mut self, data: u8) {
+        if self.enabled {
+            self.counter = LENGTH_TABLE[(data >> 3) as usize];
+        }
+    }
+
+    // half-frame clock
+    pub(super) fn clock(&mut self) {
+        if !self.halted && self.counter > 0 {
+            self.counter -= 1;
+        }
+    }
+
+    pub(super) fn active(&self) -> bool {
+        self.counter > 0
+    }
+}
+
+// volume from a decaying level or a constant, set by the channel's first
+// register as --LC VVVV
+#[derive(Default)]
+pub(super) struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    // the constant volume, or the decay period
+    volume: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    pub(super) fn write(&mut self, data: u8) {
+        self.looping = data & 0b0010_0000 != 0;
+        self.constant = data & 0b0001_0000 != 0;
+        self.volume = data & 0b1111;
+    }
+
+    pub(super) fn restart(&mut self) {
+        self.start = true;
+    }
+
+    // quarter-frame clock
+    pub(super) fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.volume;
+        } else if self.divider == 0 {
+            self.divider = self.volume;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    pub(super) fn output(&self) -> u8 {
+        if self.constant {
+            self.volume
+        } else {
+            self.decay
+        }
+    }
+}
+
+impl LengthCounter {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.enabled);
+        state.bool(self.halted);
+        state.u8(self.counter);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.enabled = state.bool()?;
+        self.halted = state.bool()?;
+        self.counter = state.u8()?;
+        Ok(())
+    }
+}
+
+impl Envelope {
+    pub(super) fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.start);
+        state.bool(self.looping);
+        state.bool(self.constant);
+        state.u8(self.volume);
+        state.u8(self.divider);
+        state.u8(self.decay);
+    }
+
+    pub(super) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.start = state.bool()?;
+        self.looping = state.bool()?;
+        self.constant = state.bool()?;
+        self.volume = state.u8()?;
+        self.divider = state.u8()?;
+        self.decay = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn length_counter_loads_only_when_enabled() {
+        let mut length = LengthCounter::default();
+        length.load(0b0000_1000);
+        assert!(!length.active());
+        length.set_enabled(true);
+        // index 1 is 254 half frames
+        length.load(0b0000_1000);
+        for _ in 0..253 {
+            length.clock();
+        }
+        assert!(length.active());
+        length.clock();
+        assert!(!length.active());
+    }
+
+    #[test]
+    fn halted_length_counter_holds() {
+        let mut length = LengthCounter::default();
+        length.set_enabled(true);
+        length.load(0b0001_1000);
+        length.set_halted(true);
+        for _ in 0..10 {
+            length.clock();
+        }
+        assert!(length.active());
+        length.set_enabled(false);
+        assert!(!length.active());
+    }
+
+    #[test]
+    fn envelope_decays_and_loops() {
+        let mut envelope = Envelope::default();
+        // loop, decay period 1: one step every two clocks
+        envelope.write(0b0010_0001);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+        for _ in 0..30 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.output(), 0);
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.output(), 15);
+
+        envelope.write(0b0001_0111);
+        assert_eq!(envelope.output(), 7);
+    }
+}
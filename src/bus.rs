@@ -0,0 +1,621 @@
+const RAM: u16 = 0x0000;
+const RAM_MIRRORS_END: u16 = 0x1FFF;
+const PPU_REGISTERS: u16 = 0x2000;
+const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const APU_REGISTERS: u16 = 0x4000;
+const APU_REGISTERS_END: u16 = 0x4013;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD_1: u16 = 0x4016;
+// reads go to the second port, writes to the APU frame counter
+const JOYPAD_2: u16 = 0x4017;
+const APU_FRAME_COUNTER: u16 = 0x4017;
+const PRG_RAM: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
+const PRG_ROM: u16 = 0x8000;
+
+use std::any::Any;
+use std::fmt;
+use std::io;
+
+use crate::apu::Apu;
+use crate::bus_trace::{BusEvent, BusTraceRecorder, TraceSource};
+use crate::cartridge::Mirroring;
+use crate::chr::{self, ChrError};
+use crate::cpu::BusAccessKind;
+use crate::input::InputDevice;
+use crate::joypad::Joypad;
+use crate::mapper::{Chr, Mapper, Nrom};
+use crate::ppu::{Frame, Ppu};
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub trait Mem {
+    fn mem_read(&mut self, addr: u16) -> u8;
+
+    fn mem_write(&mut self, addr: u16, data: u8);
+}
+
+// what answers at a CPU address, for labelling addresses in debuggers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Ram,
+    PpuRegister,
+    // APU registers, OAM DMA and the controller ports
+    Io,
+    PrgRam,
+    PrgRom { bank: usize },
+    // nothing drives the bus
+    OpenBus,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Region::Ram => write!(f, "RAM"),
+            Region::PpuRegister => write!(f, "PPU register"),
+            Region::Io => write!(f, "APU/IO"),
+            Region::PrgRam => write!(f, "PRG RAM"),
+            Region::PrgRom { bank } => write!(f, "PRG bank {bank}"),
+            Region::OpenBus => write!(f, "open bus"),
+        }
+    }
+}
+
+// start..=end all answered by region
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MapRange {
+    pub start: u16,
+    pub end: u16,
+    pub region: Region,
+}
+
+// CPU memory map:
+//   [0x0000 .. 0x1FFF] 2 KiB of work RAM, mirrored every 0x800 bytes
+//   [0x2000 .. 0x3FFF] PPU registers, mirrored every 8 bytes
+//   [0x4000 .. 0x4017] APU registers, OAM DMA and the controller ports
+//   [0x6000 .. 0x7FFF] PRG RAM on the cartridge
+//   [0x8000 .. 0xFFFF] PRG ROM, banked by the mapper
+pub struct Bus {
+    cpu_ram: [u8; 0x800],
+    prg_ram: [u8; 0x2000],
+    // the cartridge keeps PRG RAM alive with a battery
+    battery: bool,
+    ppu: Ppu,
+    apu: Apu,
+    // standard controllers unless something else is plugged in
+    ports: [Box<dyn InputDevice>; 2],
+    mapper: Box<dyn Mapper>,
+    dma_pending: bool,
+    bus_trace: Option<BusTraceRecorder>,
+    // CPU cycles ticked so far, plus the accesses made since, which stamps
+    // each traced access with roughly the cycle it happened on
+    cycles: u64,
+    accesses: u64,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Bus {
+            cpu_ram: [0; 0x800],
+            prg_ram: [0; 0x2000],
+            battery: false,
+            ppu: Ppu::new(),
+            apu: Apu::new(),
+            ports: [Box::new(Joypad::new()), Box::new(Joypad::new())],
+            dma_pending: false,
+            bus_trace: None,
+            cycles: 0,
+            accesses: 0,
+            mapper: Box::new(Nrom::new(
+                vec![0; 0x8000],
+                Chr::new(Vec::new(), 0),
+                Mirroring::Horizontal,
+            )),
+        }
+    }
+
+    pub(crate) fn set_mapper(&mut self, mapper: Box<dyn Mapper>) {
+        self.mapper = mapper;
+    }
+
+    pub(crate) fn set_battery(&mut self, battery: bool) {
+        self.battery = battery;
+    }
+
+    // battery-backed PRG RAM, the game's saves, for writing to a .sav file
+    pub fn sram(&self) -> Option<&[u8]> {
+        self.battery.then_some(&self.prg_ram[..])
+    }
+
+    // fills PRG RAM from a .sav file, which may be shorter than 8 KiB
+    pub fn load_sram(&mut self, data: &[u8]) {
+        let len = data.len().min(self.prg_ram.len());
+        self.prg_ram[..len].copy_from_slice(&data[..len]);
+    }
+
+    pub fn mapper(&self) -> &dyn Mapper {
+        self.mapper.as_ref()
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    // port 0 or 1, replacing whatever was there
+    pub fn plug(&mut self, port: usize, device: impl InputDevice) {
+        self.ports[port] = Box::new(device);
+    }
+
+    // the device in port if it is a T
+    pub fn device_mut<T: InputDevice>(&mut self, port: usize) -> Option<&mut T> {
+        (self.ports[port].as_mut() as &mut dyn Any).downcast_mut()
+    }
+
+    // None when something other than a standard controller is plugged in
+    pub fn joypad_mut(&mut self, port: usize) -> Option<&mut Joypad> {
+        self.device_mut(port)
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    // the cartridge's whole CHR as a greyscale PNG sheet
+    pub fn export_chr_png(&self) -> Vec<u8> {
+        chr::to_png(self.mapper.chr().data())
+    }
+
+    // replaces the CHR with an edited sheet from export_chr_png
+    pub fn import_chr_png(&mut self, png: &[u8]) -> Result<(), ChrError> {
+        chr::from_png(png, self.mapper.chr_mut().data_mut())
+    }
+
+    pub fn render_frame(&mut self) -> &Frame {
+        self.ppu.render(self.mapper.as_ref());
+        self.ppu.frame()
+    }
+
+    pub fn start_bus_trace(&mut self, recorder: BusTraceRecorder) {
+        self.bus_trace = Some(recorder);
+        self.apu.set_record_fetches(true);
+    }
+
+    // flushes the trace, with the first error hit while recording
+    pub fn stop_bus_trace(&mut self) -> io::Result<()> {
+        self.apu.set_record_fetches(false);
+        match self.bus_trace.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    fn record(&mut self, addr: u16, value: u8, kind: BusAccessKind, source: TraceSource) {
+        let cycle = self.cycles + self.accesses;
+        if source != TraceSource::Ppu {
+            self.accesses += 1;
+        }
+        if let Some(recorder) = &mut self.bus_trace {
+            recorder.record(BusEvent {
+                cycle,
+                addr,
+                value,
+                kind,
+                source,
+            });
+        }
+    }
+
+    // PPUDATA reaches the PPU's own bus at v, traced before the access
+    // moves v on
+    fn trace_ppu_data(&mut self, addr: u16, write: Option<u8>) {
+        if self.bus_trace.is_none()
+            || !(PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END).contains(&addr)
+            || addr & 7 != 7
+        {
+            return;
+        }
+        let vram_addr = self.ppu.vram_addr() & 0x3FFF;
+        let (value, kind) = match write {
+            Some(data) => (data, BusAccessKind::Write),
+            None => (
+                self.ppu.read_memory(vram_addr, self.mapper.as_ref()),
+                BusAccessKind::Read,
+            ),
+        };
+        self.record(vram_addr, value, kind, TraceSource::Ppu);
+    }
+
+    // copies a page of CPU memory into OAM, as a write to 0x4014 does
+    fn oam_dma(&mut self, page: u8) {
+        let base = (page as u16) << 8;
+        for offset in 0..=0xFF {
+            let data = self.read(base + offset);
+            self.record(base + offset, data, BusAccessKind::Read, TraceSource::Dma);
+            self.record(0x2004, data, BusAccessKind::Write, TraceSource::Dma);
+            self.ppu.write_oam(data);
+        }
+        self.dma_pending = true;
+    }
+
+    // the CPU stalls for 513 cycles during an OAM DMA, plus one to align
+    // with an even cycle, and for each byte the DMC fetched
+    pub(crate) fn take_dma_cycles(&mut self, cpu_cycles: u64) -> u16 {
+        let oam = if std::mem::take(&mut self.dma_pending) {
+            513 + (cpu_cycles % 2) as u16
+        } else {
+            0
+        };
+        oam + self.apu.take_dmc_stall()
+    }
+
+    pub(crate) fn tick(&mut self, cycles: u16) {
+        self.mapper.clock_cpu(cycles);
+        self.apu.tick(cycles, self.mapper.as_ref());
+        for (cycle, addr, byte) in self.apu.take_dmc_fetches() {
+            self.accesses = cycle as u64;
+            self.record(addr, byte, BusAccessKind::Read, TraceSource::Dma);
+        }
+        self.cycles += cycles as u64;
+        self.accesses = 0;
+        self.ppu.tick(cycles as u32 * 3, self.mapper.as_mut());
+    }
+
+    pub(crate) fn take_nmi(&mut self) -> bool {
+        self.ppu.take_nmi()
+    }
+
+    pub(crate) fn take_frame_complete(&mut self) -> bool {
+        self.ppu.take_frame_complete()
+    }
+
+    pub(crate) fn irq(&self) -> bool {
+        self.mapper.irq() || self.apu.irq()
+    }
+
+    // asks the mapper, so it follows bank switches as they happen
+    pub fn region(&self, addr: u16) -> Region {
+        match addr {
+            RAM..=RAM_MIRRORS_END => Region::Ram,
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => Region::PpuRegister,
+            APU_REGISTERS..=APU_FRAME_COUNTER => Region::Io,
+            PRG_RAM..=PRG_RAM_END => Region::PrgRam,
+            PRG_ROM..=0xFFFF => Region::PrgRom {
+                bank: self.mapper.prg_bank(addr),
+            },
+            _ => Region::OpenBus,
+        }
+    }
+
+    // the whole address space as it is mapped right now
+    pub fn memory_map(&self) -> Vec<MapRange> {
+        let mut ranges: Vec<MapRange> = Vec::new();
+        for addr in 0..=0xFFFF {
+            let region = self.region(addr);
+            match ranges.last_mut() {
+                Some(range) if range.region == region => range.end = addr,
+                _ => ranges.push(MapRange {
+                    start: addr,
+                    end: addr,
+                    region,
+                }),
+            }
+        }
+        ranges
+    }
+
+    // reads without side effects, for debuggers and tests
+    pub fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_ram[(addr & 0x07FF) as usize],
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.peek_register(addr, self.mapper.as_ref())
+            }
+            APU_STATUS => self.apu.peek_status(),
+            JOYPAD_1 => self.ports[0].peek(&self.ppu),
+            JOYPAD_2 => self.ports[1].peek(&self.ppu),
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize],
+            PRG_ROM..=0xFFFF => self.mapper.read_prg(addr),
+            _ => 0,
+        }
+    }
+
+    // writes past the ROM write protection, for debuggers and tests
+    pub fn poke(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM..=0xFFFF => self.mapper.poke_prg(addr, data),
+            _ => self.write(addr, data),
+        }
+    }
+}
+
+impl Bus {
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(&self.cpu_ram);
+        state.bytes(&self.prg_ram);
+        state.bool(self.dma_pending);
+        state.u64(self.cycles);
+        self.ppu.save_state(state);
+        self.apu.save_state(state);
+        self.ports.iter().for_each(|port| port.save_state(state));
+        self.mapper.save_state(state);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        state.bytes_into(&mut self.cpu_ram)?;
+        state.bytes_into(&mut self.prg_ram)?;
+        self.dma_pending = state.bool()?;
+        self.cycles = state.u64()?;
+        self.ppu.load_state(state)?;
+        self.apu.load_state(state)?;
+        for port in &mut self.ports {
+            port.load_state(state)?;
+        }
+        self.mapper.load_state(state)
+    }
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Bus {
+    // the access itself, with the side effects of the register it lands
+    // on but nothing recorded
+
+    fn read(&mut self, addr: u16) -> u8 {
+        match addr {
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.read_register(addr, self.mapper.as_ref())
+            }
+            APU_STATUS => self.apu.read_status(),
+            JOYPAD_1 => self.ports[0].read(&self.ppu),
+            JOYPAD_2 => self.ports[1].read(&self.ppu),
+            _ => self.peek(addr),
+        }
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            RAM..=RAM_MIRRORS_END => self.cpu_ram[(addr & 0x07FF) as usize] = data,
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
+                self.ppu.write_register(addr, data, self.mapper.as_mut())
+            }
+            APU_REGISTERS..=APU_REGISTERS_END | APU_STATUS | APU_FRAME_COUNTER => {
+                self.apu.write_register(addr, data)
+            }
+            OAM_DMA => self.oam_dma(data),
+            // one strobe line runs to both ports
+            JOYPAD_1 => self.ports.iter_mut().for_each(|port| port.write(data)),
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize] = data,
+            PRG_ROM..=0xFFFF => self.mapper.write_prg(addr, data),
+            // writes to unmapped registers go nowhere
+            _ => {}
+        }
+    }
+}
+
+impl Mem for Bus {
+    fn mem_read(&mut self, addr: u16) -> u8 {
+        self.trace_ppu_data(addr, None);
+        let value = self.read(addr);
+        self.record(addr, value, BusAccessKind::Read, TraceSource::Cpu);
+        value
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.trace_ppu_data(addr, Some(data));
+        self.record(addr, data, BusAccessKind::Write, TraceSource::Cpu);
+        self.write(addr, data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_regions_and_follows_bank_switches() {
+        use crate::mapper::Uxrom;
+
+        let mut bus = Bus::new();
+        bus.set_mapper(Box::new(Uxrom::new(
+            vec![0; 0x4000 * 4],
+            Chr::new(Vec::new(), 0x2000),
+            Mirroring::Vertical,
+        )));
+        let range = |start, end, region| MapRange { start, end, region };
+        assert_eq!(
+            bus.memory_map(),
+            vec![
+                range(0x0000, 0x1FFF, Region::Ram),
+                range(0x2000, 0x3FFF, Region::PpuRegister),
+                range(0x4000, 0x4017, Region::Io),
+                range(0x4018, 0x5FFF, Region::OpenBus),
+                range(0x6000, 0x7FFF, Region::PrgRam),
+                range(0x8000, 0xBFFF, Region::PrgRom { bank: 0 }),
+                range(0xC000, 0xFFFF, Region::PrgRom { bank: 3 }),
+            ]
+        );
+        bus.mem_write(0x8000, 2);
+        assert_eq!(bus.region(0x9234), Region::PrgRom { bank: 2 });
+        assert_eq!(bus.region(0x9234).to_string(), "PRG bank 2");
+        assert_eq!(bus.region(0x5000).to_string(), "open bus");
+    }
+
+    #[test]
+    fn mirrors_work_ram() {
+        let mut bus = Bus::new();
+        bus.mem_write(0x0012, 0x34);
+        assert_eq!(bus.mem_read(0x0812), 0x34);
+        assert_eq!(bus.mem_read(0x1812), 0x34);
+        bus.mem_write(0x1FFF, 0x56);
+        assert_eq!(bus.mem_read(0x07FF), 0x56);
+    }
+
+    #[test]
+    fn ignores_writes_to_prg_rom() {
+        let mut bus = Bus::new();
+        bus.poke(0x8000, 0xa9);
+        bus.mem_write(0x8000, 0xff);
+        assert_eq!(bus.mem_read(0x8000), 0xa9);
+        bus.poke(0x8000, 0xff);
+        assert_eq!(bus.mem_read(0x8000), 0xff);
+    }
+
+    #[test]
+    fn maps_prg_ram() {
+        let mut bus = Bus::new();
+        bus.mem_write(0x6000, 0x12);
+        bus.mem_write(0x7FFF, 0x34);
+        assert_eq!(bus.mem_read(0x6000), 0x12);
+        assert_eq!(bus.mem_read(0x7FFF), 0x34);
+        assert_eq!(bus.mem_read(0x4020), 0x00);
+    }
+
+    #[test]
+    fn exposes_sram_only_with_battery() {
+        let mut bus = Bus::new();
+        bus.load_sram(&[1, 2, 3]);
+        assert_eq!(bus.sram(), None);
+        bus.set_battery(true);
+        assert_eq!(bus.sram().unwrap()[..4], [1, 2, 3, 0]);
+        assert_eq!(bus.sram().unwrap().len(), 0x2000);
+        assert_eq!(bus.mem_read(0x6001), 2);
+    }
+
+    #[test]
+    fn maps_mirrored_ppu_registers() {
+        let mut bus = Bus::new();
+        // PPUADDR through a mirror, PPUDATA through another
+        bus.mem_write(0x3FFE, 0x21);
+        bus.mem_write(0x2006, 0x08);
+        bus.mem_write(0x200F, 0x5A);
+        assert_eq!(bus.ppu().read_memory(0x2108, bus.mapper()), 0x5A);
+    }
+
+    #[test]
+    fn copies_page_into_oam() {
+        let mut bus = Bus::new();
+        for i in 0..=0xFF {
+            bus.mem_write(0x0200 + i, i as u8);
+        }
+        // DMA starts at OAMADDR and wraps
+        bus.mem_write(0x2003, 0x10);
+        bus.mem_write(0x4014, 0x02);
+        assert_eq!(bus.ppu().oam()[0x10], 0x00);
+        assert_eq!(bus.ppu().oam()[0x0F], 0xFF);
+        assert_eq!(bus.take_dma_cycles(7), 514);
+        assert_eq!(bus.take_dma_cycles(7), 0);
+    }
+
+    #[test]
+    fn frame_irq_reaches_irq_line() {
+        let mut bus = Bus::new();
+        bus.mem_write(0x4015, 0b0001);
+        bus.mem_write(0x4003, 0b0000_1000);
+        bus.tick(29829);
+        assert!(bus.irq());
+        assert_eq!(bus.peek(0x4015), 0b0100_0001);
+        assert_eq!(bus.mem_read(0x4015), 0b0100_0001);
+        assert!(!bus.irq());
+    }
+
+    #[test]
+    fn reads_both_joypads() {
+        use crate::joypad::Button;
+        let mut bus = Bus::new();
+        bus.joypad_mut(0).unwrap().set_button(Button::B, true);
+        bus.joypad_mut(1).unwrap().set_button(Button::A, true);
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        assert_eq!(bus.peek(0x4016), 0x40);
+        assert_eq!(bus.mem_read(0x4016), 0x40);
+        assert_eq!(bus.mem_read(0x4016), 0x41);
+        assert_eq!(bus.mem_read(0x4017), 0x41);
+        assert_eq!(bus.mem_read(0x4017), 0x40);
+    }
+
+    #[test]
+    fn plugs_in_other_devices() {
+        use crate::four_score::FourScore;
+        use crate::zapper::Zapper;
+
+        let mut bus = Bus::new();
+        bus.plug(1, Zapper::new());
+        assert!(bus.joypad_mut(1).is_none());
+        bus.device_mut::<Zapper>(1).unwrap().pull_trigger();
+        assert_eq!(bus.mem_read(0x4017), 0b0001_1000);
+        bus.plug(1, Joypad::new());
+        assert_eq!(bus.mem_read(0x4017), 0x40);
+
+        bus.plug(0, FourScore::new(0));
+        bus.mem_write(0x4016, 1);
+        bus.mem_write(0x4016, 0);
+        let bits: Vec<_> = (0..24).map(|_| bus.mem_read(0x4016) & 1).collect();
+        assert_eq!(bits.iter().position(|&bit| bit == 1), Some(20));
+    }
+
+    #[test]
+    fn traces_cpu_dma_and_ppu_accesses() {
+        use crate::bus_trace::read_events;
+
+        let mut bus = Bus::new();
+        let path = std::env::temp_dir().join(format!("nes-bus-trace-{}", std::process::id()));
+        bus.start_bus_trace(BusTraceRecorder::create(&path).unwrap());
+        bus.mem_write(0x0010, 0x42);
+        bus.tick(10);
+        bus.mem_write(0x2006, 0x21);
+        bus.mem_write(0x2006, 0x00);
+        bus.mem_write(0x2007, 0x77);
+        bus.mem_write(0x4014, 0x00);
+        // debugger pokes stay out of the trace
+        bus.poke(0x0011, 0);
+        bus.stop_bus_trace().unwrap();
+        bus.mem_write(0x0012, 0);
+
+        let events = read_events(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        let summary: Vec<_> = events
+            .iter()
+            .take(5)
+            .map(|e| (e.cycle, e.addr, e.value, e.kind, e.source))
+            .collect();
+        use BusAccessKind::Write;
+        assert_eq!(
+            summary,
+            vec![
+                (0, 0x0010, 0x42, Write, TraceSource::Cpu),
+                (10, 0x2006, 0x21, Write, TraceSource::Cpu),
+                (11, 0x2006, 0x00, Write, TraceSource::Cpu),
+                (12, 0x2100, 0x77, Write, TraceSource::Ppu),
+                (12, 0x2007, 0x77, Write, TraceSource::Cpu),
+            ]
+        );
+        // the DMA write to 0x4014, then a read and an OAM write per byte
+        assert_eq!(events.len(), 6 + 512);
+        assert_eq!(events[6].source, TraceSource::Dma);
+        assert_eq!(events[6].addr, 0x0000);
+        assert_eq!(events[7].addr, 0x2004);
+    }
+
+    #[test]
+    fn imports_edited_chr_sheet() {
+        let mut bus = Bus::new();
+        let mut image = crate::png::decode(&bus.export_chr_png()).unwrap();
+        // paint the top left pixel of tile 0 white
+        image.rgb[..3].fill(0xFF);
+        bus.import_chr_png(&crate::png::encode(&image)).unwrap();
+        assert_eq!(bus.mapper().read_chr(0x0000), 0b1000_0000);
+        assert_eq!(bus.mapper().read_chr(0x0008), 0b1000_0000);
+    }
+}
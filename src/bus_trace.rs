@@ -0,0 +1,216 @@
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::cpu::BusAccessKind;
+
+const MAGIC: &[u8; 8] = b"NESBUS1\0";
+// cycle u64, address u16, value u8, flags u8, all little endian
+const RECORD_LEN: usize = 12;
+const FLAG_WRITE: u8 = 0b001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceSource {
+    Cpu,
+    // OAM DMA and DMC sample fetches
+    Dma,
+    // the PPU's own bus, reached through PPUDATA
+    Ppu,
+}
+
+impl TraceSource {
+    fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(TraceSource::Cpu),
+            1 => Some(TraceSource::Dma),
+            2 => Some(TraceSource::Ppu),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TraceSource::Cpu => "CPU",
+            TraceSource::Dma => "DMA",
+            TraceSource::Ppu => "PPU",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusEvent {
+    pub cycle: u64,
+    pub addr: u16,
+    pub value: u8,
+    pub kind: BusAccessKind,
+    pub source: TraceSource,
+}
+
+impl BusEvent {
+    fn to_bytes(self) -> [u8; RECORD_LEN] {
+        let mut bytes = [0; RECORD_LEN];
+        bytes[..8].copy_from_slice(&self.cycle.to_le_bytes());
+        bytes[8..10].copy_from_slice(&self.addr.to_le_bytes());
+        bytes[10] = self.value;
+        let write = (self.kind == BusAccessKind::Write) as u8;
+        bytes[11] = (self.source as u8) << 1 | write;
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8; RECORD_LEN]) -> Option<Self> {
+        let kind = if bytes[11] & FLAG_WRITE != 0 {
+            BusAccessKind::Write
+        } else {
+            BusAccessKind::Read
+        };
+        Some(BusEvent {
+            cycle: u64::from_le_bytes(bytes[..8].try_into().unwrap()),
+            addr: u16::from_le_bytes([bytes[8], bytes[9]]),
+            value: bytes[10],
+            kind,
+            source: TraceSource::from_bits(bytes[11] >> 1)?,
+        })
+    }
+}
+
+// streams events to a binary file; the first write error is kept and
+// reported when the recording stops
+pub struct BusTraceRecorder {
+    out: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+impl BusTraceRecorder {
+    pub fn new(mut out: Box<dyn Write + Send>) -> Self {
+        let error = out.write_all(MAGIC).err();
+        BusTraceRecorder { out, error }
+    }
+
+    pub fn create(path: &Path) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(Box::new(BufWriter::new(file))))
+    }
+
+    pub(crate) fn record(&mut self, event: BusEvent) {
+        if self.error.is_none() {
+            self.error = self.out.write_all(&event.to_bytes()).err();
+        }
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush(),
+        }
+    }
+}
+
+pub fn read_events(mut input: impl Read) -> io::Result<Vec<BusEvent>> {
+    let bad_data = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+    let mut magic = [0; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(bad_data("not a bus trace"));
+    }
+    let mut data = Vec::new();
+    input.read_to_end(&mut data)?;
+    if data.len() % RECORD_LEN != 0 {
+        return Err(bad_data("truncated bus trace"));
+    }
+    data.chunks_exact(RECORD_LEN)
+        .map(|chunk| {
+            BusEvent::from_bytes(chunk.try_into().unwrap())
+                .ok_or_else(|| bad_data("unknown bus trace source"))
+        })
+        .collect()
+}
+
+// one "cycle,addr,value,kind,source" row per event, addresses and values
+// in hex
+pub fn to_csv(input: impl Read, mut output: impl Write) -> io::Result<()> {
+    writeln!(output, "cycle,addr,value,kind,source")?;
+    for event in read_events(input)? {
+        let kind = match event.kind {
+            BusAccessKind::Read => 'R',
+            BusAccessKind::Write => 'W',
+        };
+        writeln!(
+            output,
+            "{},{:04X},{:02X},{},{}",
+            event.cycle,
+            event.addr,
+            event.value,
+            kind,
+            event.source.name()
+        )?;
+    }
+    Ok(())
+}
+
+pub fn convert_to_csv(trace: &Path, csv: &Path) -> io::Result<()> {
+    let input = BufReader::new(File::open(trace)?);
+    let output = BufWriter::new(File::create(csv)?);
+    to_csv(input, output)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // a Write the test can still read after the recorder is done with it
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn round_trips_events_and_converts_to_csv() {
+        let buffer = SharedBuffer::default();
+        let mut recorder = BusTraceRecorder::new(Box::new(buffer.clone()));
+        let events = [
+            BusEvent {
+                cycle: 7,
+                addr: 0x8000,
+                value: 0xa9,
+                kind: BusAccessKind::Read,
+                source: TraceSource::Cpu,
+            },
+            BusEvent {
+                cycle: 300,
+                addr: 0x2004,
+                value: 0x10,
+                kind: BusAccessKind::Write,
+                source: TraceSource::Dma,
+            },
+        ];
+        events.iter().for_each(|&event| recorder.record(event));
+        recorder.finish().unwrap();
+
+        let data = buffer.0.lock().unwrap().clone();
+        assert_eq!(read_events(&data[..]).unwrap(), events);
+        let mut csv = Vec::new();
+        to_csv(&data[..], &mut csv).unwrap();
+        assert_eq!(
+            String::from_utf8(csv).unwrap(),
+            "cycle,addr,value,kind,source\n7,8000,A9,R,CPU\n300,2004,10,W,DMA\n"
+        );
+    }
+
+    #[test]
+    fn rejects_bad_traces() {
+        assert!(read_events(&b"NOTATRACE"[..]).is_err());
+        let mut data = MAGIC.to_vec();
+        data.extend([0; 5]);
+        assert!(read_events(&data[..]).is_err());
+    }
+}
@@ -0,0 +1,532 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::checksum::crc32;
+
+pub const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
+const HEADER_SIZE: usize = 16;
+const TRAINER_SIZE: usize = 512;
+const PRG_ROM_PAGE_SIZE: usize = 0x4000;
+const CHR_ROM_PAGE_SIZE: usize = 0x2000;
+const PRG_RAM_PAGE_SIZE: usize = 0x2000;
+const PRG_ROM_BANK_MIN: usize = 0x2000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RomError {
+    TooLarge {
+        size: usize,
+        max: usize,
+    },
+    BadHeader,
+    Truncated {
+        expected: usize,
+        actual: usize,
+    },
+    UnsupportedMapper {
+        mapper: u16,
+        name: Option<&'static str>,
+        supported: &'static [u16],
+    },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::TooLarge { size, max } => {
+                write!(
+                    f,
+                    "program is {size} bytes, at most {max} bytes fit in PRG ROM"
+                )
+            }
+            RomError::BadHeader => write!(f, "not an iNES file"),
+            RomError::Truncated { expected, actual } => {
+                write!(f, "ROM should be {expected} bytes but is {actual}")
+            }
+            RomError::UnsupportedMapper {
+                mapper,
+                name,
+                supported,
+            } => {
+                write!(f, "mapper {mapper}")?;
+                if let Some(name) = name {
+                    write!(f, " ({name})")?;
+                }
+                write!(f, " is not supported, supported mappers are")?;
+                for (i, &mapper) in supported.iter().enumerate() {
+                    let separator = if i == 0 { " " } else { ", " };
+                    write!(f, "{separator}{mapper}")?;
+                    if let Some(name) = mapper_name(mapper) {
+                        write!(f, " ({name})")?;
+                    }
+                }
+                // by number, the lower one on a tie
+                let nearest = supported
+                    .iter()
+                    .min_by_key(|&&supported| (supported.abs_diff(*mapper), supported));
+                if let Some(&nearest) = nearest {
+                    write!(f, "; the nearest is {nearest}")?;
+                    if let Some(name) = mapper_name(nearest) {
+                        write!(f, " ({name})")?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Error for RomError {}
+
+const MAPPER_NAMES: [(u16, &str); 14] = [
+    (0, "NROM"),
+    (1, "MMC1"),
+    (2, "UxROM"),
+    (3, "CNROM"),
+    (4, "MMC3"),
+    (5, "MMC5"),
+    (7, "AxROM"),
+    (9, "MMC2"),
+    (10, "MMC4"),
+    (11, "Color Dreams"),
+    (34, "BNROM"),
+    (64, "RAMBO-1"),
+    (66, "GxROM"),
+    (71, "Camerica"),
+];
+
+pub fn mapper_name(mapper: u16) -> Option<&'static str> {
+    MAPPER_NAMES
+        .iter()
+        .find(|&&(number, _)| number == mapper)
+        .map(|&(_, name)| name)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    FourScreen,
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeaderFormat {
+    INes,
+    Nes2,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Timing {
+    Ntsc,
+    Pal,
+    MultiRegion,
+    Dendy,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RomHeader {
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    pub battery: bool,
+    pub trainer: bool,
+    // all sizes are in bytes
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub timing: Timing,
+    pub misc_roms: u8,
+    // a PlayChoice-10 arcade dump, its INST-ROM and PROM follow CHR ROM
+    pub playchoice: bool,
+}
+
+impl RomHeader {
+    pub fn from_bytes(raw: &[u8]) -> Result<RomHeader, RomError> {
+        if raw.len() < HEADER_SIZE || raw[0..4] != NES_TAG {
+            return Err(RomError::BadHeader);
+        }
+
+        let flags_6 = raw[6];
+        let flags_7 = raw[7];
+        let mirroring = match (flags_6 & 0b1000 != 0, flags_6 & 0b1 != 0) {
+            (true, _) => Mirroring::FourScreen,
+            (false, true) => Mirroring::Vertical,
+            (false, false) => Mirroring::Horizontal,
+        };
+        let battery = flags_6 & 0b10 != 0;
+        let trainer = flags_6 & 0b100 != 0;
+        let mapper = ((flags_7 & 0b1111_0000) | (flags_6 >> 4)) as u16;
+
+        let header = if flags_7 & 0b1100 == 0b1000 {
+            RomHeader {
+                format: HeaderFormat::Nes2,
+                mapper: mapper | ((raw[8] & 0b1111) as u16) << 8,
+                submapper: raw[8] >> 4,
+                mirroring,
+                battery,
+                trainer,
+                prg_rom_size: nes2_rom_size(raw[4], raw[9] & 0b1111, PRG_ROM_PAGE_SIZE),
+                chr_rom_size: nes2_rom_size(raw[5], raw[9] >> 4, CHR_ROM_PAGE_SIZE),
+                prg_ram_size: nes2_ram_size(raw[10] & 0b1111),
+                prg_nvram_size: nes2_ram_size(raw[10] >> 4),
+                chr_ram_size: nes2_ram_size(raw[11] & 0b1111),
+                chr_nvram_size: nes2_ram_size(raw[11] >> 4),
+                timing: match raw[12] & 0b11 {
+                    0 => Timing::Ntsc,
+                    1 => Timing::Pal,
+                    2 => Timing::MultiRegion,
+                    _ => Timing::Dendy,
+                },
+                misc_roms: raw[14] & 0b11,
+                // console type 2
+                playchoice: flags_7 & 0b11 == 0b10,
+            }
+        } else {
+            // old dumps can have garbage such as "DiskDude!" from byte 7 on,
+            // in which case the upper mapper nibble cannot be trusted
+            let dirty = raw[12..16].iter().any(|&byte| byte != 0);
+            let chr_rom_size = raw[5] as usize * CHR_ROM_PAGE_SIZE;
+            RomHeader {
+                format: HeaderFormat::INes,
+                mapper: if dirty { mapper & 0b1111 } else { mapper },
+                submapper: 0,
+                mirroring,
+                battery,
+                trainer,
+                prg_rom_size: raw[4] as usize * PRG_ROM_PAGE_SIZE,
+                chr_rom_size,
+                // a zero here means 8 KiB for compatibility
+                prg_ram_size: raw[8].max(1) as usize * PRG_RAM_PAGE_SIZE,
+                prg_nvram_size: 0,
+                chr_ram_size: if chr_rom_size == 0 {
+                    CHR_ROM_PAGE_SIZE
+                } else {
+                    0
+                },
+                chr_nvram_size: 0,
+                timing: if raw[9] & 0b1 != 0 && !dirty {
+                    Timing::Pal
+                } else {
+                    Timing::Ntsc
+                },
+                misc_roms: 0,
+                playchoice: flags_7 & 0b10 != 0 && !dirty,
+            }
+        };
+        // PRG ROM comes in whole 8 KiB banks, the smallest any mapper switches
+        if header.prg_rom_size == 0 || header.prg_rom_size % PRG_ROM_BANK_MIN != 0 {
+            return Err(RomError::BadHeader);
+        }
+        Ok(header)
+    }
+}
+
+// with the MSB nibble at 0xF the LSB byte holds an exponent and a multiplier
+fn nes2_rom_size(lsb: u8, msb: u8, page_size: usize) -> usize {
+    if msb == 0b1111 {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        2usize.saturating_pow(exponent).saturating_mul(multiplier)
+    } else {
+        ((msb as usize) << 8 | lsb as usize) * page_size
+    }
+}
+
+fn nes2_ram_size(shift: u8) -> usize {
+    if shift == 0 {
+        0
+    } else {
+        64 << shift
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rom {
+    // read-only, to_bytes writes the header the file came with
+    header: RomHeader,
+    pub prg_rom: Vec<u8>,
+    // empty when the board has CHR RAM instead
+    pub chr_rom: Vec<u8>,
+    // 512 bytes meant to be copied to 0x7000
+    pub trainer: Option<Vec<u8>>,
+    // NES 2.0 only: whatever follows CHR ROM
+    pub misc_rom: Vec<u8>,
+    // kept verbatim so patches see the same file they were made against
+    raw_header: [u8; HEADER_SIZE],
+}
+
+impl Rom {
+    pub fn from_bytes(raw: &[u8]) -> Result<Rom, RomError> {
+        let header = RomHeader::from_bytes(raw)?;
+
+        let trainer_size = if header.trainer { TRAINER_SIZE } else { 0 };
+        let prg_rom_start = HEADER_SIZE + trainer_size;
+        // exponent sizes in NES 2.0 headers can be absurdly large
+        let chr_rom_start = prg_rom_start
+            .checked_add(header.prg_rom_size)
+            .ok_or(RomError::BadHeader)?;
+        let expected = chr_rom_start
+            .checked_add(header.chr_rom_size)
+            .ok_or(RomError::BadHeader)?;
+        if raw.len() < expected {
+            return Err(RomError::Truncated {
+                expected,
+                actual: raw.len(),
+            });
+        }
+
+        Ok(Rom {
+            header,
+            prg_rom: raw[prg_rom_start..chr_rom_start].to_vec(),
+            chr_rom: raw[chr_rom_start..expected].to_vec(),
+            trainer: header
+                .trainer
+                .then(|| raw[HEADER_SIZE..prg_rom_start].to_vec()),
+            // the arcade's INST-ROM and PROM are of no use to the game
+            misc_rom: if header.misc_roms > 0 && !header.playchoice {
+                raw[expected..].to_vec()
+            } else {
+                Vec::new()
+            },
+            raw_header: raw[..HEADER_SIZE].try_into().unwrap(),
+        })
+    }
+
+    pub fn header(&self) -> &RomHeader {
+        &self.header
+    }
+
+    // the file layout again, anything after the last ROM section is dropped
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut raw = self.raw_header.to_vec();
+        if let Some(trainer) = &self.trainer {
+            raw.extend_from_slice(trainer);
+        }
+        raw.extend_from_slice(&self.prg_rom);
+        raw.extend_from_slice(&self.chr_rom);
+        raw.extend_from_slice(&self.misc_rom);
+        raw
+    }
+
+    // the header is left out so that retagged dumps still match
+    pub fn content_crc32(&self) -> u32 {
+        crc32(&[self.prg_rom.as_slice(), self.chr_rom.as_slice()].concat())
+    }
+}
+
+// a headered iNES image for tests, page counts follow the ROM sizes
+#[cfg(test)]
+pub(crate) fn ines(flags_6: u8, flags_7: u8, prg_rom: &[u8], chr_rom: &[u8]) -> Vec<u8> {
+    let mut raw = NES_TAG.to_vec();
+    raw.extend([
+        (prg_rom.len() / PRG_ROM_PAGE_SIZE) as u8,
+        (chr_rom.len() / CHR_ROM_PAGE_SIZE) as u8,
+        flags_6,
+        flags_7,
+    ]);
+    raw.resize(HEADER_SIZE, 0);
+    raw.extend_from_slice(prg_rom);
+    raw.extend_from_slice(chr_rom);
+    raw
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses_header_fields() {
+        let rom = Rom::from_bytes(&ines(
+            0b0001_0011,
+            0b0100_0000,
+            &[0x11; 2 * PRG_ROM_PAGE_SIZE],
+            &[0x22; CHR_ROM_PAGE_SIZE],
+        ))
+        .unwrap();
+        assert_eq!(rom.prg_rom.len(), 0x8000);
+        assert_eq!(rom.chr_rom.len(), 0x2000);
+        assert_eq!(rom.header().format, HeaderFormat::INes);
+        assert_eq!(rom.header().mapper, 0x41);
+        assert_eq!(rom.header().mirroring, Mirroring::Vertical);
+        assert!(rom.header().battery);
+        assert_eq!(rom.header().prg_ram_size, 0x2000);
+        assert_eq!(rom.header().chr_ram_size, 0);
+        assert_eq!(rom.trainer, None);
+    }
+
+    #[test]
+    fn skips_trainer() {
+        let mut raw = ines(0b0000_1100, 0, &[0x11; PRG_ROM_PAGE_SIZE], &[]);
+        raw.splice(HEADER_SIZE..HEADER_SIZE, [0x77; TRAINER_SIZE]);
+        let rom = Rom::from_bytes(&raw).unwrap();
+        assert_eq!(rom.trainer, Some(vec![0x77; TRAINER_SIZE]));
+        assert_eq!(rom.prg_rom, vec![0x11; PRG_ROM_PAGE_SIZE]);
+        assert!(rom.chr_rom.is_empty());
+        assert_eq!(rom.header().chr_ram_size, 0x2000);
+        assert_eq!(rom.header().mirroring, Mirroring::FourScreen);
+    }
+
+    #[test]
+    fn names_common_mappers() {
+        assert_eq!(mapper_name(1), Some("MMC1"));
+        assert_eq!(mapper_name(64), Some("RAMBO-1"));
+        assert_eq!(mapper_name(255), None);
+    }
+
+    #[test]
+    fn ignores_mapper_high_nibble_in_dirty_headers() {
+        let mut raw = ines(
+            0b0001_0000,
+            0b0100_0000,
+            &[0x11; PRG_ROM_PAGE_SIZE],
+            &[0x22; CHR_ROM_PAGE_SIZE],
+        );
+        raw[7..16].copy_from_slice(b"DiskDude!");
+        let header = RomHeader::from_bytes(&raw).unwrap();
+        assert_eq!(header.mapper, 1);
+    }
+
+    #[test]
+    fn parses_nes2_header() {
+        let mut raw = ines(
+            0b0001_0010,
+            0b0100_1000,
+            &[0x11; 2 * PRG_ROM_PAGE_SIZE],
+            &[0x22; CHR_ROM_PAGE_SIZE],
+        );
+        // mapper bits 8-11 and submapper, PRG RAM and NVRAM, CHR RAM, PAL
+        raw[8] = 0b0011_0001;
+        raw[10] = 0b0111_0111;
+        raw[11] = 0b0000_0111;
+        raw[12] = 1;
+        raw[14] = 1;
+        raw.extend([0x33; 4]);
+        let rom = Rom::from_bytes(&raw).unwrap();
+        let header = *rom.header();
+        assert_eq!(header.format, HeaderFormat::Nes2);
+        assert_eq!(header.mapper, 0x141);
+        assert_eq!(header.submapper, 3);
+        assert_eq!(header.prg_rom_size, 0x8000);
+        assert_eq!(header.chr_rom_size, 0x2000);
+        assert_eq!(header.prg_ram_size, 0x2000);
+        assert_eq!(header.prg_nvram_size, 0x2000);
+        assert_eq!(header.chr_ram_size, 0x2000);
+        assert_eq!(header.chr_nvram_size, 0);
+        assert_eq!(header.timing, Timing::Pal);
+        assert_eq!(rom.misc_rom, vec![0x33; 4]);
+    }
+
+    #[test]
+    fn decodes_nes2_exponent_sizes() {
+        assert_eq!(nes2_rom_size(2, 0, PRG_ROM_PAGE_SIZE), 0x8000);
+        assert_eq!(nes2_rom_size(0x01, 0x1, PRG_ROM_PAGE_SIZE), 0x101 * 0x4000);
+        // 2^4 * (1 * 2 + 1)
+        assert_eq!(nes2_rom_size(0b0001_0001, 0xF, PRG_ROM_PAGE_SIZE), 48);
+    }
+
+    #[test]
+    fn round_trips_file_bytes() {
+        let mut raw = ines(
+            0b0000_0100,
+            0,
+            &[0x11; PRG_ROM_PAGE_SIZE],
+            &[0x22; CHR_ROM_PAGE_SIZE],
+        );
+        raw.splice(HEADER_SIZE..HEADER_SIZE, [0x77; TRAINER_SIZE]);
+        assert_eq!(Rom::from_bytes(&raw).unwrap().to_bytes(), raw);
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        assert_eq!(Rom::from_bytes(b"NES"), Err(RomError::BadHeader));
+        assert_eq!(Rom::from_bytes(&[0; 32]), Err(RomError::BadHeader));
+    }
+
+    #[test]
+    fn rejects_oversized_exponent_prg() {
+        let mut raw = ines(0, 0b0000_1000, &[0x11; PRG_ROM_PAGE_SIZE], &[]);
+        raw[4] = 0xFF;
+        raw[9] = 0x0F;
+        assert_eq!(Rom::from_bytes(&raw), Err(RomError::BadHeader));
+        // 2^63 bytes each of PRG and CHR overflow the file offsets
+        raw[4] = 0xFC;
+        raw[5] = 0xFC;
+        raw[9] = 0xFF;
+        assert_eq!(Rom::from_bytes(&raw), Err(RomError::BadHeader));
+    }
+
+    #[test]
+    fn ignores_playchoice_sections() {
+        let game = ines(
+            0,
+            0b0000_0010,
+            &[0x11; PRG_ROM_PAGE_SIZE],
+            &[0x22; CHR_ROM_PAGE_SIZE],
+        );
+        // 8 KiB INST-ROM and 32 bytes of PROM
+        let mut raw = game.clone();
+        raw.extend([0x33; 0x2000 + 32]);
+        let rom = Rom::from_bytes(&raw).unwrap();
+        assert!(rom.header().playchoice);
+        assert_eq!(rom.prg_rom, vec![0x11; PRG_ROM_PAGE_SIZE]);
+        assert_eq!(rom.chr_rom, vec![0x22; CHR_ROM_PAGE_SIZE]);
+        assert_eq!(rom.to_bytes(), game);
+
+        // NES 2.0 console type 2 with the sections counted as misc ROM
+        raw[7] = 0b0000_1010;
+        raw[14] = 1;
+        let rom = Rom::from_bytes(&raw).unwrap();
+        assert!(rom.header().playchoice);
+        assert!(rom.misc_rom.is_empty());
+
+        let rom = Rom::from_bytes(&ines(0, 0, &[0; PRG_ROM_PAGE_SIZE], &[])).unwrap();
+        assert!(!rom.header().playchoice);
+    }
+
+    #[test]
+    fn rejects_partial_prg_bank() {
+        let mut raw = ines(0, 0b0000_1000, &[0x11; PRG_ROM_PAGE_SIZE], &[]);
+        // 2^12 * 1 bytes, half a bank
+        raw[4] = 0b0011_0000;
+        raw[9] = 0x0F;
+        assert_eq!(Rom::from_bytes(&raw), Err(RomError::BadHeader));
+    }
+
+    #[test]
+    fn rejects_truncated_file() {
+        let mut raw = ines(0, 0, &[0x11; PRG_ROM_PAGE_SIZE], &[0x22; CHR_ROM_PAGE_SIZE]);
+        raw.truncate(raw.len() - 1);
+        assert_eq!(
+            Rom::from_bytes(&raw),
+            Err(RomError::Truncated {
+                expected: 16 + 0x4000 + 0x2000,
+                actual: 16 + 0x4000 + 0x2000 - 1
+            })
+        );
+    }
+
+    #[test]
+    fn names_unsupported_mapper_and_alternatives() {
+        let err = RomError::UnsupportedMapper {
+            mapper: 4,
+            name: mapper_name(4),
+            supported: &[0, 1, 200],
+        };
+        assert_eq!(
+            err.to_string(),
+            "mapper 4 (MMC3) is not supported, supported mappers are 0 (NROM), 1 (MMC1), 200; \
+             the nearest is 1 (MMC1)"
+        );
+        let err = RomError::UnsupportedMapper {
+            mapper: 150,
+            name: None,
+            supported: &[0, 100, 200],
+        };
+        assert!(err.to_string().ends_with("the nearest is 100"));
+    }
+}
@@ -0,0 +1,171 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::png::{self, Image, PngError};
+
+// sheets are 16 tiles across, each tile 8x8 pixels in 16 bytes
+const TILES_PER_ROW: usize = 16;
+const TILE_SIZE: usize = 16;
+const SHEET_WIDTH: usize = TILES_PER_ROW * 8;
+// the four pixel values as shades of grey, darkest first
+const SHADES: [u8; 4] = [0x00, 0x55, 0xAA, 0xFF];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChrError {
+    Png(PngError),
+    WrongSize {
+        expected: (usize, usize),
+        actual: (usize, usize),
+    },
+}
+
+impl fmt::Display for ChrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ChrError::Png(err) => err.fmt(f),
+            ChrError::WrongSize { expected, actual } => write!(
+                f,
+                "sheet is {}x{} but the CHR needs {}x{}",
+                actual.0, actual.1, expected.0, expected.1
+            ),
+        }
+    }
+}
+
+impl Error for ChrError {}
+
+impl From<PngError> for ChrError {
+    fn from(err: PngError) -> Self {
+        ChrError::Png(err)
+    }
+}
+
+fn sheet_size(chr_len: usize) -> (usize, usize) {
+    let tiles = chr_len / TILE_SIZE;
+    (SHEET_WIDTH, tiles.div_ceil(TILES_PER_ROW) * 8)
+}
+
+// lays every tile out on a greyscale sheet
+pub(crate) fn to_image(chr: &[u8]) -> Image {
+    let (width, height) = sheet_size(chr.len());
+    let mut rgb = vec![0; width * height * 3];
+    for (tile, data) in chr.chunks_exact(TILE_SIZE).enumerate() {
+        let (left, top) = ((tile % TILES_PER_ROW) * 8, (tile / TILES_PER_ROW) * 8);
+        for row in 0..8 {
+            for col in 0..8 {
+                let bit = 7 - col;
+                let value = ((data[row + 8] >> bit) & 1) << 1 | ((data[row] >> bit) & 1);
+                let base = ((top + row) * width + left + col) * 3;
+                rgb[base..base + 3].fill(SHADES[value as usize]);
+            }
+        }
+    }
+    Image { width, height, rgb }
+}
+
+// encodes a sheet back into tiles, taking each pixel as the nearest shade
+// by brightness so sheets recolored in an editor still import
+pub(crate) fn from_image(image: &Image, chr: &mut [u8]) -> Result<(), ChrError> {
+    let expected = sheet_size(chr.len());
+    if (image.width, image.height) != expected {
+        return Err(ChrError::WrongSize {
+            expected,
+            actual: (image.width, image.height),
+        });
+    }
+    for (tile, data) in chr.chunks_exact_mut(TILE_SIZE).enumerate() {
+        let (left, top) = ((tile % TILES_PER_ROW) * 8, (tile / TILES_PER_ROW) * 8);
+        for row in 0..8 {
+            let (mut low, mut high) = (0, 0);
+            for col in 0..8 {
+                let base = ((top + row) * image.width + left + col) * 3;
+                let value = shade(&image.rgb[base..base + 3]);
+                low = (low << 1) | (value & 1);
+                high = (high << 1) | (value >> 1);
+            }
+            data[row] = low;
+            data[row + 8] = high;
+        }
+    }
+    Ok(())
+}
+
+fn shade(rgb: &[u8]) -> u8 {
+    let brightness = (rgb[0] as u16 * 2 + rgb[1] as u16 * 5 + rgb[2] as u16) / 8;
+    ((brightness + 0x2A) / 0x55).min(3) as u8
+}
+
+pub fn to_png(chr: &[u8]) -> Vec<u8> {
+    png::encode(&to_image(chr))
+}
+
+pub fn from_png(data: &[u8], chr: &mut [u8]) -> Result<(), ChrError> {
+    let expected = sheet_size(chr.len());
+    let actual = png::dimensions(data)?;
+    if actual != expected {
+        return Err(ChrError::WrongSize { expected, actual });
+    }
+    from_image(&png::decode(data)?, chr)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lays_out_tiles_sixteen_across() {
+        let mut chr = vec![0; 0x2000];
+        // tile 17: top left pixel value 3, next one value 1
+        chr[17 * 16] = 0b1100_0000;
+        chr[17 * 16 + 8] = 0b1000_0000;
+        let image = to_image(&chr);
+        assert_eq!((image.width, image.height), (128, 256));
+        let pixel = |x: usize, y: usize| image.rgb[(y * 128 + x) * 3];
+        assert_eq!((pixel(8, 8), pixel(9, 8), pixel(10, 8)), (0xFF, 0x55, 0x00));
+    }
+
+    #[test]
+    fn round_trips_through_png() {
+        let chr: Vec<u8> = (0..0x2000).map(|i| (i * 7 + i / 3) as u8).collect();
+        let mut imported = vec![0; 0x2000];
+        from_png(&to_png(&chr), &mut imported).unwrap();
+        assert_eq!(imported, chr);
+    }
+
+    #[test]
+    fn maps_colors_to_nearest_shade() {
+        assert_eq!(shade(&[0x10, 0x10, 0x10]), 0);
+        assert_eq!(shade(&[0x60, 0x50, 0x40]), 1);
+        assert_eq!(shade(&[0xFF, 0x00, 0x00]), 1);
+        assert_eq!(shade(&[0xF0, 0xF0, 0xF0]), 3);
+    }
+
+    #[test]
+    fn rejects_sheet_of_wrong_size() {
+        let mut chr = vec![0; 0x2000];
+        let image = to_image(&[0; 0x1000]);
+        assert_eq!(
+            from_image(&image, &mut chr),
+            Err(ChrError::WrongSize {
+                expected: (128, 256),
+                actual: (128, 128)
+            })
+        );
+    }
+
+    #[test]
+    fn checks_sheet_size_before_decoding() {
+        let mut chr = vec![0; 0x2000];
+        let mut png = to_png(&[0; 0x1000]);
+        // a corrupt image body is never reached
+        let last = png.len() - 1;
+        png[last] ^= 1;
+        assert_eq!(
+            from_png(&png, &mut chr),
+            Err(ChrError::WrongSize {
+                expected: (128, 256),
+                actual: (128, 128)
+            })
+        );
+    }
+}
@@ -0,0 +1,155 @@
+use super::CPU;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Accumulator,
+    Immediate,
+    ZeroPage,
+    ZeroPageX,
+    ZeroPageY,
+    Absolute,
+    AbsoluteX,
+    AbsoluteY,
+    Indirect,
+    IndirectX,
+    IndirectY,
+    Relative,
+    NoneAddressing,
+}
+
+impl CPU {
+    pub(super) fn operand_address(&mut self, mode: AddressingMode) -> u16 {
+        self.operand_address_crossing(mode).0
+    }
+
+    // also reports whether indexing or a branch crossed into another page,
+    // which costs an extra cycle on some instructions
+    pub(super) fn operand_address_crossing(&mut self, mode: AddressingMode) -> (u16, bool) {
+        let addr = match mode {
+            AddressingMode::Immediate => self.prog_counter,
+            AddressingMode::ZeroPage => self.mem_read(self.prog_counter) as u16,
+            AddressingMode::Absolute => self.mem_read_u16(self.prog_counter),
+
+            AddressingMode::ZeroPageX => {
+                let pos = self.mem_read(self.prog_counter);
+                pos.wrapping_add(self.reg_x) as u16
+            }
+
+            AddressingMode::ZeroPageY => {
+                let pos = self.mem_read(self.prog_counter);
+                pos.wrapping_add(self.reg_y) as u16
+            }
+
+            AddressingMode::AbsoluteX => {
+                let base = self.mem_read_u16(self.prog_counter);
+                let addr = base.wrapping_add(self.reg_x as u16);
+                return (addr, page_crossed(base, addr));
+            }
+
+            AddressingMode::AbsoluteY => {
+                let base = self.mem_read_u16(self.prog_counter);
+                let addr = base.wrapping_add(self.reg_y as u16);
+                return (addr, page_crossed(base, addr));
+            }
+
+            AddressingMode::Indirect => {
+                let ptr = self.mem_read_u16(self.prog_counter);
+                // 6502 bug: a pointer at 0xXXFF takes its high byte from 0xXX00
+                let low = self.mem_read(ptr) as u16;
+                let high = self.mem_read((ptr & 0xFF00) | (ptr.wrapping_add(1) & 0x00FF)) as u16;
+                (high << 8) | low
+            }
+
+            AddressingMode::IndirectX => {
+                let base = self.mem_read(self.prog_counter);
+
+                let ptr: u8 = base.wrapping_add(self.reg_x);
+                self.mem_read_u16_zero_page(ptr)
+            }
+
+            AddressingMode::IndirectY => {
+                let base = self.mem_read(self.prog_counter);
+
+                let deref_base = self.mem_read_u16_zero_page(base);
+                let addr = deref_base.wrapping_add(self.reg_y as u16);
+                return (addr, page_crossed(deref_base, addr));
+            }
+
+            // the offset is relative to the instruction that follows the branch
+            AddressingMode::Relative => {
+                let offset = self.mem_read(self.prog_counter) as i8;
+                let next = self.prog_counter.wrapping_add(1);
+                let addr = next.wrapping_add(offset as u16);
+                return (addr, page_crossed(next, addr));
+            }
+
+            AddressingMode::Accumulator | AddressingMode::NoneAddressing => {
+                panic!("mode {:?} is not supported", mode);
+            }
+        };
+        (addr, false)
+    }
+}
+
+fn page_crossed(from: u16, to: u16) -> bool {
+    from & 0xFF00 != to & 0xFF00
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::CPU;
+
+    #[test]
+    fn indirect_x_wraps_within_zero_page() {
+        let mut cpu = CPU::new();
+        cpu.prog_counter = 0x8000;
+        cpu.bus.poke(0x8000, 0xFE);
+        cpu.reg_x = 0x01;
+        cpu.bus.poke(0x00FF, 0x34);
+        cpu.bus.poke(0x0000, 0x12);
+        cpu.bus.poke(0x0100, 0x99);
+        // operand 0xFE + X lands on 0xFF, high byte comes from 0x00
+        assert_eq!(cpu.operand_address(AddressingMode::IndirectX), 0x1234);
+    }
+
+    #[test]
+    fn indirect_y_wraps_within_zero_page() {
+        let mut cpu = CPU::new();
+        cpu.prog_counter = 0x8000;
+        cpu.bus.poke(0x8000, 0xFF);
+        cpu.bus.poke(0x00FF, 0x00);
+        cpu.bus.poke(0x0000, 0x02);
+        cpu.bus.poke(0x0100, 0x99);
+        cpu.reg_y = 0x10;
+        assert_eq!(cpu.operand_address(AddressingMode::IndirectY), 0x0210);
+    }
+
+    #[test]
+    fn reports_page_crossing() {
+        let mut cpu = CPU::new();
+        cpu.prog_counter = 0x8000;
+        cpu.bus.poke(0x8000, 0xF0);
+        cpu.bus.poke(0x8001, 0x02);
+        cpu.reg_x = 0x0F;
+        assert_eq!(
+            cpu.operand_address_crossing(AddressingMode::AbsoluteX),
+            (0x02FF, false)
+        );
+        cpu.reg_x = 0x10;
+        assert_eq!(
+            cpu.operand_address_crossing(AddressingMode::AbsoluteX),
+            (0x0300, true)
+        );
+    }
+
+    #[test]
+    fn absolute_y_wraps_around_address_space() {
+        let mut cpu = CPU::new();
+        cpu.prog_counter = 0x8000;
+        cpu.bus.poke(0x8000, 0xFF);
+        cpu.bus.poke(0x8001, 0xFF);
+        cpu.reg_y = 0x02;
+        assert_eq!(cpu.operand_address(AddressingMode::AbsoluteY), 0x0001);
+    }
+}
@@ -0,0 +1,43 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::cartridge::RomError;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuError {
+    UnknownOpcode { opcode: u8, pc: u16 },
+    Rom(RomError),
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::UnknownOpcode { opcode, pc } => {
+                write!(f, "unknown opcode {opcode:#04x} at {pc:#06x}")
+            }
+            CpuError::Rom(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl Error for CpuError {}
+
+impl From<RomError> for CpuError {
+    fn from(err: RomError) -> Self {
+        CpuError::Rom(err)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn describes_unknown_opcode() {
+        let err = CpuError::UnknownOpcode {
+            opcode: 0x02,
+            pc: 0x8001,
+        };
+        assert_eq!(err.to_string(), "unknown opcode 0x02 at 0x8001");
+    }
+}
@@ -0,0 +1,1108 @@
+use super::{
+    AddressingMode, BREAK, CARRY, CPU, DECIMAL, IRQ_VECTOR, NEGATIVE, NO_INTERRUPT, OVERFLOW,
+    UNUSED, ZERO,
+};
+
+impl CPU {
+    fn update_flags_zero_and_neg(&mut self, val: u8) {
+        // updating zero flag
+        if val == 0 {
+            self.proc_status |= ZERO;
+        } else {
+            self.proc_status &= !ZERO;
+        }
+
+        // updating neg flag
+        if val & 0b1000_0000 != 0 {
+            self.proc_status |= NEGATIVE;
+        } else {
+            self.proc_status &= !NEGATIVE;
+        }
+    }
+
+    fn set_flag(&mut self, flag: u8, value: bool) {
+        if value {
+            self.proc_status |= flag;
+        } else {
+            self.proc_status &= !flag;
+        }
+    }
+
+    fn set_carry(&mut self, carry: bool) {
+        self.set_flag(CARRY, carry);
+    }
+
+    // the next interrupt poll still sees I as it was before this instruction
+    fn delay_irq_inhibit_change(&mut self) {
+        self.delayed_irq_inhibit = Some(self.proc_status & NO_INTERRUPT != 0);
+    }
+
+    // indexed reads take an extra cycle to fix up the high byte, reading
+    // from the unfixed address first
+    fn read_operand(&mut self, mode: AddressingMode) -> u8 {
+        let (addr, crossed) = self.operand_address_crossing(mode);
+        if crossed {
+            self.extra_cycles += 1;
+            self.dummy_read(addr, crossed);
+        }
+        self.mem_read(addr)
+    }
+
+    // stores and read-modify-writes through an index always spend that cycle
+    fn write_address(&mut self, mode: AddressingMode) -> u16 {
+        let (addr, crossed) = self.operand_address_crossing(mode);
+        if matches!(
+            mode,
+            AddressingMode::AbsoluteX | AddressingMode::AbsoluteY | AddressingMode::IndirectY
+        ) {
+            self.dummy_read(addr, crossed);
+        }
+        addr
+    }
+
+    // the read still goes through the bus, so register side effects happen
+    fn dummy_read(&mut self, addr: u16, crossed: bool) {
+        let unfixed = if crossed {
+            addr.wrapping_sub(0x100)
+        } else {
+            addr
+        };
+        self.mem_read(unfixed);
+    }
+}
+
+impl CPU {
+    pub(super) fn lda(&mut self, mode: AddressingMode) {
+        let param = self.read_operand(mode);
+        self.accumulator = param;
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn ldx(&mut self, mode: AddressingMode) {
+        self.reg_x = self.read_operand(mode);
+        self.update_flags_zero_and_neg(self.reg_x);
+    }
+
+    pub(super) fn ldy(&mut self, mode: AddressingMode) {
+        self.reg_y = self.read_operand(mode);
+        self.update_flags_zero_and_neg(self.reg_y);
+    }
+
+    pub(super) fn sta(&mut self, mode: AddressingMode) {
+        let addr = self.write_address(mode);
+        self.mem_write(addr, self.accumulator);
+    }
+
+    pub(super) fn stx(&mut self, mode: AddressingMode) {
+        let addr = self.write_address(mode);
+        self.mem_write(addr, self.reg_x);
+    }
+
+    pub(super) fn sty(&mut self, mode: AddressingMode) {
+        let addr = self.write_address(mode);
+        self.mem_write(addr, self.reg_y);
+    }
+}
+
+impl CPU {
+    // the 2A03 has no decimal mode, so D is ignored here
+    fn add_to_accumulator(&mut self, data: u8) {
+        let sum = self.accumulator as u16 + data as u16 + (self.proc_status & CARRY) as u16;
+        let result = sum as u8;
+        self.set_carry(sum > 0xff);
+        self.set_flag(
+            OVERFLOW,
+            (self.accumulator ^ result) & (data ^ result) & 0x80 != 0,
+        );
+        self.accumulator = result;
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn adc(&mut self, mode: AddressingMode) {
+        let data = self.read_operand(mode);
+        self.add_to_accumulator(data);
+    }
+
+    pub(super) fn sbc(&mut self, mode: AddressingMode) {
+        // A - M - !C == A + !M + C
+        let data = self.read_operand(mode);
+        self.add_to_accumulator(!data);
+    }
+
+    pub(super) fn and(&mut self, mode: AddressingMode) {
+        self.accumulator &= self.read_operand(mode);
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn ora(&mut self, mode: AddressingMode) {
+        self.accumulator |= self.read_operand(mode);
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn eor(&mut self, mode: AddressingMode) {
+        self.accumulator ^= self.read_operand(mode);
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    fn compare(&mut self, mode: AddressingMode, register: u8) {
+        let data = self.read_operand(mode);
+        self.compare_value(register, data);
+    }
+
+    fn compare_value(&mut self, register: u8, data: u8) {
+        self.set_carry(register >= data);
+        self.update_flags_zero_and_neg(register.wrapping_sub(data));
+    }
+
+    pub(super) fn cmp(&mut self, mode: AddressingMode) {
+        self.compare(mode, self.accumulator);
+    }
+
+    pub(super) fn cpx(&mut self, mode: AddressingMode) {
+        self.compare(mode, self.reg_x);
+    }
+
+    pub(super) fn cpy(&mut self, mode: AddressingMode) {
+        self.compare(mode, self.reg_y);
+    }
+
+    pub(super) fn bit(&mut self, mode: AddressingMode) {
+        let data = self.read_operand(mode);
+
+        self.set_flag(ZERO, self.accumulator & data == 0);
+
+        // N and V are copied straight from bits 7 and 6 of memory
+        self.proc_status =
+            (self.proc_status & !(NEGATIVE | OVERFLOW)) | (data & (NEGATIVE | OVERFLOW));
+    }
+}
+
+impl CPU {
+    fn read_modify_write(&mut self, mode: AddressingMode, op: fn(&mut CPU, u8) -> u8) -> u8 {
+        if mode == AddressingMode::Accumulator {
+            self.accumulator = op(self, self.accumulator);
+            self.update_flags_zero_and_neg(self.accumulator);
+            return self.accumulator;
+        }
+        let addr = self.write_address(mode);
+        let data = self.mem_read(addr);
+        // the 6502 writes the unmodified value back before the result
+        self.mem_write(addr, data);
+        let result = op(self, data);
+        self.mem_write(addr, result);
+        self.update_flags_zero_and_neg(result);
+        result
+    }
+
+    fn shift_left(&mut self, data: u8) -> u8 {
+        self.set_carry(data & 0b1000_0000 != 0);
+        data << 1
+    }
+
+    fn shift_right(&mut self, data: u8) -> u8 {
+        self.set_carry(data & 0b0000_0001 != 0);
+        data >> 1
+    }
+
+    fn rotate_left(&mut self, data: u8) -> u8 {
+        let carry_in = self.proc_status & CARRY;
+        self.set_carry(data & 0b1000_0000 != 0);
+        (data << 1) | carry_in
+    }
+
+    fn rotate_right(&mut self, data: u8) -> u8 {
+        let carry_in = (self.proc_status & CARRY) << 7;
+        self.set_carry(data & 0b0000_0001 != 0);
+        (data >> 1) | carry_in
+    }
+
+    pub(super) fn asl(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, CPU::shift_left);
+    }
+
+    pub(super) fn lsr(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, CPU::shift_right);
+    }
+
+    pub(super) fn rol(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, CPU::rotate_left);
+    }
+
+    pub(super) fn ror(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, CPU::rotate_right);
+    }
+
+    pub(super) fn inc(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, |_, data| data.wrapping_add(1));
+    }
+
+    pub(super) fn dec(&mut self, mode: AddressingMode) {
+        self.read_modify_write(mode, |_, data| data.wrapping_sub(1));
+    }
+}
+
+impl CPU {
+    pub(super) fn inx(&mut self) {
+        self.reg_x = self.reg_x.wrapping_add(1);
+        self.update_flags_zero_and_neg(self.reg_x);
+    }
+
+    pub(super) fn iny(&mut self) {
+        self.reg_y = self.reg_y.wrapping_add(1);
+        self.update_flags_zero_and_neg(self.reg_y);
+    }
+
+    pub(super) fn dex(&mut self) {
+        self.reg_x = self.reg_x.wrapping_sub(1);
+        self.update_flags_zero_and_neg(self.reg_x);
+    }
+
+    pub(super) fn dey(&mut self) {
+        self.reg_y = self.reg_y.wrapping_sub(1);
+        self.update_flags_zero_and_neg(self.reg_y);
+    }
+
+    pub(super) fn tax(&mut self) {
+        self.reg_x = self.accumulator;
+        self.update_flags_zero_and_neg(self.reg_x);
+    }
+
+    pub(super) fn tay(&mut self) {
+        self.reg_y = self.accumulator;
+        self.update_flags_zero_and_neg(self.reg_y);
+    }
+
+    pub(super) fn txa(&mut self) {
+        self.accumulator = self.reg_x;
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn tya(&mut self) {
+        self.accumulator = self.reg_y;
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn tsx(&mut self) {
+        self.reg_x = self.stack_pointer;
+        self.update_flags_zero_and_neg(self.reg_x);
+    }
+
+    pub(super) fn txs(&mut self) {
+        self.stack_pointer = self.reg_x;
+    }
+
+    pub(super) fn clc(&mut self) {
+        self.proc_status &= !CARRY;
+    }
+
+    pub(super) fn cld(&mut self) {
+        self.proc_status &= !DECIMAL;
+    }
+
+    pub(super) fn cli(&mut self) {
+        self.delay_irq_inhibit_change();
+        self.proc_status &= !NO_INTERRUPT;
+    }
+
+    pub(super) fn clv(&mut self) {
+        self.proc_status &= !OVERFLOW;
+    }
+
+    pub(super) fn sec(&mut self) {
+        self.proc_status |= CARRY;
+    }
+
+    pub(super) fn sed(&mut self) {
+        self.proc_status |= DECIMAL;
+    }
+
+    pub(super) fn sei(&mut self) {
+        self.delay_irq_inhibit_change();
+        self.proc_status |= NO_INTERRUPT;
+    }
+
+    // the multi-byte NOPs still read their operand
+    pub(super) fn nop(&mut self, mode: AddressingMode) {
+        if mode != AddressingMode::NoneAddressing {
+            self.read_operand(mode);
+        }
+    }
+}
+
+impl CPU {
+    pub(super) fn pha(&mut self) {
+        self.stack_push(self.accumulator);
+    }
+
+    // the copy of P pushed by PHP always has B and the unused bit set
+    pub(super) fn php(&mut self) {
+        self.stack_push(self.proc_status | BREAK | UNUSED);
+    }
+
+    pub(super) fn pla(&mut self) {
+        self.accumulator = self.stack_pop();
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    // B does not exist in the real register, only in pushed copies
+    pub(super) fn plp(&mut self) {
+        self.delay_irq_inhibit_change();
+        self.proc_status = (self.stack_pop() & !BREAK) | UNUSED;
+    }
+
+    // JSR pushes the address of its own last byte, RTS adds one back
+    pub(super) fn jsr(&mut self) {
+        self.stack_push_u16(self.prog_counter.wrapping_add(1));
+        self.prog_counter = self.mem_read_u16(self.prog_counter);
+    }
+
+    pub(super) fn rts(&mut self) {
+        self.prog_counter = self.stack_pop_u16().wrapping_add(1);
+    }
+
+    // BRK skips the padding byte after it, so the pushed return address is BRK + 2
+    pub(super) fn brk(&mut self) {
+        self.mem_read(self.prog_counter);
+        self.stack_push_u16(self.prog_counter.wrapping_add(1));
+        self.stack_push(self.proc_status | BREAK | UNUSED);
+        self.proc_status |= NO_INTERRUPT;
+        self.prog_counter = self.mem_read_u16(IRQ_VECTOR);
+    }
+
+    pub(super) fn rti(&mut self) {
+        self.proc_status = (self.stack_pop() & !BREAK) | UNUSED;
+        self.prog_counter = self.stack_pop_u16();
+    }
+
+    pub(super) fn jmp(&mut self, mode: AddressingMode) {
+        self.prog_counter = self.operand_address(mode);
+    }
+
+    // a taken branch costs one more cycle, two if it lands on another page
+    pub(super) fn branch(&mut self, flag: u8, expected: bool) {
+        let (target, crossed) = self.operand_address_crossing(AddressingMode::Relative);
+        self.prog_counter = self.prog_counter.wrapping_add(1);
+        if (self.proc_status & flag != 0) == expected {
+            self.prog_counter = target;
+            self.extra_cycles += 1 + crossed as u8;
+            self.skip_interrupt_poll = !crossed;
+        }
+    }
+}
+
+// undocumented opcodes, most of them a read-modify-write fused with an ALU op
+impl CPU {
+    pub(super) fn lax(&mut self, mode: AddressingMode) {
+        self.accumulator = self.read_operand(mode);
+        self.reg_x = self.accumulator;
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn sax(&mut self, mode: AddressingMode) {
+        let addr = self.write_address(mode);
+        self.mem_write(addr, self.accumulator & self.reg_x);
+    }
+
+    pub(super) fn dcp(&mut self, mode: AddressingMode) {
+        let data = self.read_modify_write(mode, |_, data| data.wrapping_sub(1));
+        self.compare_value(self.accumulator, data);
+    }
+
+    pub(super) fn isb(&mut self, mode: AddressingMode) {
+        let data = self.read_modify_write(mode, |_, data| data.wrapping_add(1));
+        self.add_to_accumulator(!data);
+    }
+
+    pub(super) fn slo(&mut self, mode: AddressingMode) {
+        self.accumulator |= self.read_modify_write(mode, CPU::shift_left);
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn rla(&mut self, mode: AddressingMode) {
+        self.accumulator &= self.read_modify_write(mode, CPU::rotate_left);
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn sre(&mut self, mode: AddressingMode) {
+        self.accumulator ^= self.read_modify_write(mode, CPU::shift_right);
+        self.update_flags_zero_and_neg(self.accumulator);
+    }
+
+    pub(super) fn rra(&mut self, mode: AddressingMode) {
+        let data = self.read_modify_write(mode, CPU::rotate_right);
+        self.add_to_accumulator(data);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::opcodes::OPCODES;
+    use crate::cpu::BusAccessKind;
+    use crate::debug::Breakpoint;
+
+    #[test]
+    fn updates_flag_zero() {
+        let mut cpu = CPU::new();
+        cpu.update_flags_zero_and_neg(0);
+        assert!(cpu.flag_zero());
+    }
+
+    #[test]
+    fn updates_flag_neg() {
+        let mut cpu = CPU::new();
+        cpu.update_flags_zero_and_neg(0b1000_0000);
+        assert!(cpu.flag_neg());
+    }
+
+    #[test]
+    fn lda_loads_data() {
+        let mut cpu = CPU::new();
+        cpu.bus.poke(0x0, 0x05);
+        cpu.lda(AddressingMode::Immediate);
+        assert_eq!(cpu.accumulator, 0x05);
+    }
+
+    #[test]
+    fn bit_leaves_accumulator_untouched() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x24, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x0010, 0b0000_1111);
+        cpu.accumulator = 0b1111_0000;
+        cpu.step().unwrap();
+        assert_eq!(cpu.accumulator, 0b1111_0000);
+        assert!(cpu.flag_zero());
+        assert!(!cpu.flag_neg());
+        assert!(!cpu.flag_overflow());
+    }
+
+    #[test]
+    fn bit_copies_memory_bits_7_and_6_zero_page() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x24, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x0010, 0b1100_0001);
+        cpu.accumulator = 0b0000_0001;
+        cpu.step().unwrap();
+        assert_eq!(cpu.accumulator, 0b0000_0001);
+        assert!(!cpu.flag_zero());
+        assert!(cpu.flag_neg());
+        assert!(cpu.flag_overflow());
+    }
+
+    #[test]
+    fn bit_copies_memory_bits_7_and_6_absolute() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x2c, 0x34, 0x12, 0x00]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x1234, 0b0100_0000);
+        cpu.accumulator = 0b1000_0000;
+        cpu.proc_status = NEGATIVE;
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8003);
+        assert_eq!(cpu.accumulator, 0b1000_0000);
+        assert!(cpu.flag_zero());
+        assert!(!cpu.flag_neg());
+        assert!(cpu.flag_overflow());
+    }
+
+    #[test]
+    fn asl_memory_writes_back_and_keeps_accumulator() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x06, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        cpu.accumulator = 0x55;
+        cpu.bus.poke(0x0010, 0b1100_0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.peek(0x0010), 0b1000_0010);
+        assert_eq!(cpu.accumulator, 0x55);
+        assert!(cpu.flag_carry());
+        assert!(cpu.flag_neg());
+    }
+
+    #[test]
+    fn asl_accumulator_shifts_a() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x0a, 0x00]).unwrap();
+        cpu.reset();
+        cpu.accumulator = 0b1000_0000;
+        cpu.step().unwrap();
+        assert_eq!(cpu.accumulator, 0);
+        assert!(cpu.flag_carry());
+        assert!(cpu.flag_zero());
+    }
+
+    #[test]
+    fn lsr_memory_writes_back() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x4e, 0x34, 0x12, 0x00]).unwrap();
+        cpu.reset();
+        cpu.accumulator = 0x55;
+        cpu.bus.poke(0x1234, 0b0000_0011);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.peek(0x1234), 0b0000_0001);
+        assert_eq!(cpu.accumulator, 0x55);
+        assert!(cpu.flag_carry());
+    }
+
+    #[test]
+    fn rol_memory_rotates_carry_in() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x36, 0x0f, 0x00]).unwrap();
+        cpu.reset();
+        cpu.reg_x = 0x01;
+        cpu.proc_status = CARRY;
+        cpu.bus.poke(0x0010, 0b0100_0000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.peek(0x0010), 0b1000_0001);
+        assert!(!cpu.flag_carry());
+        assert!(cpu.flag_neg());
+    }
+
+    #[test]
+    fn ror_memory_rotates_carry_in() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x7e, 0x00, 0x12, 0x00]).unwrap();
+        cpu.reset();
+        cpu.reg_x = 0x34;
+        cpu.proc_status = CARRY;
+        cpu.bus.poke(0x1234, 0b0000_0001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.peek(0x1234), 0b1000_0000);
+        assert!(cpu.flag_carry());
+    }
+
+    #[test]
+    fn inc_and_dec_write_memory() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xe6, 0x10, 0xc6, 0x11, 0x00])
+            .unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x0010, 0xff);
+        cpu.bus.poke(0x0011, 0x01);
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.peek(0x0010), 0x00);
+        assert_eq!(cpu.bus.peek(0x0011), 0x00);
+        assert!(cpu.flag_zero());
+        assert_eq!(cpu.accumulator, 0);
+    }
+
+    #[test]
+    fn read_modify_write_bus_sequence() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xe6, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x0010, 0x41);
+        cpu.set_bus_recording(true);
+        let writes: Vec<(u16, u8)> = cpu
+            .step()
+            .unwrap()
+            .bus_activity
+            .iter()
+            .filter(|access| access.kind == BusAccessKind::Write)
+            .map(|access| (access.addr, access.value))
+            .collect();
+        assert_eq!(writes, vec![(0x0010, 0x41), (0x0010, 0x42)]);
+    }
+
+    fn bus_reads(cpu: &mut CPU) -> Vec<u16> {
+        cpu.step()
+            .unwrap()
+            .bus_activity
+            .iter()
+            .filter(|access| access.kind == BusAccessKind::Read)
+            .map(|access| access.addr)
+            .collect()
+    }
+
+    #[test]
+    fn indexed_read_crossing_page_reads_unfixed_address_first() {
+        // LDA $02F0,X; LDA $02F0,X
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xbd, 0xf0, 0x02, 0xbd, 0xf0, 0x02, 0x00])
+            .unwrap();
+        cpu.reset();
+        cpu.set_bus_recording(true);
+        cpu.reg_x = 0x20;
+        assert_eq!(
+            bus_reads(&mut cpu),
+            vec![0x8000, 0x8001, 0x8002, 0x0210, 0x0310]
+        );
+        cpu.reg_x = 0x01;
+        assert_eq!(bus_reads(&mut cpu), vec![0x8003, 0x8004, 0x8005, 0x02f1]);
+    }
+
+    #[test]
+    fn indexed_store_always_reads_first() {
+        // STA $0200,Y
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x99, 0x00, 0x02, 0x00]).unwrap();
+        cpu.reset();
+        cpu.set_bus_recording(true);
+        cpu.reg_y = 0x05;
+        assert_eq!(bus_reads(&mut cpu), vec![0x8000, 0x8001, 0x8002, 0x0205]);
+    }
+
+    #[test]
+    fn dummy_read_reaches_registers() {
+        // LDA $20FF,X touches PPUDATA at $2007 before reading $2107
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xbd, 0xff, 0x20, 0x00]).unwrap();
+        cpu.reset();
+        cpu.add_breakpoint(Breakpoint::on_register("PPUDATA", BusAccessKind::Read).unwrap());
+        cpu.reg_x = 0x08;
+        let hit = cpu.step().unwrap().breakpoint.unwrap();
+        assert_eq!(hit.addr, 0x2007);
+    }
+
+    #[test]
+    fn tax_moves_a_to_x() {
+        let mut cpu = CPU::new();
+        cpu.accumulator = 10;
+        cpu.tax();
+        assert_eq!(cpu.reg_x, 10);
+    }
+
+    #[test]
+    fn inx_increments() {
+        let mut cpu = CPU::new();
+        cpu.inx();
+        assert_eq!(cpu.reg_x, 1);
+    }
+
+    #[test]
+    fn branches_on_every_condition() {
+        let branches = OPCODES
+            .iter()
+            .flatten()
+            .filter_map(|op| op.branch.map(|(flag, expected)| (op.code, flag, expected)));
+        for (opcode, flag, expected) in branches {
+            for flag_set in [false, true] {
+                let mut cpu = CPU::new();
+                cpu.load_program(vec![opcode, 0x10, 0x00]).unwrap();
+                cpu.reset();
+                cpu.proc_status = if flag_set { flag } else { !flag };
+                cpu.step().unwrap();
+                let target = if flag_set == expected { 0x8012 } else { 0x8002 };
+                assert_eq!(
+                    cpu.prog_counter, target,
+                    "opcode {:#04x} with flag set = {}",
+                    opcode, flag_set
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bvs_branches_only_when_overflow_set() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x70, 0x04, 0x00]).unwrap();
+        cpu.reset();
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8002);
+
+        cpu.reset();
+        cpu.proc_status = OVERFLOW;
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8006);
+    }
+
+    #[test]
+    fn branches_backwards() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xe8, 0xd0, 0xfd, 0x00]).unwrap();
+        cpu.reset();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8000);
+    }
+
+    #[test]
+    fn inx_overflows() {
+        let mut cpu = CPU::new();
+        cpu.reg_x = 0xff;
+        cpu.inx();
+        assert_eq!(cpu.reg_x, 0);
+    }
+
+    // runs up to, but not including, the terminating BRK
+    fn run_program(program: Vec<u8>, setup: impl FnOnce(&mut CPU)) -> CPU {
+        let mut cpu = CPU::new();
+        cpu.load_program(program).unwrap();
+        cpu.reset();
+        setup(&mut cpu);
+        while cpu.bus.peek(cpu.prog_counter) != 0x00 {
+            cpu.step().unwrap();
+        }
+        cpu
+    }
+
+    #[test]
+    fn adc_adds_with_carry() {
+        let cpu = run_program(vec![0x69, 0x10, 0x00], |cpu| {
+            cpu.accumulator = 0x20;
+            cpu.proc_status = CARRY;
+        });
+        assert_eq!(cpu.accumulator, 0x31);
+        assert!(!cpu.flag_carry());
+        assert!(!cpu.flag_overflow());
+    }
+
+    #[test]
+    fn adc_sets_carry_and_overflow() {
+        let cpu = run_program(vec![0x69, 0xff, 0x00], |cpu| cpu.accumulator = 0x01);
+        assert_eq!(cpu.accumulator, 0x00);
+        assert!(cpu.flag_carry());
+        assert!(cpu.flag_zero());
+        assert!(!cpu.flag_overflow());
+
+        let cpu = run_program(vec![0x69, 0x50, 0x00], |cpu| cpu.accumulator = 0x50);
+        assert_eq!(cpu.accumulator, 0xa0);
+        assert!(cpu.flag_overflow());
+        assert!(cpu.flag_neg());
+    }
+
+    #[test]
+    fn adc_ignores_decimal_mode() {
+        let cpu = run_program(vec![0xf8, 0x69, 0x01, 0x00], |cpu| cpu.accumulator = 0x09);
+        assert_eq!(cpu.accumulator, 0x0a);
+    }
+
+    #[test]
+    fn sbc_subtracts_with_borrow() {
+        let cpu = run_program(vec![0x38, 0xe9, 0x01, 0x00], |cpu| cpu.accumulator = 0x03);
+        assert_eq!(cpu.accumulator, 0x02);
+        assert!(cpu.flag_carry());
+
+        let cpu = run_program(vec![0x18, 0xe9, 0x01, 0x00], |cpu| cpu.accumulator = 0x00);
+        assert_eq!(cpu.accumulator, 0xfe);
+        assert!(!cpu.flag_carry());
+        assert!(cpu.flag_neg());
+    }
+
+    #[test]
+    fn sbc_sets_overflow() {
+        let cpu = run_program(vec![0x38, 0xe9, 0x01, 0x00], |cpu| cpu.accumulator = 0x80);
+        assert_eq!(cpu.accumulator, 0x7f);
+        assert!(cpu.flag_overflow());
+    }
+
+    #[test]
+    fn logical_operations() {
+        let cpu = run_program(vec![0x29, 0x0f, 0x00], |cpu| cpu.accumulator = 0x3c);
+        assert_eq!(cpu.accumulator, 0x0c);
+        let cpu = run_program(vec![0x09, 0x80, 0x00], |cpu| cpu.accumulator = 0x01);
+        assert_eq!(cpu.accumulator, 0x81);
+        assert!(cpu.flag_neg());
+        let cpu = run_program(vec![0x49, 0xff, 0x00], |cpu| cpu.accumulator = 0xff);
+        assert_eq!(cpu.accumulator, 0x00);
+        assert!(cpu.flag_zero());
+    }
+
+    #[test]
+    fn compares_registers() {
+        let cpu = run_program(vec![0xc9, 0x10, 0x00], |cpu| cpu.accumulator = 0x10);
+        assert!(cpu.flag_carry());
+        assert!(cpu.flag_zero());
+
+        let cpu = run_program(vec![0xe0, 0x10, 0x00], |cpu| cpu.reg_x = 0x0f);
+        assert!(!cpu.flag_carry());
+        assert!(cpu.flag_neg());
+
+        let cpu = run_program(vec![0xc0, 0x10, 0x00], |cpu| cpu.reg_y = 0x20);
+        assert!(cpu.flag_carry());
+        assert!(!cpu.flag_zero());
+        assert_eq!(cpu.reg_y, 0x20);
+    }
+
+    #[test]
+    fn loads_x_and_y() {
+        // LDX #$05; LDY $05,X
+        let cpu = run_program(vec![0xa2, 0x05, 0xb4, 0x05, 0x00], |cpu| {
+            cpu.bus.poke(0x0a, 0x80);
+        });
+        assert_eq!(cpu.reg_x, 0x05);
+        assert_eq!(cpu.reg_y, 0x80);
+        assert!(cpu.flag_neg());
+
+        // LDY #$02; LDX $10,Y
+        let cpu = run_program(vec![0xa0, 0x02, 0xb6, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x12, 0x33);
+        });
+        assert_eq!(cpu.reg_x, 0x33);
+    }
+
+    #[test]
+    fn stores_registers() {
+        // STA $10; STX $0200; STY $20,X
+        let cpu = run_program(
+            vec![0x85, 0x10, 0x8e, 0x00, 0x02, 0x94, 0x20, 0x00],
+            |cpu| {
+                cpu.accumulator = 0x11;
+                cpu.reg_x = 0x01;
+                cpu.reg_y = 0x33;
+            },
+        );
+        assert_eq!(cpu.bus.peek(0x10), 0x11);
+        assert_eq!(cpu.bus.peek(0x0200), 0x01);
+        assert_eq!(cpu.bus.peek(0x21), 0x33);
+    }
+
+    #[test]
+    fn sta_indirect_y() {
+        let cpu = run_program(vec![0x91, 0x40, 0x00], |cpu| {
+            cpu.bus.poke(0x40, 0x00);
+            cpu.bus.poke(0x41, 0x03);
+            cpu.reg_y = 0x05;
+            cpu.accumulator = 0x77;
+        });
+        assert_eq!(cpu.bus.peek(0x0305), 0x77);
+    }
+
+    #[test]
+    fn transfers_between_registers() {
+        // TAY; INY; TYA; TXA
+        let cpu = run_program(vec![0xa8, 0xc8, 0x98, 0x00], |cpu| cpu.accumulator = 0x41);
+        assert_eq!(cpu.reg_y, 0x42);
+        assert_eq!(cpu.accumulator, 0x42);
+
+        let cpu = run_program(vec![0x8a, 0x00], |cpu| cpu.reg_x = 0x00);
+        assert!(cpu.flag_zero());
+    }
+
+    #[test]
+    fn decrements_registers() {
+        let cpu = run_program(vec![0xca, 0x88, 0x00], |cpu| cpu.reg_y = 0x01);
+        assert_eq!(cpu.reg_x, 0xff);
+        assert_eq!(cpu.reg_y, 0x00);
+        assert!(cpu.flag_zero());
+    }
+
+    #[test]
+    fn sets_and_clears_flags() {
+        let cpu = run_program(vec![0x38, 0xf8, 0x78, 0x00], |_| {});
+        assert_eq!(cpu.proc_status, CARRY | DECIMAL | NO_INTERRUPT);
+
+        let cpu = run_program(vec![0x18, 0xd8, 0x58, 0xb8, 0x00], |cpu| {
+            cpu.proc_status = 0xff
+        });
+        assert_eq!(
+            cpu.proc_status,
+            !(CARRY | DECIMAL | NO_INTERRUPT | OVERFLOW)
+        );
+    }
+
+    #[test]
+    fn jmp_absolute() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x4c, 0x34, 0x12, 0x00]).unwrap();
+        cpu.reset();
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x1234);
+    }
+
+    #[test]
+    fn jmp_indirect_wraps_within_page() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x6c, 0xff, 0x02, 0x00]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x02ff, 0x34);
+        cpu.bus.poke(0x0200, 0x12);
+        cpu.bus.poke(0x0300, 0x99);
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x1234);
+    }
+
+    #[test]
+    fn nop_does_nothing() {
+        let cpu = run_program(vec![0xea, 0x00], |_| {});
+        assert_eq!(cpu.registers().accumulator, 0);
+        assert_eq!(cpu.prog_counter, 0x8001);
+    }
+
+    #[test]
+    fn pushes_and_pulls_accumulator() {
+        // PHA; LDA #$00; PLA
+        let cpu = run_program(vec![0x48, 0xa9, 0x00, 0x68, 0x00], |cpu| {
+            cpu.accumulator = 0x80
+        });
+        assert_eq!(cpu.accumulator, 0x80);
+        assert!(cpu.flag_neg());
+        assert_eq!(cpu.stack_pointer, 0xfd);
+        assert_eq!(cpu.bus.peek(0x01fd), 0x80);
+    }
+
+    #[test]
+    fn php_pushes_break_and_unused_bits() {
+        let cpu = run_program(vec![0x08, 0x00], |cpu| cpu.proc_status = CARRY);
+        assert_eq!(cpu.bus.peek(0x01fd), CARRY | BREAK | UNUSED);
+        assert_eq!(cpu.stack_pointer, 0xfc);
+    }
+
+    #[test]
+    fn plp_ignores_break_bit() {
+        // LDA #$FF; PHA; PLP
+        let cpu = run_program(vec![0xa9, 0xff, 0x48, 0x28, 0x00], |_| {});
+        assert_eq!(cpu.proc_status, !BREAK);
+    }
+
+    #[test]
+    fn jsr_and_rts() {
+        // JSR $8005; BRK; INX; INX; RTS
+        let cpu = run_program(vec![0x20, 0x05, 0x80, 0x00, 0x00, 0xe8, 0xe8, 0x60], |_| {});
+        assert_eq!(cpu.reg_x, 2);
+        assert_eq!(cpu.prog_counter, 0x8003);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+        // return address is the last byte of the JSR instruction
+        assert_eq!(cpu.bus.peek(0x01fd), 0x80);
+        assert_eq!(cpu.bus.peek(0x01fc), 0x02);
+    }
+
+    #[test]
+    fn transfers_stack_pointer() {
+        // TSX; INX; TXS
+        let cpu = run_program(vec![0xba, 0xe8, 0x9a, 0x00], |_| {});
+        assert_eq!(cpu.reg_x, 0xfe);
+        assert_eq!(cpu.stack_pointer, 0xfe);
+    }
+
+    #[test]
+    fn brk_pushes_state_and_jumps_through_irq_vector() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x00, 0xff, 0xe8]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0xfffe, 0x00);
+        cpu.bus.poke(0xffff, 0x90);
+        cpu.proc_status = CARRY;
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x9000);
+        assert_eq!(cpu.stack_pointer, 0xfa);
+        assert_eq!(cpu.bus.peek(0x01fd), 0x80);
+        assert_eq!(cpu.bus.peek(0x01fc), 0x02);
+        assert_eq!(cpu.bus.peek(0x01fb), CARRY | BREAK | UNUSED);
+        assert_eq!(cpu.proc_status, CARRY | NO_INTERRUPT);
+    }
+
+    #[test]
+    fn rti_returns_from_brk() {
+        let mut cpu = CPU::new();
+        // BRK; padding; INX, with the handler at $9000 doing INY; RTI
+        cpu.load_program(vec![0x00, 0xff, 0xe8]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0xfffe, 0x00);
+        cpu.bus.poke(0xffff, 0x90);
+        cpu.bus.poke(0x9000, 0xc8);
+        cpu.bus.poke(0x9001, 0x40);
+        cpu.proc_status = CARRY;
+        for _ in 0..4 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.reg_y, 1);
+        assert_eq!(cpu.reg_x, 1);
+        assert_eq!(cpu.prog_counter, 0x8003);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+        assert_eq!(cpu.proc_status, CARRY | UNUSED);
+    }
+
+    fn run_illegal(program: Vec<u8>, setup: impl FnOnce(&mut CPU)) -> CPU {
+        run_program(program, |cpu| {
+            cpu.set_illegal_opcodes(true);
+            setup(cpu);
+        })
+    }
+
+    #[test]
+    fn lax_loads_a_and_x() {
+        let cpu = run_illegal(vec![0xa7, 0x10, 0x00], |cpu| cpu.bus.poke(0x10, 0x80));
+        assert_eq!(cpu.accumulator, 0x80);
+        assert_eq!(cpu.reg_x, 0x80);
+        assert!(cpu.flag_neg());
+    }
+
+    #[test]
+    fn sax_stores_a_and_x() {
+        let cpu = run_illegal(vec![0x87, 0x10, 0x00], |cpu| {
+            cpu.accumulator = 0b1100;
+            cpu.reg_x = 0b1010;
+            cpu.proc_status = ZERO;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0b1000);
+        // flags are left alone
+        assert!(cpu.flag_zero());
+    }
+
+    #[test]
+    fn dcp_decrements_then_compares() {
+        let cpu = run_illegal(vec![0xc7, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x10, 0x06);
+            cpu.accumulator = 0x05;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0x05);
+        assert!(cpu.flag_zero());
+        assert!(cpu.flag_carry());
+    }
+
+    #[test]
+    fn isb_increments_then_subtracts() {
+        let cpu = run_illegal(vec![0x38, 0xe7, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x10, 0x01);
+            cpu.accumulator = 0x05;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0x02);
+        assert_eq!(cpu.accumulator, 0x03);
+        assert!(cpu.flag_carry());
+    }
+
+    #[test]
+    fn shift_and_combine_with_accumulator() {
+        // SLO $10
+        let cpu = run_illegal(vec![0x07, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x10, 0b1000_0001);
+            cpu.accumulator = 0b0000_0001;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0b0000_0010);
+        assert_eq!(cpu.accumulator, 0b0000_0011);
+        assert!(cpu.flag_carry());
+
+        // RLA $10
+        let cpu = run_illegal(vec![0x27, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x10, 0b0100_0000);
+            cpu.accumulator = 0xff;
+            cpu.proc_status = CARRY;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0b1000_0001);
+        assert_eq!(cpu.accumulator, 0b1000_0001);
+        assert!(!cpu.flag_carry());
+
+        // SRE $10
+        let cpu = run_illegal(vec![0x47, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x10, 0b0000_0011);
+            cpu.accumulator = 0b0000_0001;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0b0000_0001);
+        assert_eq!(cpu.accumulator, 0);
+        assert!(cpu.flag_zero());
+        assert!(cpu.flag_carry());
+
+        // RRA $10 adds the carry shifted out of memory
+        let cpu = run_illegal(vec![0x67, 0x10, 0x00], |cpu| {
+            cpu.bus.poke(0x10, 0b0000_0011);
+            cpu.accumulator = 0x10;
+        });
+        assert_eq!(cpu.bus.peek(0x10), 0b0000_0001);
+        assert_eq!(cpu.accumulator, 0x12);
+    }
+
+    #[test]
+    fn multi_byte_nops_skip_operands() {
+        // NOP #$ff; NOP $10,X; NOP $1234,X; NOP
+        let cpu = run_illegal(
+            vec![0x80, 0xff, 0x14, 0x10, 0x1c, 0x34, 0x12, 0x1a, 0x00],
+            |_| {},
+        );
+        assert_eq!(cpu.prog_counter, 0x8008);
+        assert_eq!(cpu.registers().accumulator, 0);
+    }
+}
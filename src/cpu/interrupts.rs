@@ -0,0 +1,274 @@
+use super::{BREAK, CPU, IRQ_VECTOR, NMI_VECTOR, NO_INTERRUPT, UNUSED};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+impl CPU {
+    // edge triggered: serviced once before the next instruction
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // stays pending until it is serviced, which waits for I to be clear
+    pub fn trigger_irq(&mut self) {
+        self.irq_pending = true;
+    }
+
+    pub fn clear_irq(&mut self) {
+        self.irq_pending = false;
+    }
+
+    // the 6502 polls for interrupts before the last cycle of an instruction,
+    // so CLI, SEI and PLP only affect IRQs after the following instruction,
+    // and a taken branch that stays on its page does not poll at all
+    pub(super) fn poll_interrupts(&mut self) -> Option<Interrupt> {
+        let irq_inhibit = self
+            .delayed_irq_inhibit
+            .take()
+            .unwrap_or(self.proc_status & NO_INTERRUPT != 0);
+        if std::mem::take(&mut self.skip_interrupt_poll) {
+            return None;
+        }
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.interrupt(NMI_VECTOR);
+            Some(Interrupt::Nmi)
+        } else if (self.irq_pending || self.bus.irq()) && !irq_inhibit {
+            // a mapper holds its line until the handler acknowledges it
+            self.irq_pending = false;
+            self.interrupt(IRQ_VECTOR);
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
+    }
+
+    // hardware interrupts push P with B clear, unlike BRK and PHP. Like
+    // BRK they read the next opcode twice, push PCH, PCL and P, set I and
+    // fetch the vector, one bus access per cycle
+    fn interrupt(&mut self, vector: u16) {
+        self.mem_read(self.prog_counter);
+        self.mem_read(self.prog_counter);
+        self.stack_push_u16(self.prog_counter);
+        self.stack_push((self.proc_status & !BREAK) | UNUSED);
+        self.proc_status |= NO_INTERRUPT;
+        self.extra_cycles += 7;
+        self.prog_counter = self.mem_read_u16(vector);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cpu::{BusAccess, BusAccessKind, CARRY, DECIMAL, NEGATIVE, OVERFLOW};
+
+    fn cpu_with_handlers() -> CPU {
+        let mut cpu = CPU::new();
+        // INX; INX; BRK
+        cpu.load_program(vec![0xe8, 0xe8, 0x00]).unwrap();
+        cpu.reset();
+        // NMI handler at $9000: INY; RTI
+        cpu.bus.poke(0xfffa, 0x00);
+        cpu.bus.poke(0xfffb, 0x90);
+        cpu.bus.poke(0x9000, 0xc8);
+        cpu.bus.poke(0x9001, 0x40);
+        // IRQ handler at $a000: DEY; RTI
+        cpu.bus.poke(0xfffe, 0x00);
+        cpu.bus.poke(0xffff, 0xa0);
+        cpu.bus.poke(0xa000, 0x88);
+        cpu.bus.poke(0xa001, 0x40);
+        cpu
+    }
+
+    #[test]
+    fn services_nmi_before_next_instruction() {
+        let mut cpu = cpu_with_handlers();
+        cpu.proc_status = CARRY;
+        cpu.step().unwrap();
+        cpu.trigger_nmi();
+        let info = cpu.step().unwrap();
+        assert_eq!(info.interrupt, Some(Interrupt::Nmi));
+        assert_eq!(info.pc, 0x9000);
+        // 7 cycles for the interrupt sequence plus the handler's INY
+        assert_eq!(info.cycles, 9);
+        assert_eq!(cpu.reg_y, 1);
+        assert_eq!(cpu.bus.peek(0x01fd), 0x80);
+        assert_eq!(cpu.bus.peek(0x01fc), 0x01);
+        assert_eq!(cpu.bus.peek(0x01fb), CARRY | UNUSED);
+        assert!(cpu.proc_status & NO_INTERRUPT != 0);
+
+        // RTI, then the interrupted INX
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_x, 2);
+    }
+
+    #[test]
+    fn nmi_ignores_interrupt_disable() {
+        let mut cpu = cpu_with_handlers();
+        cpu.proc_status = NO_INTERRUPT;
+        cpu.trigger_nmi();
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Nmi));
+    }
+
+    #[test]
+    fn irq_waits_for_interrupt_disable_to_clear() {
+        let mut cpu = cpu_with_handlers();
+        cpu.proc_status = NO_INTERRUPT;
+        cpu.trigger_irq();
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        assert_eq!(cpu.reg_x, 1);
+
+        cpu.proc_status = 0;
+        let info = cpu.step().unwrap();
+        assert_eq!(info.interrupt, Some(Interrupt::Irq));
+        assert_eq!(cpu.reg_y, 0xff);
+        assert_eq!(cpu.bus.peek(0x01fb) & BREAK, 0);
+    }
+
+    #[test]
+    fn cleared_irq_is_not_serviced() {
+        let mut cpu = cpu_with_handlers();
+        cpu.trigger_irq();
+        cpu.clear_irq();
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+    }
+
+    #[test]
+    fn cli_delays_irq_by_one_instruction() {
+        let mut cpu = cpu_with_handlers();
+        // CLI; INX; INX
+        cpu.bus.poke(0x8000, 0x58);
+        cpu.proc_status = NO_INTERRUPT;
+        cpu.trigger_irq();
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        assert_eq!(cpu.reg_x, 1);
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Irq));
+    }
+
+    #[test]
+    fn irq_slips_through_right_after_sei() {
+        let mut cpu = cpu_with_handlers();
+        // SEI; INX
+        cpu.bus.poke(0x8000, 0x78);
+        cpu.step().unwrap();
+        cpu.trigger_irq();
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Irq));
+        assert_eq!(cpu.reg_x, 0);
+    }
+
+    #[test]
+    fn taken_branch_on_same_page_delays_interrupt() {
+        let mut cpu = cpu_with_handlers();
+        // BNE +0; INX
+        cpu.bus.poke(0x8000, 0xd0);
+        cpu.bus.poke(0x8001, 0x00);
+        cpu.step().unwrap();
+        cpu.trigger_nmi();
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Nmi));
+    }
+
+    #[test]
+    fn nmi_takes_priority_over_irq() {
+        let mut cpu = cpu_with_handlers();
+        cpu.trigger_irq();
+        cpu.trigger_nmi();
+        assert_eq!(cpu.step().unwrap().interrupt, Some(Interrupt::Nmi));
+        // the IRQ is still pending but I is now set by the NMI entry
+        assert_eq!(cpu.step().unwrap().interrupt, None);
+    }
+
+    fn access(addr: u16, value: u8, kind: BusAccessKind) -> BusAccess {
+        BusAccess { addr, value, kind }
+    }
+
+    // the seven bus accesses of an entry sequence, from the interrupted
+    // state of cpu_with_handlers after one INX
+    fn entry_sequence(status: u8, vector: u16, handler: u16) -> Vec<BusAccess> {
+        use BusAccessKind::{Read, Write};
+        vec![
+            access(0x8001, 0xe8, Read),
+            access(0x8001, 0xe8, Read),
+            access(0x01fd, 0x80, Write),
+            access(0x01fc, 0x01, Write),
+            access(0x01fb, status, Write),
+            access(vector, 0x00, Read),
+            access(vector + 1, (handler >> 8) as u8, Read),
+        ]
+    }
+
+    #[test]
+    fn nmi_entry_pushes_pc_then_status_without_break() {
+        let mut cpu = cpu_with_handlers();
+        cpu.proc_status = CARRY | DECIMAL;
+        cpu.step().unwrap();
+        cpu.set_bus_recording(true);
+        cpu.trigger_nmi();
+        let info = cpu.step().unwrap();
+        assert_eq!(
+            info.bus_activity[..7],
+            entry_sequence(CARRY | DECIMAL | UNUSED, NMI_VECTOR, 0x9000)[..]
+        );
+        // I is set only after P went onto the stack, and D is left alone
+        assert_eq!(cpu.proc_status, CARRY | DECIMAL | NO_INTERRUPT);
+    }
+
+    #[test]
+    fn irq_entry_pushes_pc_then_status_without_break() {
+        let mut cpu = cpu_with_handlers();
+        cpu.proc_status = OVERFLOW;
+        cpu.step().unwrap();
+        cpu.set_bus_recording(true);
+        cpu.trigger_irq();
+        let info = cpu.step().unwrap();
+        assert_eq!(
+            info.bus_activity[..7],
+            entry_sequence(OVERFLOW | UNUSED, IRQ_VECTOR, 0xa000)[..]
+        );
+        // the handler's DEY has set N by now
+        assert_eq!(cpu.proc_status, OVERFLOW | NO_INTERRUPT | NEGATIVE);
+    }
+
+    #[test]
+    fn brk_skips_padding_byte_and_pushes_break() {
+        use BusAccessKind::{Read, Write};
+        let mut cpu = cpu_with_handlers();
+        cpu.step().unwrap();
+        cpu.step().unwrap();
+        cpu.proc_status = CARRY | DECIMAL;
+        cpu.set_bus_recording(true);
+        let info = cpu.step().unwrap();
+        assert_eq!(
+            info.bus_activity,
+            vec![
+                access(0x8002, 0x00, Read),
+                access(0x8003, 0x00, Read),
+                access(0x01fd, 0x80, Write),
+                access(0x01fc, 0x04, Write),
+                access(0x01fb, CARRY | DECIMAL | BREAK | UNUSED, Write),
+                access(0xfffe, 0x00, Read),
+                access(0xffff, 0xa0, Read),
+            ]
+        );
+        assert_eq!(cpu.prog_counter, 0xa000);
+        assert_eq!(cpu.proc_status, CARRY | DECIMAL | NO_INTERRUPT);
+    }
+
+    #[test]
+    fn interrupt_pushes_wrap_within_stack_page() {
+        let mut cpu = cpu_with_handlers();
+        cpu.stack_pointer = 0x01;
+        cpu.trigger_nmi();
+        cpu.step().unwrap();
+        assert_eq!(cpu.bus.peek(0x0101), 0x80);
+        assert_eq!(cpu.bus.peek(0x0100), 0x00);
+        assert_eq!(cpu.bus.peek(0x01ff), UNUSED);
+        assert_eq!(cpu.stack_pointer, 0xfe);
+    }
+}
@@ -0,0 +1,689 @@
+use std::time::Instant;
+
+use crate::cartridge::{Mirroring, Rom, RomError};
+use crate::mapper::{self, Chr, Nrom};
+use crate::trace::TraceEntry;
+
+use super::opcodes::Mnemonic::*;
+use super::opcodes::{OpCode, OPCODES};
+use super::{CpuError, RunExit, RunLimits, StepInfo, CPU, RESET_VECTOR, STACK_RESET};
+
+const PRG_ROM_SIZE: usize = 0x8000;
+const TRAINER: u16 = 0x7000;
+
+impl CPU {
+    pub fn reset(&mut self) {
+        self.accumulator = 0;
+        self.reg_x = 0;
+        self.reg_y = 0;
+        self.stack_pointer = STACK_RESET;
+        self.proc_status = 0;
+        self.nmi_pending = false;
+        self.irq_pending = false;
+        self.delayed_irq_inhibit = None;
+        self.skip_interrupt_poll = false;
+        self.prog_counter = self.mem_read_u16(RESET_VECTOR);
+    }
+
+    pub fn load(&mut self, mut rom: Rom) -> Result<(), RomError> {
+        let trainer = rom.trainer.take();
+        let battery = rom.header().battery;
+        self.bus.set_mapper(mapper::for_rom(rom)?);
+        self.bus.set_battery(battery);
+        if let Some(trainer) = trainer {
+            for (i, byte) in trainer.into_iter().enumerate() {
+                self.bus.poke(TRAINER + i as u16, byte);
+            }
+        }
+        Ok(())
+    }
+
+    // loads a bare program at 0x8000 and points the reset vector at it
+    pub fn load_program(&mut self, program: Vec<u8>) -> Result<(), RomError> {
+        if program.len() > PRG_ROM_SIZE {
+            return Err(RomError::TooLarge {
+                size: program.len(),
+                max: PRG_ROM_SIZE,
+            });
+        }
+        let mut prg_rom = program;
+        prg_rom.resize(PRG_ROM_SIZE, 0);
+        self.bus.set_mapper(Box::new(Nrom::new(
+            prg_rom,
+            Chr::new(Vec::new(), 0),
+            Mirroring::Horizontal,
+        )));
+        self.bus.poke(RESET_VECTOR, 0x00);
+        self.bus.poke(RESET_VECTOR + 1, 0x80);
+        Ok(())
+    }
+
+    // runs until a BRK instruction has been executed
+    pub fn run(&mut self) -> Result<(), CpuError> {
+        self.run_with_callback(|_| {})
+    }
+
+    // the callback runs before every instruction
+    pub fn run_with_callback<F: FnMut(&mut CPU)>(&mut self, callback: F) -> Result<(), CpuError> {
+        let limits = RunLimits {
+            stop_on_brk: true,
+            ..RunLimits::default()
+        };
+        self.run_until(limits, callback).map(|_| ())
+    }
+
+    pub fn run_with_limits(&mut self, limits: RunLimits) -> Result<RunExit, CpuError> {
+        self.run_until(limits, |_| {})
+    }
+
+    fn run_until<F: FnMut(&mut CPU)>(
+        &mut self,
+        limits: RunLimits,
+        mut callback: F,
+    ) -> Result<RunExit, CpuError> {
+        let started = Instant::now();
+        let mut instructions = 0;
+        let mut cycles = 0;
+        loop {
+            callback(self);
+            let info = self.step()?;
+            if let Some(access) = info.breakpoint {
+                return Ok(RunExit::Breakpoint(access));
+            }
+            if info.opcode == 0x00 && limits.stop_on_brk {
+                return Ok(RunExit::Brk);
+            }
+            instructions += 1;
+            cycles += info.cycles as u64;
+            if limits
+                .max_instructions
+                .is_some_and(|max| instructions >= max)
+                || limits.max_cycles.is_some_and(|max| cycles >= max)
+                || limits.max_time.is_some_and(|max| started.elapsed() >= max)
+            {
+                return Ok(RunExit::LimitReached);
+            }
+        }
+    }
+
+    pub fn step(&mut self) -> Result<StepInfo, CpuError> {
+        self.bus_activity.clear();
+        self.breakpoint_hit = None;
+        self.extra_cycles = 0;
+        let interrupt = self.poll_interrupts();
+        let pc = self.prog_counter;
+        let registers = self.registers();
+        let opcode = self.mem_read(self.prog_counter);
+        self.prog_counter = self.prog_counter.wrapping_add(1);
+        self.opcode_counts[opcode as usize] += 1;
+        self.recent.push(TraceEntry {
+            pc,
+            opcode,
+            registers,
+        });
+        let mut skipped = false;
+        let op = match OPCODES[opcode as usize] {
+            Some(op) if !op.illegal || self.illegal_opcodes => op,
+            _ if self.skip_unknown_opcodes => {
+                skipped = true;
+                self.skip_unknown_opcode(opcode)
+            }
+            _ => return Err(self.crash(CpuError::UnknownOpcode { opcode, pc })),
+        };
+        let mode = op.mode;
+        match op.mnemonic {
+            Adc => self.adc(mode),
+            Sbc => self.sbc(mode),
+            And => self.and(mode),
+            Ora => self.ora(mode),
+            Eor => self.eor(mode),
+            Cmp => self.cmp(mode),
+            Cpx => self.cpx(mode),
+            Cpy => self.cpy(mode),
+            Bit => self.bit(mode),
+
+            Lda => self.lda(mode),
+            Ldx => self.ldx(mode),
+            Ldy => self.ldy(mode),
+            Sta => self.sta(mode),
+            Stx => self.stx(mode),
+            Sty => self.sty(mode),
+
+            Asl => self.asl(mode),
+            Lsr => self.lsr(mode),
+            Rol => self.rol(mode),
+            Ror => self.ror(mode),
+            Inc => self.inc(mode),
+            Dec => self.dec(mode),
+
+            Inx => self.inx(),
+            Iny => self.iny(),
+            Dex => self.dex(),
+            Dey => self.dey(),
+            Tax => self.tax(),
+            Tay => self.tay(),
+            Txa => self.txa(),
+            Tya => self.tya(),
+            Tsx => self.tsx(),
+            Txs => self.txs(),
+
+            Clc => self.clc(),
+            Cld => self.cld(),
+            Cli => self.cli(),
+            Clv => self.clv(),
+            Sec => self.sec(),
+            Sed => self.sed(),
+            Sei => self.sei(),
+            Nop => self.nop(mode),
+
+            Pha => self.pha(),
+            Php => self.php(),
+            Pla => self.pla(),
+            Plp => self.plp(),
+
+            Jmp => self.jmp(mode),
+            Jsr => self.jsr(),
+            Rts => self.rts(),
+            Brk => self.brk(),
+            Rti => self.rti(),
+
+            // the condition comes from the table, see OpCode::branch
+            Bpl | Bmi | Bvc | Bvs | Bcc | Bcs | Bne | Beq => {
+                if let Some((flag, expected)) = op.branch {
+                    self.branch(flag, expected);
+                }
+            }
+
+            Lax => self.lax(mode),
+            Sax => self.sax(mode),
+            Dcp => self.dcp(mode),
+            Isb => self.isb(mode),
+            Slo => self.slo(mode),
+            Rla => self.rla(mode),
+            Sre => self.sre(mode),
+            Rra => self.rra(mode),
+        }
+        if !op.sets_pc() {
+            self.prog_counter = self.prog_counter.wrapping_add(op.len as u16 - 1);
+        }
+        let mut cycles = op.cycles as u16 + self.extra_cycles as u16;
+        // an OAM DMA started by the instruction halts the CPU after it
+        cycles += self.bus.take_dma_cycles(self.total_cycles + cycles as u64);
+        self.total_cycles += cycles as u64;
+        self.bus.tick(cycles);
+        if self.bus.take_nmi() {
+            self.nmi_pending = true;
+        }
+        Ok(StepInfo {
+            pc,
+            opcode,
+            bytes: op.len,
+            cycles,
+            bus_activity: std::mem::take(&mut self.bus_activity),
+            breakpoint: self.breakpoint_hit.take(),
+            interrupt,
+            skipped,
+        })
+    }
+
+    // stands in a 1-byte NOP for an opcode the CPU cannot run, the caller
+    // sees it through StepInfo::skipped and skipped_opcodes()
+    fn skip_unknown_opcode(&mut self, opcode: u8) -> OpCode {
+        self.skipped_opcodes += 1;
+        OpCode {
+            code: opcode,
+            illegal: false,
+            ..OPCODES[0xea].unwrap()
+        }
+    }
+
+    fn crash(&mut self, error: CpuError) -> CpuError {
+        if let CpuError::UnknownOpcode { opcode, pc } = error {
+            // leave the PC on the offending instruction
+            self.prog_counter = pc;
+            if let Some(dir) = &self.crash_dump_dir {
+                let written = self.crash_dump(&error.to_string(), opcode).write_to(dir);
+                self.crash_dump_written = Some(written);
+            }
+        }
+        error
+    }
+
+    pub fn load_and_run(&mut self, program: Vec<u8>) -> Result<(), CpuError> {
+        self.load_program(program)?;
+        self.reset();
+        self.run()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cartridge::{ines, Rom, RomError};
+    use crate::cpu::*;
+    use crate::debug::Breakpoint;
+
+    #[test]
+    fn resets() {
+        let mut cpu = CPU::new();
+        cpu.bus.poke(0xFFFC, 0x00);
+        cpu.bus.poke(0xFFFD, 0x80);
+        cpu.reset();
+        assert_eq!(cpu.accumulator, 0);
+        assert_eq!(cpu.reg_x, 0);
+        assert_eq!(cpu.stack_pointer, 0xfd);
+        assert_eq!(cpu.proc_status, 0);
+        assert_eq!(cpu.prog_counter, 0x8000);
+    }
+
+    #[test]
+    fn loads() {
+        let mut cpu = CPU::new();
+        let program = vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00];
+        let prog_len = program.len() as u16;
+        cpu.load_program(program).unwrap();
+        assert_eq!(
+            (0x8000..(0x8000 + prog_len))
+                .map(|addr| cpu.bus.peek(addr))
+                .collect::<Vec<_>>(),
+            vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00]
+        )
+    }
+
+    #[test]
+    fn loads_program_filling_prg_rom() {
+        let mut cpu = CPU::new();
+        assert_eq!(cpu.load_program(vec![0xea; 0x8000]), Ok(()));
+        assert_eq!(cpu.bus.peek(0xFFFB), 0xea);
+    }
+
+    #[test]
+    fn rejects_oversized_program() {
+        let mut cpu = CPU::new();
+        assert_eq!(
+            cpu.load_program(vec![0xea; 0x8001]),
+            Err(RomError::TooLarge {
+                size: 0x8001,
+                max: 0x8000
+            })
+        );
+        assert_eq!(cpu.bus.peek(0x8000), 0x00);
+    }
+
+    fn nrom(program: &[u8]) -> Rom {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        // reset vector at 0xFFFC, mirrored from 0xBFFC
+        prg_rom[0x3FFC] = 0x00;
+        prg_rom[0x3FFD] = 0xC0;
+        Rom::from_bytes(&ines(0, 0, &prg_rom, &[0; 0x2000])).unwrap()
+    }
+
+    #[test]
+    fn boots_16k_rom_through_its_reset_vector() {
+        let mut cpu = CPU::new();
+        cpu.load(nrom(&[0xe8, 0x00])).unwrap();
+        cpu.reset();
+        assert_eq!(cpu.prog_counter, 0xC000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_x, 1);
+    }
+
+    #[test]
+    fn wraps_program_counter_past_0xffff() {
+        let mut cpu = CPU::new();
+        let mut rom = nrom(&[]);
+        rom.prg_rom[0x3FFF] = 0xea;
+        cpu.load(rom).unwrap();
+        cpu.prog_counter = 0xFFFF;
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x0000);
+    }
+
+    #[test]
+    fn copies_trainer_to_prg_ram() {
+        let mut cpu = CPU::new();
+        // flags 6 bit 2 puts a trainer between the header and PRG ROM
+        let mut raw = ines(0b0100, 0, &[0; 0x4000], &[0; 0x2000]);
+        raw.splice(16..16, [0x5a; 512]);
+        cpu.load(Rom::from_bytes(&raw).unwrap()).unwrap();
+        assert_eq!(cpu.bus.peek(0x7000), 0x5a);
+        assert_eq!(cpu.bus.peek(0x71FF), 0x5a);
+        assert_eq!(cpu.bus.peek(0x7200), 0x00);
+    }
+
+    #[test]
+    fn rejects_unsupported_mapper() {
+        let mut cpu = CPU::new();
+        // mapper 4 in the upper nibble of flags 6
+        let rom = Rom::from_bytes(&ines(0x40, 0, &[0; 0x4000], &[0; 0x2000])).unwrap();
+        assert_eq!(
+            cpu.load(rom),
+            Err(RomError::UnsupportedMapper {
+                mapper: 4,
+                name: Some("MMC3"),
+                supported: &crate::mapper::SUPPORTED,
+            })
+        );
+    }
+
+    #[test]
+    fn load_and_run_reports_oversized_program() {
+        let mut cpu = CPU::new();
+        assert_eq!(
+            cpu.load_and_run(vec![0xea; 0x8001]),
+            Err(CpuError::Rom(RomError::TooLarge {
+                size: 0x8001,
+                max: 0x8000
+            }))
+        );
+    }
+
+    #[test]
+    fn step_executes_one_instruction() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xa9, 0xc0, 0xaa, 0x00]).unwrap();
+        cpu.reset();
+        let info = cpu.step().unwrap();
+        assert_eq!(info.pc, 0x8000);
+        assert_eq!(info.opcode, 0xa9);
+        assert_eq!((info.bytes, info.cycles), (2, 2));
+        assert_eq!(cpu.accumulator, 0xc0);
+        assert_eq!(cpu.reg_x, 0);
+        assert_eq!(cpu.prog_counter, 0x8002);
+    }
+
+    #[test]
+    fn counts_page_cross_penalty_on_indexed_reads() {
+        // LDA $02FF,X; STA $02FF,X
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xbd, 0xff, 0x02, 0x9d, 0xff, 0x02, 0x00])
+            .unwrap();
+        cpu.reset();
+        cpu.reg_x = 1;
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        // stores always take the fixed-up cycle, so there is no penalty
+        assert_eq!(cpu.step().unwrap().cycles, 5);
+        assert_eq!(cpu.cycles(), 10);
+    }
+
+    #[test]
+    fn stalls_for_oam_dma() {
+        // STA $4014 twice, the second DMA starts on an odd cycle
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x8d, 0x14, 0x40, 0x8d, 0x14, 0x40, 0x00])
+            .unwrap();
+        cpu.reset();
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 513);
+        assert_eq!(cpu.step().unwrap().cycles, 4 + 514);
+    }
+
+    #[test]
+    fn counts_branch_cycles() {
+        let cycles = |program: Vec<u8>| {
+            let mut cpu = CPU::new();
+            cpu.load_program(program).unwrap();
+            cpu.reset();
+            cpu.step().unwrap().cycles
+        };
+        // with Z clear BEQ falls through and BNE is taken
+        assert_eq!(cycles(vec![0xf0, 0x02, 0x00]), 2);
+        assert_eq!(cycles(vec![0xd0, 0x02, 0x00]), 3);
+        // taken backwards across the page boundary at $8000
+        assert_eq!(cycles(vec![0xd0, 0x80, 0x00]), 4);
+    }
+
+    #[test]
+    fn step_skips_bus_activity_by_default() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xa9, 0xc0, 0x00]).unwrap();
+        cpu.reset();
+        assert!(cpu.step().unwrap().bus_activity.is_empty());
+    }
+
+    #[test]
+    fn step_records_bus_activity() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xad, 0x10, 0x00, 0x00]).unwrap();
+        cpu.reset();
+        cpu.bus.poke(0x0010, 0x42);
+        cpu.set_bus_recording(true);
+        let info = cpu.step().unwrap();
+        assert_eq!(
+            info.bus_activity,
+            vec![
+                BusAccess {
+                    addr: 0x8000,
+                    value: 0xad,
+                    kind: BusAccessKind::Read
+                },
+                BusAccess {
+                    addr: 0x8001,
+                    value: 0x10,
+                    kind: BusAccessKind::Read
+                },
+                BusAccess {
+                    addr: 0x8002,
+                    value: 0x00,
+                    kind: BusAccessKind::Read
+                },
+                BusAccess {
+                    addr: 0x0010,
+                    value: 0x42,
+                    kind: BusAccessKind::Read
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unknown_opcode() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xe8, 0x02, 0x00]).unwrap();
+        cpu.reset();
+        assert_eq!(
+            cpu.run(),
+            Err(CpuError::UnknownOpcode {
+                opcode: 0x02,
+                pc: 0x8001
+            })
+        );
+        assert_eq!(cpu.prog_counter, 0x8001);
+        assert_eq!(cpu.reg_x, 1);
+    }
+
+    #[test]
+    fn skips_unknown_opcodes_when_asked() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xe8, 0x02, 0x12, 0xe8, 0x00])
+            .unwrap();
+        cpu.reset();
+        cpu.set_skip_unknown_opcodes(true);
+        assert!(!cpu.step().unwrap().skipped);
+        assert!(cpu.step().unwrap().skipped);
+        cpu.run().unwrap();
+        assert_eq!(cpu.reg_x, 2);
+        assert_eq!(cpu.skipped_opcodes(), 2);
+    }
+
+    #[test]
+    fn illegal_opcodes_need_to_be_enabled() {
+        // LAX $10
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xa7, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        assert_eq!(
+            cpu.step(),
+            Err(CpuError::UnknownOpcode {
+                opcode: 0xa7,
+                pc: 0x8000
+            })
+        );
+        assert_eq!(cpu.prog_counter, 0x8000);
+
+        cpu.set_illegal_opcodes(true);
+        cpu.step().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8002);
+    }
+
+    #[test]
+    fn writes_crash_dump_on_unknown_opcode() {
+        let dir = std::env::temp_dir().join(format!("nes-cpu-crash-{}", std::process::id()));
+        let mut cpu = CPU::new();
+        cpu.set_crash_dump_dir(Some(dir.clone()));
+        cpu.load_program(vec![0x02]).unwrap();
+        cpu.reset();
+        assert!(cpu.run().is_err());
+        let path = cpu.take_crash_dump_result().unwrap().unwrap();
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        assert_eq!(std::fs::read(path.join("ram.bin")).unwrap().len(), 0x10000);
+        assert_eq!(
+            std::fs::read_to_string(path.join("trace.txt")).unwrap(),
+            "8000  02  A:00 X:00 Y:00 P:00 SP:FD\n"
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reports_crash_dump_write_failure() {
+        // a plain file where the dump directory should be
+        let file = std::env::temp_dir().join(format!("nes-cpu-crash-file-{}", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let mut cpu = CPU::new();
+        cpu.set_crash_dump_dir(Some(file.clone()));
+        cpu.load_program(vec![0x02]).unwrap();
+        cpu.reset();
+        assert!(cpu.run().is_err());
+        assert!(cpu.take_crash_dump_result().unwrap().is_err());
+        assert!(cpu.take_crash_dump_result().is_none());
+        std::fs::remove_file(file).unwrap();
+    }
+
+    #[test]
+    fn keeps_recent_instructions() {
+        let mut cpu = CPU::new();
+        cpu.set_recent_instructions_capacity(2);
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00])
+            .unwrap();
+        let recent = cpu.recent_instructions();
+        assert_eq!(recent.len(), 2);
+        assert_eq!((recent[0].pc, recent[0].opcode), (0x8003, 0xe8));
+        assert_eq!(recent[0].registers.reg_x, 0xc0);
+        assert_eq!((recent[1].pc, recent[1].opcode), (0x8004, 0x00));
+    }
+
+    #[test]
+    fn counts_executed_opcodes() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0xc0, 0xaa, 0xe8, 0xe8, 0xe8, 0x00])
+            .unwrap();
+        assert_eq!(
+            cpu.opcode_stats(),
+            vec![(0xe8, 3), (0x00, 1), (0xa9, 1), (0xaa, 1)]
+        );
+        cpu.reset_opcode_stats();
+        assert!(cpu.opcode_stats().is_empty());
+    }
+
+    #[test]
+    fn breaks_on_register_read() {
+        let mut cpu = CPU::new();
+        // LDA $2002; LDA #$01; BRK
+        cpu.load_program(vec![0xad, 0x02, 0x20, 0xa9, 0x01, 0x00])
+            .unwrap();
+        cpu.reset();
+        cpu.add_breakpoint(Breakpoint::on_register("PPUSTATUS", BusAccessKind::Read).unwrap());
+        cpu.run().unwrap();
+        assert_eq!(cpu.prog_counter, 0x8003);
+        assert_eq!(cpu.accumulator, 0x00);
+    }
+
+    #[test]
+    fn run_reports_why_it_stopped() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xad, 0x02, 0x20, 0x00]).unwrap();
+        cpu.reset();
+        cpu.add_breakpoint(Breakpoint::on_register("PPUSTATUS", BusAccessKind::Read).unwrap());
+        let exit = cpu.run_with_limits(RunLimits::default()).unwrap();
+        assert!(matches!(exit, RunExit::Breakpoint(access) if access.addr == 0x2002));
+        let limits = RunLimits {
+            stop_on_brk: true,
+            ..Default::default()
+        };
+        assert_eq!(cpu.run_with_limits(limits), Ok(RunExit::Brk));
+    }
+
+    #[test]
+    fn runs_through_brk_unless_asked_to_stop() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0x00]).unwrap();
+        cpu.reset();
+        let limits = RunLimits {
+            max_instructions: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(cpu.run_with_limits(limits), Ok(RunExit::LimitReached));
+    }
+
+    #[test]
+    fn calls_back_before_each_instruction() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xe8, 0xe8, 0xe8, 0x00]).unwrap();
+        cpu.reset();
+        let mut seen = Vec::new();
+        cpu.run_with_callback(|cpu| seen.push((cpu.prog_counter, cpu.reg_x)))
+            .unwrap();
+        assert_eq!(
+            seen,
+            vec![(0x8000, 0), (0x8001, 1), (0x8002, 2), (0x8003, 3)]
+        );
+    }
+
+    #[test]
+    fn callback_can_change_state() {
+        let mut cpu = CPU::new();
+        // LDA $10 after the callback has written it
+        cpu.load_program(vec![0xa5, 0x10, 0x00]).unwrap();
+        cpu.reset();
+        cpu.run_with_callback(|cpu| cpu.mem_write(0x10, 0x42))
+            .unwrap();
+        assert_eq!(cpu.accumulator, 0x42);
+    }
+
+    #[test]
+    fn stops_runaway_loops_at_limits() {
+        let mut cpu = CPU::new();
+        // JMP $8000
+        cpu.load_program(vec![0x4c, 0x00, 0x80]).unwrap();
+        cpu.reset();
+
+        let limits = RunLimits {
+            max_instructions: Some(100),
+            ..Default::default()
+        };
+        assert_eq!(cpu.run_with_limits(limits), Ok(RunExit::LimitReached));
+        assert_eq!(cpu.opcode_stats(), vec![(0x4c, 100)]);
+
+        let limits = RunLimits {
+            max_cycles: Some(30),
+            ..Default::default()
+        };
+        let before = cpu.cycles();
+        assert_eq!(cpu.run_with_limits(limits), Ok(RunExit::LimitReached));
+        assert_eq!(cpu.cycles() - before, 30);
+
+        let limits = RunLimits {
+            max_time: Some(std::time::Duration::from_millis(5)),
+            ..Default::default()
+        };
+        assert_eq!(cpu.run_with_limits(limits), Ok(RunExit::LimitReached));
+    }
+
+    #[test]
+    fn ignores_breakpoint_of_other_kind() {
+        let mut cpu = CPU::new();
+        cpu.load_program(vec![0xad, 0x02, 0x20, 0x00]).unwrap();
+        cpu.reset();
+        cpu.add_breakpoint(Breakpoint::on_register("PPUSTATUS", BusAccessKind::Write).unwrap());
+        assert_eq!(cpu.step().unwrap().breakpoint, None);
+    }
+}
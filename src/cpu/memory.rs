@@ -0,0 +1,141 @@
+use crate::bus::Mem;
+
+use super::{BusAccess, BusAccessKind, CPU, STACK};
+
+impl CPU {
+    fn observe(&mut self, access: BusAccess) {
+        if self.record_bus {
+            self.bus_activity.push(access);
+        }
+        if self.breakpoint_hit.is_none()
+            && self
+                .breakpoints
+                .iter()
+                .any(|bp| bp.matches(access.addr, access.kind))
+        {
+            self.breakpoint_hit = Some(access);
+        }
+    }
+
+    pub(super) fn mem_read(&mut self, addr: u16) -> u8 {
+        let value = self.bus.mem_read(addr);
+        self.observe(BusAccess {
+            addr,
+            value,
+            kind: BusAccessKind::Read,
+        });
+        value
+    }
+
+    pub(super) fn mem_write(&mut self, addr: u16, data: u8) {
+        self.observe(BusAccess {
+            addr,
+            value: data,
+            kind: BusAccessKind::Write,
+        });
+        self.bus.mem_write(addr, data);
+    }
+}
+
+impl CPU {
+    // the high byte of a word at 0xFFFF is read from 0x0000
+    pub(super) fn mem_read_u16(&mut self, pos: u16) -> u16 {
+        let low = self.mem_read(pos) as u16;
+        let high = self.mem_read(pos.wrapping_add(1)) as u16;
+        (high << 8) | low
+    }
+
+    // pointers stored in the zero page never leave it: a word at 0xFF
+    // takes its high byte from 0x00
+    pub(super) fn mem_read_u16_zero_page(&mut self, ptr: u8) -> u16 {
+        let low = self.mem_read(ptr as u16) as u16;
+        let high = self.mem_read(ptr.wrapping_add(1) as u16) as u16;
+        (high << 8) | low
+    }
+}
+
+impl CPU {
+    // the stack lives in page one and grows downwards
+    pub(super) fn stack_push(&mut self, data: u8) {
+        self.mem_write(STACK + self.stack_pointer as u16, data);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    }
+
+    pub(super) fn stack_pop(&mut self) -> u8 {
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.mem_read(STACK + self.stack_pointer as u16)
+    }
+
+    pub(super) fn stack_push_u16(&mut self, data: u16) {
+        self.stack_push((data >> 8) as u8);
+        self.stack_push((data & 0xff) as u8);
+    }
+
+    pub(super) fn stack_pop_u16(&mut self) -> u16 {
+        let low = self.stack_pop() as u16;
+        let high = self.stack_pop() as u16;
+        (high << 8) | low
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::cpu::CPU;
+
+    #[test]
+    fn reads_mem_u16() {
+        let mut cpu = CPU::new();
+        cpu.bus.poke(0x0, 0xef);
+        cpu.bus.poke(0x1, 0xbe);
+        assert_eq!(cpu.mem_read_u16(0x0), 0xbeef);
+    }
+
+    #[test]
+    fn reads_mem_u16_wrapping_at_end_of_memory() {
+        let mut cpu = CPU::new();
+        cpu.bus.poke(0xFFFF, 0xef);
+        cpu.bus.poke(0x0000, 0xbe);
+        assert_eq!(cpu.mem_read_u16(0xFFFF), 0xbeef);
+    }
+
+    #[test]
+    fn reads_zero_page_pointer_wrapping() {
+        let mut cpu = CPU::new();
+        cpu.bus.poke(0x00FF, 0xef);
+        cpu.bus.poke(0x0000, 0xbe);
+        cpu.bus.poke(0x0100, 0x12);
+        assert_eq!(cpu.mem_read_u16_zero_page(0xFF), 0xbeef);
+    }
+
+    #[test]
+    fn pushes_and_pops_stack() {
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xff;
+        cpu.stack_push(0x42);
+        assert_eq!(cpu.bus.peek(0x01ff), 0x42);
+        assert_eq!(cpu.stack_pointer, 0xfe);
+        assert_eq!(cpu.stack_pop(), 0x42);
+        assert_eq!(cpu.stack_pointer, 0xff);
+    }
+
+    #[test]
+    fn pushes_words_high_byte_first() {
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0xff;
+        cpu.stack_push_u16(0xbeef);
+        assert_eq!(cpu.bus.peek(0x01ff), 0xbe);
+        assert_eq!(cpu.bus.peek(0x01fe), 0xef);
+        assert_eq!(cpu.stack_pop_u16(), 0xbeef);
+    }
+
+    #[test]
+    fn stack_wraps_within_page_one() {
+        let mut cpu = CPU::new();
+        cpu.stack_pointer = 0x00;
+        cpu.stack_push(0x11);
+        cpu.stack_push(0x22);
+        assert_eq!(cpu.bus.peek(0x0100), 0x11);
+        assert_eq!(cpu.bus.peek(0x01ff), 0x22);
+        assert_eq!(cpu.stack_pointer, 0xfe);
+    }
+}
@@ -0,0 +1,386 @@
+mod addressing;
+mod error;
+mod instructions;
+mod interrupts;
+mod lifecycle;
+mod memory;
+pub mod opcodes;
+
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::bus::Bus;
+use crate::crash::CrashDump;
+use crate::debug::Breakpoint;
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::trace::{TraceEntry, TraceRing};
+
+pub use addressing::AddressingMode;
+pub use error::CpuError;
+pub use interrupts::Interrupt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusAccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusAccess {
+    pub addr: u16,
+    pub value: u8,
+    pub kind: BusAccessKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepInfo {
+    pub pc: u16,
+    pub opcode: u8,
+    pub bytes: u8,
+    // including page-cross, taken-branch and interrupt penalties
+    pub cycles: u16,
+    // only filled in while bus recording is enabled
+    pub bus_activity: Vec<BusAccess>,
+    pub breakpoint: Option<BusAccess>,
+    // set when an interrupt was serviced before this instruction
+    pub interrupt: Option<Interrupt>,
+    // an unknown opcode ran as a 1-byte NOP
+    pub skipped: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunExit {
+    Brk,
+    Breakpoint(BusAccess),
+    LimitReached,
+}
+
+// unset limits never stop the run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunLimits {
+    pub max_instructions: Option<u64>,
+    pub max_cycles: Option<u64>,
+    pub max_time: Option<Duration>,
+    // test programs end on BRK, games use it as a software interrupt
+    pub stop_on_brk: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RegisterFile {
+    pub accumulator: u8,
+    pub proc_status: u8,
+    pub prog_counter: u16,
+    pub reg_x: u8,
+    pub reg_y: u8,
+    pub stack_pointer: u8,
+}
+
+pub const CARRY: u8 = 0b0000_0001;
+pub const ZERO: u8 = 0b0000_0010;
+pub const NO_INTERRUPT: u8 = 0b0000_0100;
+pub const DECIMAL: u8 = 0b0000_1000;
+pub const BREAK: u8 = 0b0001_0000;
+pub const UNUSED: u8 = 0b0010_0000;
+pub const OVERFLOW: u8 = 0b0100_0000;
+pub const NEGATIVE: u8 = 0b1000_0000;
+
+const STACK: u16 = 0x0100;
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+const STACK_RESET: u8 = 0xfd;
+
+pub struct CPU {
+    pub(crate) accumulator: u8,
+    pub(crate) proc_status: u8,
+    pub(crate) prog_counter: u16,
+    pub(crate) reg_x: u8,
+    pub(crate) reg_y: u8,
+    pub(crate) stack_pointer: u8,
+
+    bus: Bus,
+
+    nmi_pending: bool,
+    irq_pending: bool,
+    delayed_irq_inhibit: Option<bool>,
+    skip_interrupt_poll: bool,
+
+    record_bus: bool,
+    bus_activity: Vec<BusAccess>,
+
+    breakpoints: Vec<Breakpoint>,
+    breakpoint_hit: Option<BusAccess>,
+
+    opcode_counts: [u64; 256],
+    extra_cycles: u8,
+    total_cycles: u64,
+    illegal_opcodes: bool,
+    skip_unknown_opcodes: bool,
+    skipped_opcodes: u64,
+
+    crash_dump_dir: Option<PathBuf>,
+    crash_dump_written: Option<io::Result<PathBuf>>,
+    recent: TraceRing,
+}
+
+impl CPU {
+    pub fn new() -> Self {
+        CPU {
+            accumulator: 0,
+            proc_status: 0,
+            prog_counter: 0,
+            reg_x: 0,
+            reg_y: 0,
+            stack_pointer: STACK_RESET,
+
+            bus: Bus::new(),
+
+            nmi_pending: false,
+            irq_pending: false,
+            delayed_irq_inhibit: None,
+            skip_interrupt_poll: false,
+
+            record_bus: false,
+            bus_activity: Vec::new(),
+
+            breakpoints: Vec::new(),
+            breakpoint_hit: None,
+
+            opcode_counts: [0; 256],
+            extra_cycles: 0,
+            total_cycles: 0,
+            illegal_opcodes: false,
+            skip_unknown_opcodes: false,
+            skipped_opcodes: 0,
+
+            crash_dump_dir: None,
+            crash_dump_written: None,
+            recent: TraceRing::default(),
+        }
+    }
+}
+
+impl Default for CPU {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CPU {
+    pub fn accumulator(&self) -> u8 {
+        self.accumulator
+    }
+    pub fn reg_x(&self) -> u8 {
+        self.reg_x
+    }
+    pub fn reg_y(&self) -> u8 {
+        self.reg_y
+    }
+    pub fn proc_status(&self) -> u8 {
+        self.proc_status
+    }
+    pub fn pc(&self) -> u16 {
+        self.prog_counter
+    }
+    pub fn stack_pointer(&self) -> u8 {
+        self.stack_pointer
+    }
+
+    pub fn flag_zero(&self) -> bool {
+        (self.proc_status & ZERO) != 0
+    }
+    pub fn flag_neg(&self) -> bool {
+        (self.proc_status & NEGATIVE) != 0
+    }
+    pub fn flag_carry(&self) -> bool {
+        (self.proc_status & CARRY) != 0
+    }
+    pub fn flag_overflow(&self) -> bool {
+        (self.proc_status & OVERFLOW) != 0
+    }
+
+    pub fn set_bus_recording(&mut self, enabled: bool) {
+        self.record_bus = enabled;
+        self.bus_activity.clear();
+    }
+
+    pub fn bus(&self) -> &Bus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
+    pub fn set_pc(&mut self, addr: u16) {
+        self.prog_counter = addr;
+    }
+
+    pub fn registers(&self) -> RegisterFile {
+        RegisterFile {
+            accumulator: self.accumulator,
+            proc_status: self.proc_status,
+            prog_counter: self.prog_counter,
+            reg_x: self.reg_x,
+            reg_y: self.reg_y,
+            stack_pointer: self.stack_pointer,
+        }
+    }
+
+    // CPU cycles run since creation
+    pub fn cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    pub fn set_registers(&mut self, registers: RegisterFile) {
+        self.accumulator = registers.accumulator;
+        self.proc_status = registers.proc_status;
+        self.prog_counter = registers.prog_counter;
+        self.reg_x = registers.reg_x;
+        self.reg_y = registers.reg_y;
+        self.stack_pointer = registers.stack_pointer;
+    }
+
+    // executed opcodes with their counts, most frequent first
+    pub fn opcode_stats(&self) -> Vec<(u8, u64)> {
+        let mut stats: Vec<(u8, u64)> = self
+            .opcode_counts
+            .iter()
+            .enumerate()
+            .filter(|&(_, &count)| count > 0)
+            .map(|(opcode, &count)| (opcode as u8, count))
+            .collect();
+        stats.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        stats
+    }
+
+    pub fn reset_opcode_stats(&mut self) {
+        self.opcode_counts = [0; 256];
+    }
+
+    // off by default, so undocumented opcodes stop the run as unknown ones
+    pub fn set_illegal_opcodes(&mut self, enabled: bool) {
+        self.illegal_opcodes = enabled;
+    }
+
+    // run unknown opcodes as 1-byte NOPs instead of stopping
+    pub fn set_skip_unknown_opcodes(&mut self, enabled: bool) {
+        self.skip_unknown_opcodes = enabled;
+    }
+
+    pub fn skipped_opcodes(&self) -> u64 {
+        self.skipped_opcodes
+    }
+
+    pub fn set_crash_dump_dir(&mut self, dir: Option<PathBuf>) {
+        self.crash_dump_dir = dir;
+    }
+
+    // where the last crash dump went, or why it could not be written
+    pub fn take_crash_dump_result(&mut self) -> Option<io::Result<PathBuf>> {
+        self.crash_dump_written.take()
+    }
+
+    pub fn crash_dump(&self, reason: &str, opcode: u8) -> CrashDump {
+        CrashDump {
+            reason: reason.to_string(),
+            opcode,
+            registers: self.registers(),
+            ram: (0..=0xFFFF).map(|addr| self.bus.peek(addr)).collect(),
+            trace: self.recent.entries(),
+        }
+    }
+
+    // the last executed instructions, oldest first; kept even with tracing off
+    pub fn recent_instructions(&self) -> Vec<TraceEntry> {
+        self.recent.entries()
+    }
+
+    pub fn set_recent_instructions_capacity(&mut self, capacity: usize) {
+        self.recent.set_capacity(capacity);
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+}
+
+impl CPU {
+    // registers, interrupt lines and the cycle count, then the bus; debugger
+    // settings and statistics stay as they are
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.accumulator);
+        state.u8(self.proc_status);
+        state.u16(self.prog_counter);
+        state.u8(self.reg_x);
+        state.u8(self.reg_y);
+        state.u8(self.stack_pointer);
+        state.bool(self.nmi_pending);
+        state.bool(self.irq_pending);
+        state.u8(match self.delayed_irq_inhibit {
+            None => 0,
+            Some(false) => 1,
+            Some(true) => 2,
+        });
+        state.bool(self.skip_interrupt_poll);
+        state.u64(self.total_cycles);
+        self.bus.save_state(state);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.accumulator = state.u8()?;
+        self.proc_status = state.u8()?;
+        self.prog_counter = state.u16()?;
+        self.reg_x = state.u8()?;
+        self.reg_y = state.u8()?;
+        self.stack_pointer = state.u8()?;
+        self.nmi_pending = state.bool()?;
+        self.irq_pending = state.bool()?;
+        self.delayed_irq_inhibit = match state.u8()? {
+            0 => None,
+            1 => Some(false),
+            2 => Some(true),
+            _ => return Err(StateError::BadData),
+        };
+        self.skip_interrupt_poll = state.bool()?;
+        self.total_cycles = state.u64()?;
+        self.bus.load_state(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sets_pc() {
+        let mut cpu = CPU::new();
+        cpu.bus.poke(0xC000, 0xe8);
+        cpu.set_pc(0xC000);
+        cpu.step().unwrap();
+        assert_eq!(cpu.reg_x, 1);
+        assert_eq!(cpu.prog_counter, 0xC001);
+    }
+
+    #[test]
+    fn sets_and_reads_back_registers() {
+        let mut cpu = CPU::new();
+        let registers = RegisterFile {
+            accumulator: 0x01,
+            proc_status: CARRY | NEGATIVE,
+            prog_counter: 0xC000,
+            reg_x: 0x02,
+            reg_y: 0x03,
+            stack_pointer: 0xf0,
+        };
+        cpu.set_registers(registers);
+        assert_eq!(cpu.registers(), registers);
+        assert!(cpu.flag_carry());
+        assert_eq!(cpu.reg_y, 0x03);
+    }
+}
@@ -0,0 +1,541 @@
+use std::fmt;
+
+use super::AddressingMode;
+use super::AddressingMode::*;
+use super::{CARRY, NEGATIVE, OVERFLOW, ZERO};
+use Mnemonic::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    Adc,
+    And,
+    Asl,
+    Bcc,
+    Bcs,
+    Beq,
+    Bit,
+    Bmi,
+    Bne,
+    Bpl,
+    Brk,
+    Bvc,
+    Bvs,
+    Clc,
+    Cld,
+    Cli,
+    Clv,
+    Cmp,
+    Cpx,
+    Cpy,
+    Dcp,
+    Dec,
+    Dex,
+    Dey,
+    Eor,
+    Inc,
+    Inx,
+    Iny,
+    Isb,
+    Jmp,
+    Jsr,
+    Lax,
+    Lda,
+    Ldx,
+    Ldy,
+    Lsr,
+    Nop,
+    Ora,
+    Pha,
+    Php,
+    Pla,
+    Plp,
+    Rla,
+    Rol,
+    Ror,
+    Rra,
+    Rti,
+    Rts,
+    Sax,
+    Sbc,
+    Sec,
+    Sed,
+    Sei,
+    Slo,
+    Sre,
+    Sta,
+    Stx,
+    Sty,
+    Tax,
+    Tay,
+    Tsx,
+    Txa,
+    Txs,
+    Tya,
+}
+
+impl Mnemonic {
+    pub fn name(self) -> &'static str {
+        match self {
+            Adc => "ADC",
+            And => "AND",
+            Asl => "ASL",
+            Bcc => "BCC",
+            Bcs => "BCS",
+            Beq => "BEQ",
+            Bit => "BIT",
+            Bmi => "BMI",
+            Bne => "BNE",
+            Bpl => "BPL",
+            Brk => "BRK",
+            Bvc => "BVC",
+            Bvs => "BVS",
+            Clc => "CLC",
+            Cld => "CLD",
+            Cli => "CLI",
+            Clv => "CLV",
+            Cmp => "CMP",
+            Cpx => "CPX",
+            Cpy => "CPY",
+            Dcp => "DCP",
+            Dec => "DEC",
+            Dex => "DEX",
+            Dey => "DEY",
+            Eor => "EOR",
+            Inc => "INC",
+            Inx => "INX",
+            Iny => "INY",
+            Isb => "ISB",
+            Jmp => "JMP",
+            Jsr => "JSR",
+            Lax => "LAX",
+            Lda => "LDA",
+            Ldx => "LDX",
+            Ldy => "LDY",
+            Lsr => "LSR",
+            Nop => "NOP",
+            Ora => "ORA",
+            Pha => "PHA",
+            Php => "PHP",
+            Pla => "PLA",
+            Plp => "PLP",
+            Rla => "RLA",
+            Rol => "ROL",
+            Ror => "ROR",
+            Rra => "RRA",
+            Rti => "RTI",
+            Rts => "RTS",
+            Sax => "SAX",
+            Sbc => "SBC",
+            Sec => "SEC",
+            Sed => "SED",
+            Sei => "SEI",
+            Slo => "SLO",
+            Sre => "SRE",
+            Sta => "STA",
+            Stx => "STX",
+            Sty => "STY",
+            Tax => "TAX",
+            Tay => "TAY",
+            Tsx => "TSX",
+            Txa => "TXA",
+            Txs => "TXS",
+            Tya => "TYA",
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OpCode {
+    pub code: u8,
+    pub mnemonic: Mnemonic,
+    pub mode: AddressingMode,
+    pub len: u8,
+    // not counting page-cross or taken-branch penalties
+    pub cycles: u8,
+    // undocumented opcodes only run with illegal opcodes enabled
+    pub illegal: bool,
+    // branches only: (flag tested, taken when the flag equals this)
+    pub branch: Option<(u8, bool)>,
+}
+
+impl OpCode {
+    const fn new(code: u8, mnemonic: Mnemonic, mode: AddressingMode, len: u8, cycles: u8) -> Self {
+        OpCode {
+            code,
+            mnemonic,
+            mode,
+            len,
+            cycles,
+            illegal: false,
+            branch: None,
+        }
+    }
+
+    const fn branch(code: u8, mnemonic: Mnemonic, flag: u8, expected: bool) -> Self {
+        OpCode {
+            branch: Some((flag, expected)),
+            ..OpCode::new(code, mnemonic, Relative, 2, 2)
+        }
+    }
+
+    const fn unofficial(
+        code: u8,
+        mnemonic: Mnemonic,
+        mode: AddressingMode,
+        len: u8,
+        cycles: u8,
+    ) -> Self {
+        OpCode {
+            illegal: true,
+            ..OpCode::new(code, mnemonic, mode, len, cycles)
+        }
+    }
+
+    // jumps, branches and interrupts move the PC themselves instead of
+    // stepping over their operand
+    pub fn sets_pc(&self) -> bool {
+        self.branch.is_some() || matches!(self.mnemonic, Jmp | Jsr | Rts | Rti | Brk)
+    }
+}
+
+const OFFICIAL: [OpCode; 151] = [
+    OpCode::new(0x69, Adc, Immediate, 2, 2),
+    OpCode::new(0x65, Adc, ZeroPage, 2, 3),
+    OpCode::new(0x75, Adc, ZeroPageX, 2, 4),
+    OpCode::new(0x6d, Adc, Absolute, 3, 4),
+    OpCode::new(0x7d, Adc, AbsoluteX, 3, 4),
+    OpCode::new(0x79, Adc, AbsoluteY, 3, 4),
+    OpCode::new(0x61, Adc, IndirectX, 2, 6),
+    OpCode::new(0x71, Adc, IndirectY, 2, 5),
+    OpCode::new(0x29, And, Immediate, 2, 2),
+    OpCode::new(0x25, And, ZeroPage, 2, 3),
+    OpCode::new(0x35, And, ZeroPageX, 2, 4),
+    OpCode::new(0x2d, And, Absolute, 3, 4),
+    OpCode::new(0x3d, And, AbsoluteX, 3, 4),
+    OpCode::new(0x39, And, AbsoluteY, 3, 4),
+    OpCode::new(0x21, And, IndirectX, 2, 6),
+    OpCode::new(0x31, And, IndirectY, 2, 5),
+    OpCode::new(0x0a, Asl, Accumulator, 1, 2),
+    OpCode::new(0x06, Asl, ZeroPage, 2, 5),
+    OpCode::new(0x16, Asl, ZeroPageX, 2, 6),
+    OpCode::new(0x0e, Asl, Absolute, 3, 6),
+    OpCode::new(0x1e, Asl, AbsoluteX, 3, 7),
+    OpCode::branch(0x90, Bcc, CARRY, false),
+    OpCode::branch(0xb0, Bcs, CARRY, true),
+    OpCode::branch(0xf0, Beq, ZERO, true),
+    OpCode::new(0x24, Bit, ZeroPage, 2, 3),
+    OpCode::new(0x2c, Bit, Absolute, 3, 4),
+    OpCode::branch(0x30, Bmi, NEGATIVE, true),
+    OpCode::branch(0xd0, Bne, ZERO, false),
+    OpCode::branch(0x10, Bpl, NEGATIVE, false),
+    OpCode::new(0x00, Brk, NoneAddressing, 1, 7),
+    OpCode::branch(0x50, Bvc, OVERFLOW, false),
+    OpCode::branch(0x70, Bvs, OVERFLOW, true),
+    OpCode::new(0x18, Clc, NoneAddressing, 1, 2),
+    OpCode::new(0xd8, Cld, NoneAddressing, 1, 2),
+    OpCode::new(0x58, Cli, NoneAddressing, 1, 2),
+    OpCode::new(0xb8, Clv, NoneAddressing, 1, 2),
+    OpCode::new(0xc9, Cmp, Immediate, 2, 2),
+    OpCode::new(0xc5, Cmp, ZeroPage, 2, 3),
+    OpCode::new(0xd5, Cmp, ZeroPageX, 2, 4),
+    OpCode::new(0xcd, Cmp, Absolute, 3, 4),
+    OpCode::new(0xdd, Cmp, AbsoluteX, 3, 4),
+    OpCode::new(0xd9, Cmp, AbsoluteY, 3, 4),
+    OpCode::new(0xc1, Cmp, IndirectX, 2, 6),
+    OpCode::new(0xd1, Cmp, IndirectY, 2, 5),
+    OpCode::new(0xe0, Cpx, Immediate, 2, 2),
+    OpCode::new(0xe4, Cpx, ZeroPage, 2, 3),
+    OpCode::new(0xec, Cpx, Absolute, 3, 4),
+    OpCode::new(0xc0, Cpy, Immediate, 2, 2),
+    OpCode::new(0xc4, Cpy, ZeroPage, 2, 3),
+    OpCode::new(0xcc, Cpy, Absolute, 3, 4),
+    OpCode::new(0xc6, Dec, ZeroPage, 2, 5),
+    OpCode::new(0xd6, Dec, ZeroPageX, 2, 6),
+    OpCode::new(0xce, Dec, Absolute, 3, 6),
+    OpCode::new(0xde, Dec, AbsoluteX, 3, 7),
+    OpCode::new(0xca, Dex, NoneAddressing, 1, 2),
+    OpCode::new(0x88, Dey, NoneAddressing, 1, 2),
+    OpCode::new(0x49, Eor, Immediate, 2, 2),
+    OpCode::new(0x45, Eor, ZeroPage, 2, 3),
+    OpCode::new(0x55, Eor, ZeroPageX, 2, 4),
+    OpCode::new(0x4d, Eor, Absolute, 3, 4),
+    OpCode::new(0x5d, Eor, AbsoluteX, 3, 4),
+    OpCode::new(0x59, Eor, AbsoluteY, 3, 4),
+    OpCode::new(0x41, Eor, IndirectX, 2, 6),
+    OpCode::new(0x51, Eor, IndirectY, 2, 5),
+    OpCode::new(0xe6, Inc, ZeroPage, 2, 5),
+    OpCode::new(0xf6, Inc, ZeroPageX, 2, 6),
+    OpCode::new(0xee, Inc, Absolute, 3, 6),
+    OpCode::new(0xfe, Inc, AbsoluteX, 3, 7),
+    OpCode::new(0xe8, Inx, NoneAddressing, 1, 2),
+    OpCode::new(0xc8, Iny, NoneAddressing, 1, 2),
+    OpCode::new(0x4c, Jmp, Absolute, 3, 3),
+    OpCode::new(0x6c, Jmp, Indirect, 3, 5),
+    OpCode::new(0x20, Jsr, Absolute, 3, 6),
+    OpCode::new(0xa9, Lda, Immediate, 2, 2),
+    OpCode::new(0xa5, Lda, ZeroPage, 2, 3),
+    OpCode::new(0xb5, Lda, ZeroPageX, 2, 4),
+    OpCode::new(0xad, Lda, Absolute, 3, 4),
+    OpCode::new(0xbd, Lda, AbsoluteX, 3, 4),
+    OpCode::new(0xb9, Lda, AbsoluteY, 3, 4),
+    OpCode::new(0xa1, Lda, IndirectX, 2, 6),
+    OpCode::new(0xb1, Lda, IndirectY, 2, 5),
+    OpCode::new(0xa2, Ldx, Immediate, 2, 2),
+    OpCode::new(0xa6, Ldx, ZeroPage, 2, 3),
+    OpCode::new(0xb6, Ldx, ZeroPageY, 2, 4),
+    OpCode::new(0xae, Ldx, Absolute, 3, 4),
+    OpCode::new(0xbe, Ldx, AbsoluteY, 3, 4),
+    OpCode::new(0xa0, Ldy, Immediate, 2, 2),
+    OpCode::new(0xa4, Ldy, ZeroPage, 2, 3),
+    OpCode::new(0xb4, Ldy, ZeroPageX, 2, 4),
+    OpCode::new(0xac, Ldy, Absolute, 3, 4),
+    OpCode::new(0xbc, Ldy, AbsoluteX, 3, 4),
+    OpCode::new(0x4a, Lsr, Accumulator, 1, 2),
+    OpCode::new(0x46, Lsr, ZeroPage, 2, 5),
+    OpCode::new(0x56, Lsr, ZeroPageX, 2, 6),
+    OpCode::new(0x4e, Lsr, Absolute, 3, 6),
+    OpCode::new(0x5e, Lsr, AbsoluteX, 3, 7),
+    OpCode::new(0xea, Nop, NoneAddressing, 1, 2),
+    OpCode::new(0x09, Ora, Immediate, 2, 2),
+    OpCode::new(0x05, Ora, ZeroPage, 2, 3),
+    OpCode::new(0x15, Ora, ZeroPageX, 2, 4),
+    OpCode::new(0x0d, Ora, Absolute, 3, 4),
+    OpCode::new(0x1d, Ora, AbsoluteX, 3, 4),
+    OpCode::new(0x19, Ora, AbsoluteY, 3, 4),
+    OpCode::new(0x01, Ora, IndirectX, 2, 6),
+    OpCode::new(0x11, Ora, IndirectY, 2, 5),
+    OpCode::new(0x48, Pha, NoneAddressing, 1, 3),
+    OpCode::new(0x08, Php, NoneAddressing, 1, 3),
+    OpCode::new(0x68, Pla, NoneAddressing, 1, 4),
+    OpCode::new(0x28, Plp, NoneAddressing, 1, 4),
+    OpCode::new(0x2a, Rol, Accumulator, 1, 2),
+    OpCode::new(0x26, Rol, ZeroPage, 2, 5),
+    OpCode::new(0x36, Rol, ZeroPageX, 2, 6),
+    OpCode::new(0x2e, Rol, Absolute, 3, 6),
+    OpCode::new(0x3e, Rol, AbsoluteX, 3, 7),
+    OpCode::new(0x6a, Ror, Accumulator, 1, 2),
+    OpCode::new(0x66, Ror, ZeroPage, 2, 5),
+    OpCode::new(0x76, Ror, ZeroPageX, 2, 6),
+    OpCode::new(0x6e, Ror, Absolute, 3, 6),
+    OpCode::new(0x7e, Ror, AbsoluteX, 3, 7),
+    OpCode::new(0x40, Rti, NoneAddressing, 1, 6),
+    OpCode::new(0x60, Rts, NoneAddressing, 1, 6),
+    OpCode::new(0xe9, Sbc, Immediate, 2, 2),
+    OpCode::new(0xe5, Sbc, ZeroPage, 2, 3),
+    OpCode::new(0xf5, Sbc, ZeroPageX, 2, 4),
+    OpCode::new(0xed, Sbc, Absolute, 3, 4),
+    OpCode::new(0xfd, Sbc, AbsoluteX, 3, 4),
+    OpCode::new(0xf9, Sbc, AbsoluteY, 3, 4),
+    OpCode::new(0xe1, Sbc, IndirectX, 2, 6),
+    OpCode::new(0xf1, Sbc, IndirectY, 2, 5),
+    OpCode::new(0x38, Sec, NoneAddressing, 1, 2),
+    OpCode::new(0xf8, Sed, NoneAddressing, 1, 2),
+    OpCode::new(0x78, Sei, NoneAddressing, 1, 2),
+    OpCode::new(0x85, Sta, ZeroPage, 2, 3),
+    OpCode::new(0x95, Sta, ZeroPageX, 2, 4),
+    OpCode::new(0x8d, Sta, Absolute, 3, 4),
+    OpCode::new(0x9d, Sta, AbsoluteX, 3, 5),
+    OpCode::new(0x99, Sta, AbsoluteY, 3, 5),
+    OpCode::new(0x81, Sta, IndirectX, 2, 6),
+    OpCode::new(0x91, Sta, IndirectY, 2, 6),
+    OpCode::new(0x86, Stx, ZeroPage, 2, 3),
+    OpCode::new(0x96, Stx, ZeroPageY, 2, 4),
+    OpCode::new(0x8e, Stx, Absolute, 3, 4),
+    OpCode::new(0x84, Sty, ZeroPage, 2, 3),
+    OpCode::new(0x94, Sty, ZeroPageX, 2, 4),
+    OpCode::new(0x8c, Sty, Absolute, 3, 4),
+    OpCode::new(0xaa, Tax, NoneAddressing, 1, 2),
+    OpCode::new(0xa8, Tay, NoneAddressing, 1, 2),
+    OpCode::new(0xba, Tsx, NoneAddressing, 1, 2),
+    OpCode::new(0x8a, Txa, NoneAddressing, 1, 2),
+    OpCode::new(0x9a, Txs, NoneAddressing, 1, 2),
+    OpCode::new(0x98, Tya, NoneAddressing, 1, 2),
+];
+
+const ILLEGAL: [OpCode; 80] = [
+    OpCode::unofficial(0xa7, Lax, ZeroPage, 2, 3),
+    OpCode::unofficial(0xb7, Lax, ZeroPageY, 2, 4),
+    OpCode::unofficial(0xaf, Lax, Absolute, 3, 4),
+    OpCode::unofficial(0xbf, Lax, AbsoluteY, 3, 4),
+    OpCode::unofficial(0xa3, Lax, IndirectX, 2, 6),
+    OpCode::unofficial(0xb3, Lax, IndirectY, 2, 5),
+    OpCode::unofficial(0x87, Sax, ZeroPage, 2, 3),
+    OpCode::unofficial(0x97, Sax, ZeroPageY, 2, 4),
+    OpCode::unofficial(0x8f, Sax, Absolute, 3, 4),
+    OpCode::unofficial(0x83, Sax, IndirectX, 2, 6),
+    OpCode::unofficial(0xc7, Dcp, ZeroPage, 2, 5),
+    OpCode::unofficial(0xd7, Dcp, ZeroPageX, 2, 6),
+    OpCode::unofficial(0xcf, Dcp, Absolute, 3, 6),
+    OpCode::unofficial(0xdf, Dcp, AbsoluteX, 3, 7),
+    OpCode::unofficial(0xdb, Dcp, AbsoluteY, 3, 7),
+    OpCode::unofficial(0xc3, Dcp, IndirectX, 2, 8),
+    OpCode::unofficial(0xd3, Dcp, IndirectY, 2, 8),
+    OpCode::unofficial(0xe7, Isb, ZeroPage, 2, 5),
+    OpCode::unofficial(0xf7, Isb, ZeroPageX, 2, 6),
+    OpCode::unofficial(0xef, Isb, Absolute, 3, 6),
+    OpCode::unofficial(0xff, Isb, AbsoluteX, 3, 7),
+    OpCode::unofficial(0xfb, Isb, AbsoluteY, 3, 7),
+    OpCode::unofficial(0xe3, Isb, IndirectX, 2, 8),
+    OpCode::unofficial(0xf3, Isb, IndirectY, 2, 8),
+    OpCode::unofficial(0x07, Slo, ZeroPage, 2, 5),
+    OpCode::unofficial(0x17, Slo, ZeroPageX, 2, 6),
+    OpCode::unofficial(0x0f, Slo, Absolute, 3, 6),
+    OpCode::unofficial(0x1f, Slo, AbsoluteX, 3, 7),
+    OpCode::unofficial(0x1b, Slo, AbsoluteY, 3, 7),
+    OpCode::unofficial(0x03, Slo, IndirectX, 2, 8),
+    OpCode::unofficial(0x13, Slo, IndirectY, 2, 8),
+    OpCode::unofficial(0x27, Rla, ZeroPage, 2, 5),
+    OpCode::unofficial(0x37, Rla, ZeroPageX, 2, 6),
+    OpCode::unofficial(0x2f, Rla, Absolute, 3, 6),
+    OpCode::unofficial(0x3f, Rla, AbsoluteX, 3, 7),
+    OpCode::unofficial(0x3b, Rla, AbsoluteY, 3, 7),
+    OpCode::unofficial(0x23, Rla, IndirectX, 2, 8),
+    OpCode::unofficial(0x33, Rla, IndirectY, 2, 8),
+    OpCode::unofficial(0x47, Sre, ZeroPage, 2, 5),
+    OpCode::unofficial(0x57, Sre, ZeroPageX, 2, 6),
+    OpCode::unofficial(0x4f, Sre, Absolute, 3, 6),
+    OpCode::unofficial(0x5f, Sre, AbsoluteX, 3, 7),
+    OpCode::unofficial(0x5b, Sre, AbsoluteY, 3, 7),
+    OpCode::unofficial(0x43, Sre, IndirectX, 2, 8),
+    OpCode::unofficial(0x53, Sre, IndirectY, 2, 8),
+    OpCode::unofficial(0x67, Rra, ZeroPage, 2, 5),
+    OpCode::unofficial(0x77, Rra, ZeroPageX, 2, 6),
+    OpCode::unofficial(0x6f, Rra, Absolute, 3, 6),
+    OpCode::unofficial(0x7f, Rra, AbsoluteX, 3, 7),
+    OpCode::unofficial(0x7b, Rra, AbsoluteY, 3, 7),
+    OpCode::unofficial(0x63, Rra, IndirectX, 2, 8),
+    OpCode::unofficial(0x73, Rra, IndirectY, 2, 8),
+    OpCode::unofficial(0xeb, Sbc, Immediate, 2, 2),
+    OpCode::unofficial(0x1a, Nop, NoneAddressing, 1, 2),
+    OpCode::unofficial(0x3a, Nop, NoneAddressing, 1, 2),
+    OpCode::unofficial(0x5a, Nop, NoneAddressing, 1, 2),
+    OpCode::unofficial(0x7a, Nop, NoneAddressing, 1, 2),
+    OpCode::unofficial(0xda, Nop, NoneAddressing, 1, 2),
+    OpCode::unofficial(0xfa, Nop, NoneAddressing, 1, 2),
+    OpCode::unofficial(0x80, Nop, Immediate, 2, 2),
+    OpCode::unofficial(0x82, Nop, Immediate, 2, 2),
+    OpCode::unofficial(0x89, Nop, Immediate, 2, 2),
+    OpCode::unofficial(0xc2, Nop, Immediate, 2, 2),
+    OpCode::unofficial(0xe2, Nop, Immediate, 2, 2),
+    OpCode::unofficial(0x04, Nop, ZeroPage, 2, 3),
+    OpCode::unofficial(0x44, Nop, ZeroPage, 2, 3),
+    OpCode::unofficial(0x64, Nop, ZeroPage, 2, 3),
+    OpCode::unofficial(0x14, Nop, ZeroPageX, 2, 4),
+    OpCode::unofficial(0x34, Nop, ZeroPageX, 2, 4),
+    OpCode::unofficial(0x54, Nop, ZeroPageX, 2, 4),
+    OpCode::unofficial(0x74, Nop, ZeroPageX, 2, 4),
+    OpCode::unofficial(0xd4, Nop, ZeroPageX, 2, 4),
+    OpCode::unofficial(0xf4, Nop, ZeroPageX, 2, 4),
+    OpCode::unofficial(0x0c, Nop, Absolute, 3, 4),
+    OpCode::unofficial(0x1c, Nop, AbsoluteX, 3, 4),
+    OpCode::unofficial(0x3c, Nop, AbsoluteX, 3, 4),
+    OpCode::unofficial(0x5c, Nop, AbsoluteX, 3, 4),
+    OpCode::unofficial(0x7c, Nop, AbsoluteX, 3, 4),
+    OpCode::unofficial(0xdc, Nop, AbsoluteX, 3, 4),
+    OpCode::unofficial(0xfc, Nop, AbsoluteX, 3, 4),
+];
+
+const fn build_table() -> [Option<OpCode>; 256] {
+    let mut table = [None; 256];
+    let mut i = 0;
+    while i < OFFICIAL.len() {
+        table[OFFICIAL[i].code as usize] = Some(OFFICIAL[i]);
+        i += 1;
+    }
+    i = 0;
+    while i < ILLEGAL.len() {
+        table[ILLEGAL[i].code as usize] = Some(ILLEGAL[i]);
+        i += 1;
+    }
+    table
+}
+
+// indexed by opcode, None for the ones the CPU does not implement (the
+// remaining unstable opcodes and the KIL/JAM ones)
+pub static OPCODES: [Option<OpCode>; 256] = build_table();
+
+// the table as a JSON array ordered by opcode, for external tools
+pub fn to_json() -> String {
+    let entries: Vec<String> = OPCODES
+        .iter()
+        .flatten()
+        .map(|op| {
+            format!(
+                concat!(
+                    "  {{\"opcode\": {}, \"mnemonic\": \"{}\", \"mode\": \"{:?}\", ",
+                    "\"bytes\": {}, \"cycles\": {}, \"illegal\": {}}}"
+                ),
+                op.code, op.mnemonic, op.mode, op.len, op.cycles, op.illegal
+            )
+        })
+        .collect();
+    format!("[\n{}\n]\n", entries.join(",\n"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn table_is_indexed_by_opcode() {
+        for (code, op) in OPCODES.iter().enumerate() {
+            if let Some(op) = op {
+                assert_eq!(op.code as usize, code);
+            }
+        }
+        assert_eq!(
+            OPCODES.iter().flatten().filter(|op| !op.illegal).count(),
+            151
+        );
+        assert_eq!(OPCODES.iter().flatten().filter(|op| op.illegal).count(), 80);
+    }
+
+    #[test]
+    fn describes_instructions() {
+        let lda = OPCODES[0xbd].unwrap();
+        assert_eq!(lda.mnemonic, Lda);
+        assert_eq!(lda.mnemonic.to_string(), "LDA");
+        assert_eq!(lda.mode, AbsoluteX);
+        assert_eq!(lda.len, 3);
+        assert_eq!(lda.cycles, 4);
+        assert!(OPCODES[0x02].is_none());
+    }
+
+    #[test]
+    fn every_relative_opcode_is_a_branch() {
+        for op in OPCODES.iter().flatten() {
+            assert_eq!(op.mode == Relative, op.branch.is_some(), "{}", op.mnemonic);
+        }
+        assert_eq!(OPCODES[0xd0].unwrap().branch, Some((ZERO, false)));
+    }
+
+    #[test]
+    fn exports_table_as_json() {
+        let json = to_json();
+        assert!(json.starts_with("[\n  {\"opcode\": 0, \"mnemonic\": \"BRK\""));
+        assert!(json.contains(
+            "{\"opcode\": 189, \"mnemonic\": \"LDA\", \"mode\": \"AbsoluteX\", \
+             \"bytes\": 3, \"cycles\": 4, \"illegal\": false}"
+        ));
+        assert_eq!(json.matches("\"opcode\"").count(), 231);
+        assert!(json.ends_with("}\n]\n"));
+    }
+
+    #[test]
+    fn length_follows_addressing_mode() {
+        for op in OPCODES.iter().flatten() {
+            let expected = match op.mode {
+                NoneAddressing | Accumulator => 1,
+                Absolute | AbsoluteX | AbsoluteY | Indirect => 3,
+                _ => 2,
+            };
+            assert_eq!(op.len, expected, "{} {:#04x}", op.mnemonic, op.code);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::cpu::RegisterFile;
+use crate::trace::TraceEntry;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrashDump {
+    pub reason: String,
+    pub opcode: u8,
+    pub registers: RegisterFile,
+    pub ram: Vec<u8>,
+    pub trace: Vec<TraceEntry>,
+}
+
+impl CrashDump {
+    pub fn to_json(&self) -> String {
+        let r = &self.registers;
+        format!(
+            concat!(
+                "{{\n",
+                "  \"reason\": \"{}\",\n",
+                "  \"opcode\": {},\n",
+                "  \"registers\": {{\n",
+                "    \"accumulator\": {},\n",
+                "    \"proc_status\": {},\n",
+                "    \"prog_counter\": {},\n",
+                "    \"reg_x\": {},\n",
+                "    \"reg_y\": {},\n",
+                "    \"stack_pointer\": {}\n",
+                "  }}\n",
+                "}}\n"
+            ),
+            escape(&self.reason),
+            self.opcode,
+            r.accumulator,
+            r.proc_status,
+            r.prog_counter,
+            r.reg_x,
+            r.reg_y,
+            r.stack_pointer
+        )
+    }
+
+    pub fn trace_lines(&self) -> String {
+        self.trace
+            .iter()
+            .map(|entry| format!("{entry}\n"))
+            .collect()
+    }
+
+    // writes state.json, trace.txt and ram.bin into a fresh subdirectory of `dir`
+    pub fn write_to(&self, dir: &Path) -> io::Result<PathBuf> {
+        let stamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis())
+            .unwrap_or(0);
+        let mut path = dir.join(format!("crash-{stamp}"));
+        let mut n = 1;
+        while path.exists() {
+            path = dir.join(format!("crash-{stamp}-{n}"));
+            n += 1;
+        }
+        fs::create_dir_all(&path)?;
+        fs::write(path.join("state.json"), self.to_json())?;
+        fs::write(path.join("trace.txt"), self.trace_lines())?;
+        fs::write(path.join("ram.bin"), &self.ram)?;
+        Ok(path)
+    }
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if c.is_control() => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn dump() -> CrashDump {
+        CrashDump {
+            reason: "unknown opcode 0x02 at 0x8000".to_string(),
+            opcode: 0x02,
+            registers: RegisterFile {
+                accumulator: 1,
+                proc_status: 2,
+                prog_counter: 0x8000,
+                reg_x: 3,
+                reg_y: 4,
+                stack_pointer: 0xfd,
+            },
+            ram: vec![0xaa; 16],
+            trace: vec![TraceEntry {
+                pc: 0x8000,
+                opcode: 0x02,
+                registers: RegisterFile::default(),
+            }],
+        }
+    }
+
+    #[test]
+    fn serializes_state_as_json() {
+        let json = dump().to_json();
+        assert!(json.contains("\"opcode\": 2,"));
+        assert!(json.contains("\"prog_counter\": 32768,"));
+        assert!(json.contains("\"reg_y\": 4,"));
+        assert!(json.contains("\"stack_pointer\": 253\n"));
+    }
+
+    #[test]
+    fn escapes_reason() {
+        let mut dump = dump();
+        dump.reason = "bad \"rom\"\\\n\u{1}".to_string();
+        assert!(dump
+            .to_json()
+            .contains(r#""reason": "bad \"rom\"\\\n\u0001","#));
+    }
+
+    #[test]
+    fn writes_dump_files() {
+        let dir = std::env::temp_dir().join(format!("nes-crash-test-{}", std::process::id()));
+        let path = dump().write_to(&dir).unwrap();
+        assert_eq!(fs::read(path.join("ram.bin")).unwrap(), vec![0xaa; 16]);
+        assert_eq!(
+            fs::read_to_string(path.join("trace.txt")).unwrap(),
+            "8000  02  A:00 X:00 Y:00 P:00 SP:00\n"
+        );
+        assert!(fs::read_to_string(path.join("state.json"))
+            .unwrap()
+            .contains("unknown opcode 0x02 at 0x8000"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,119 @@
+use crate::cpu::BusAccessKind;
+
+const REGISTERS: [(&str, u16); 30] = [
+    ("PPUCTRL", 0x2000),
+    ("PPUMASK", 0x2001),
+    ("PPUSTATUS", 0x2002),
+    ("OAMADDR", 0x2003),
+    ("OAMDATA", 0x2004),
+    ("PPUSCROLL", 0x2005),
+    ("PPUADDR", 0x2006),
+    ("PPUDATA", 0x2007),
+    ("SQ1_VOL", 0x4000),
+    ("SQ1_SWEEP", 0x4001),
+    ("SQ1_LO", 0x4002),
+    ("SQ1_HI", 0x4003),
+    ("SQ2_VOL", 0x4004),
+    ("SQ2_SWEEP", 0x4005),
+    ("SQ2_LO", 0x4006),
+    ("SQ2_HI", 0x4007),
+    ("TRI_LINEAR", 0x4008),
+    ("TRI_LO", 0x400A),
+    ("TRI_HI", 0x400B),
+    ("NOISE_VOL", 0x400C),
+    ("NOISE_LO", 0x400E),
+    ("NOISE_HI", 0x400F),
+    ("DMC_FREQ", 0x4010),
+    ("DMC_RAW", 0x4011),
+    ("DMC_START", 0x4012),
+    ("DMC_LEN", 0x4013),
+    ("OAMDMA", 0x4014),
+    ("SND_CHN", 0x4015),
+    ("JOY1", 0x4016),
+    ("JOY2", 0x4017),
+];
+
+pub fn register_address(name: &str) -> Option<u16> {
+    REGISTERS
+        .iter()
+        .find(|(reg, _)| reg.eq_ignore_ascii_case(name))
+        .map(|&(_, addr)| addr)
+}
+
+pub fn register_name(addr: u16) -> Option<&'static str> {
+    let addr = unmirror(addr);
+    REGISTERS
+        .iter()
+        .find(|&&(_, reg)| reg == addr)
+        .map(|&(name, _)| name)
+}
+
+// the 8 PPU registers are mirrored every 8 bytes up to 0x3FFF
+fn unmirror(addr: u16) -> u16 {
+    match addr {
+        0x2000..=0x3FFF => 0x2000 | (addr & 0x7),
+        _ => addr,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    // the register's unmirrored address, resolved once from its name
+    pub register: u16,
+    pub kind: BusAccessKind,
+}
+
+impl Breakpoint {
+    pub fn on_register(name: &str, kind: BusAccessKind) -> Option<Self> {
+        Some(Breakpoint {
+            register: register_address(name)?,
+            kind,
+        })
+    }
+
+    pub fn name(&self) -> Option<&'static str> {
+        register_name(self.register)
+    }
+
+    // runs on every bus access, so it only compares numbers
+    pub fn matches(&self, addr: u16, kind: BusAccessKind) -> bool {
+        self.kind == kind && unmirror(addr) == self.register
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn maps_names_to_addresses() {
+        assert_eq!(register_address("PPUSCROLL"), Some(0x2005));
+        assert_eq!(register_address("oamdma"), Some(0x4014));
+        assert_eq!(register_address("NOPE"), None);
+    }
+
+    #[test]
+    fn maps_mirrored_ppu_addresses_to_names() {
+        assert_eq!(register_name(0x2005), Some("PPUSCROLL"));
+        assert_eq!(register_name(0x3FFD), Some("PPUSCROLL"));
+        assert_eq!(register_name(0x0005), None);
+    }
+
+    #[test]
+    fn rejects_unknown_register() {
+        assert_eq!(
+            Breakpoint::on_register("PPUFOO", BusAccessKind::Write),
+            None
+        );
+    }
+
+    #[test]
+    fn matches_access_kind_and_register() {
+        let bp = Breakpoint::on_register("ppuscroll", BusAccessKind::Write).unwrap();
+        assert_eq!((bp.register, bp.name()), (0x2005, Some("PPUSCROLL")));
+        assert!(bp.matches(0x2005, BusAccessKind::Write));
+        assert!(bp.matches(0x200D, BusAccessKind::Write));
+        assert!(!bp.matches(0x2005, BusAccessKind::Read));
+        assert!(!bp.matches(0x2006, BusAccessKind::Write));
+    }
+}
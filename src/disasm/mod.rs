@@ -0,0 +1,115 @@
+mod predecode;
+
+use crate::cpu::opcodes::OPCODES;
+use crate::cpu::AddressingMode;
+
+pub use predecode::Predecoder;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+}
+
+impl Line {
+    // address just past the instruction, where the next line starts
+    pub fn next_addr(&self) -> u16 {
+        self.addr.wrapping_add(self.bytes.len() as u16)
+    }
+
+    pub fn contains(&self, addr: u16) -> bool {
+        addr.wrapping_sub(self.addr) < self.bytes.len() as u16
+    }
+}
+
+// decodes the instruction at addr, with bytes the opcode table does not
+// know shown as data
+pub fn decode(addr: u16, read: impl Fn(u16) -> u8) -> Line {
+    let code = read(addr);
+    let Some(op) = OPCODES[code as usize] else {
+        return Line {
+            addr,
+            bytes: vec![code],
+            text: format!(".db ${code:02X}"),
+        };
+    };
+    let bytes: Vec<u8> = (0..op.len as u16)
+        .map(|i| read(addr.wrapping_add(i)))
+        .collect();
+    let byte = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([byte, bytes.get(2).copied().unwrap_or(0)]);
+    let operand = match op.mode {
+        AddressingMode::Accumulator => "A".to_string(),
+        AddressingMode::Immediate => format!("#${byte:02X}"),
+        AddressingMode::ZeroPage => format!("${byte:02X}"),
+        AddressingMode::ZeroPageX => format!("${byte:02X},X"),
+        AddressingMode::ZeroPageY => format!("${byte:02X},Y"),
+        AddressingMode::Absolute => format!("${word:04X}"),
+        AddressingMode::AbsoluteX => format!("${word:04X},X"),
+        AddressingMode::AbsoluteY => format!("${word:04X},Y"),
+        AddressingMode::Indirect => format!("(${word:04X})"),
+        AddressingMode::IndirectX => format!("(${byte:02X},X)"),
+        AddressingMode::IndirectY => format!("(${byte:02X}),Y"),
+        // branches show their target rather than the offset
+        AddressingMode::Relative => {
+            let target = addr.wrapping_add(2).wrapping_add(byte as i8 as u16);
+            format!("${target:04X}")
+        }
+        AddressingMode::NoneAddressing => String::new(),
+    };
+    let text = if operand.is_empty() {
+        op.mnemonic.to_string()
+    } else {
+        format!("{} {}", op.mnemonic, operand)
+    };
+    Line { addr, bytes, text }
+}
+
+// count lines from start, one after the other
+pub fn decode_range(start: u16, count: usize, read: impl Fn(u16) -> u8) -> Vec<Line> {
+    let mut lines = Vec::with_capacity(count);
+    let mut addr = start;
+    for _ in 0..count {
+        let line = decode(addr, &read);
+        addr = line.next_addr();
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn text(bytes: &[u8]) -> String {
+        decode(0x8000, |addr| bytes[(addr - 0x8000) as usize]).text
+    }
+
+    #[test]
+    fn formats_addressing_modes() {
+        assert_eq!(text(&[0xa9, 0x10]), "LDA #$10");
+        assert_eq!(text(&[0x9d, 0x00, 0x02]), "STA $0200,X");
+        assert_eq!(text(&[0xb1, 0x20]), "LDA ($20),Y");
+        assert_eq!(text(&[0x6c, 0xfc, 0xff]), "JMP ($FFFC)");
+        assert_eq!(text(&[0x0a]), "ASL A");
+        assert_eq!(text(&[0xe8]), "INX");
+        assert_eq!(text(&[0x02]), ".db $02");
+    }
+
+    #[test]
+    fn branches_show_target() {
+        assert_eq!(text(&[0xd0, 0xfe]), "BNE $8000");
+        assert_eq!(text(&[0x10, 0x04]), "BPL $8006");
+    }
+
+    #[test]
+    fn decodes_consecutive_lines() {
+        let program = [0xa9, 0x01, 0x8d, 0x00, 0x20, 0xe8];
+        let lines = decode_range(0x8000, 3, |addr| program[(addr - 0x8000) as usize]);
+        let addrs: Vec<_> = lines.iter().map(|line| line.addr).collect();
+        assert_eq!(addrs, vec![0x8000, 0x8002, 0x8005]);
+        assert!(lines[1].contains(0x8004));
+        assert!(!lines[1].contains(0x8005));
+    }
+}
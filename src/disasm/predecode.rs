@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use super::{decode_range, Line};
+use crate::bus::Bus;
+use crate::cpu::{BusAccess, BusAccessKind};
+
+// the longest 6502 instruction
+const MAX_LEN: usize = 3;
+
+#[derive(Default)]
+struct Cache {
+    // lines keyed by (bank, address): bank is whatever the caller uses to
+    // tell apart code switched in at the same address, such as the PRG bank
+    lines: HashMap<(u32, u16), Line>,
+    // bumped by every invalidation, with the last one to hit each address,
+    // so lines decoded from bytes copied before a write are thrown away
+    generation: u64,
+    invalidated: HashMap<u16, u64>,
+}
+
+impl Cache {
+    fn is_stale(&self, line: &Line, generation: u64) -> bool {
+        (0..line.bytes.len() as u16).any(|i| {
+            self.invalidated
+                .get(&line.addr.wrapping_add(i))
+                .is_some_and(|&at| at > generation)
+        })
+    }
+}
+
+enum Request {
+    Decode {
+        bank: u32,
+        start: u16,
+        count: usize,
+        generation: u64,
+        // a copy of the bytes from start on, so the worker never touches
+        // the bus the emulation thread is using
+        memory: Vec<u8>,
+    },
+    Sync(Sender<()>),
+}
+
+// decodes disassembly windows on a background thread so a large view does
+// not hold up the emulation thread, which only copies the bytes
+pub struct Predecoder {
+    requests: Option<Sender<Request>>,
+    cache: Arc<Mutex<Cache>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl Predecoder {
+    pub fn new() -> Self {
+        let (requests, receiver) = mpsc::channel();
+        let cache = Arc::new(Mutex::new(Cache::default()));
+        let worker_cache = cache.clone();
+        let worker = thread::spawn(move || {
+            for request in receiver {
+                match request {
+                    Request::Decode {
+                        bank,
+                        start,
+                        count,
+                        generation,
+                        memory,
+                    } => {
+                        let read = |addr: u16| memory[addr.wrapping_sub(start) as usize];
+                        let lines = decode_range(start, count, read);
+                        let mut cache = worker_cache.lock().unwrap();
+                        for line in lines {
+                            if !cache.is_stale(&line, generation) {
+                                cache.lines.insert((bank, line.addr), line);
+                            }
+                        }
+                    }
+                    Request::Sync(done) => {
+                        let _ = done.send(());
+                    }
+                }
+            }
+        });
+        Predecoder {
+            requests: Some(requests),
+            cache,
+            worker: Some(worker),
+        }
+    }
+
+    // queues count lines from start, reading the bytes with Bus::peek now
+    pub fn request(&self, bus: &Bus, bank: u32, start: u16, count: usize) {
+        let memory = (0..count * MAX_LEN)
+            .map(|i| bus.peek(start.wrapping_add(i as u16)))
+            .collect();
+        let generation = self.cache.lock().unwrap().generation;
+        self.send(Request::Decode {
+            bank,
+            start,
+            count,
+            generation,
+            memory,
+        });
+    }
+
+    // the decoded lines from start on, stopping at the first one not
+    // decoded yet
+    pub fn window(&self, bank: u32, start: u16, count: usize) -> Vec<Line> {
+        let cache = self.cache.lock().unwrap();
+        let mut lines = Vec::new();
+        let mut addr = start;
+        while lines.len() < count {
+            let Some(line) = cache.lines.get(&(bank, addr)) else {
+                break;
+            };
+            addr = line.next_addr();
+            lines.push(line.clone());
+        }
+        lines
+    }
+
+    // drops every line covering addr, in all banks
+    pub fn invalidate(&self, addr: u16) {
+        let mut cache = self.cache.lock().unwrap();
+        cache.generation += 1;
+        let generation = cache.generation;
+        cache.invalidated.insert(addr, generation);
+        cache.lines.retain(|_, line| !line.contains(addr));
+    }
+
+    // invalidates the writes of a step recorded with set_bus_recording
+    pub fn invalidate_writes(&self, accesses: &[BusAccess]) {
+        for access in accesses {
+            if access.kind == BusAccessKind::Write {
+                self.invalidate(access.addr);
+            }
+        }
+    }
+
+    // blocks until every request made so far is in the cache
+    pub fn sync(&self) {
+        let (done, wait) = mpsc::channel();
+        self.send(Request::Sync(done));
+        let _ = wait.recv();
+    }
+
+    fn send(&self, request: Request) {
+        if let Some(requests) = &self.requests {
+            // the worker only stops once the sender is dropped
+            let _ = requests.send(request);
+        }
+    }
+}
+
+impl Default for Predecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for Predecoder {
+    fn drop(&mut self) {
+        self.requests.take();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn bus() -> Bus {
+        let mut bus = Bus::new();
+        // LDA #$01; STA $0200; INX
+        for (i, byte) in [0xa9, 0x01, 0x8d, 0x00, 0x02, 0xe8].into_iter().enumerate() {
+            bus.poke(0x8000 + i as u16, byte);
+        }
+        bus
+    }
+
+    #[test]
+    fn decodes_window_in_background() {
+        let bus = bus();
+        let predecoder = Predecoder::new();
+        predecoder.request(&bus, 0, 0x8000, 3);
+        predecoder.sync();
+        let text: Vec<_> = predecoder
+            .window(0, 0x8000, 3)
+            .into_iter()
+            .map(|line| line.text)
+            .collect();
+        assert_eq!(text, vec!["LDA #$01", "STA $0200", "INX"]);
+        // other banks at the same address are separate
+        assert!(predecoder.window(1, 0x8000, 3).is_empty());
+    }
+
+    #[test]
+    fn writes_invalidate_covering_lines() {
+        let mut bus = bus();
+        let predecoder = Predecoder::new();
+        predecoder.request(&bus, 0, 0x8000, 3);
+        predecoder.sync();
+        predecoder.invalidate_writes(&[BusAccess {
+            addr: 0x8003,
+            value: 0x10,
+            kind: BusAccessKind::Write,
+        }]);
+        assert_eq!(predecoder.window(0, 0x8000, 3).len(), 1);
+
+        bus.poke(0x8003, 0x10);
+        predecoder.request(&bus, 0, 0x8002, 2);
+        predecoder.sync();
+        assert_eq!(predecoder.window(0, 0x8000, 3)[1].text, "STA $0210");
+    }
+
+    #[test]
+    fn drops_lines_invalidated_while_queued() {
+        let bus = bus();
+        let predecoder = Predecoder::new();
+        // keep the worker busy so the invalidation usually lands first
+        predecoder.request(&bus, 1, 0, 0x4000);
+        predecoder.request(&bus, 0, 0x8000, 3);
+        predecoder.invalidate(0x8003);
+        predecoder.sync();
+        assert_eq!(predecoder.window(0, 0x8000, 3).len(), 1);
+
+        // a request made after the invalidation is not affected by it
+        predecoder.request(&bus, 0, 0x8002, 2);
+        predecoder.sync();
+        assert_eq!(predecoder.window(0, 0x8000, 3).len(), 3);
+    }
+}
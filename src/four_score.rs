@@ -0,0 +1,100 @@
+use crate::input::InputDevice;
+use crate::joypad::Joypad;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+// four players on two ports. Each port reads its two controllers one after
+// the other, eight bits each, then an ID byte that tells the adapter apart
+// from a single controller, and 1s after that
+#[derive(Debug, Clone)]
+pub struct FourScore {
+    pads: [Joypad; 2],
+    signature: u8,
+    strobe: bool,
+    shift: u32,
+}
+
+impl FourScore {
+    // the half for port 0 carries players 1 and 3, port 1 players 2 and 4
+    pub fn new(port: usize) -> Self {
+        FourScore {
+            pads: [Joypad::new(), Joypad::new()],
+            signature: if port == 0 { 0b0001_0000 } else { 0b0010_0000 },
+            strobe: false,
+            shift: 0,
+        }
+    }
+
+    // 0 for the first player on this port, 1 for the second
+    pub fn pad_mut(&mut self, pad: usize) -> &mut Joypad {
+        &mut self.pads[pad]
+    }
+
+    fn latched(&self) -> u32 {
+        self.pads[0].buttons() as u32
+            | (self.pads[1].buttons() as u32) << 8
+            | (self.signature as u32) << 16
+    }
+}
+
+impl InputDevice for FourScore {
+    // latched as the strobe drops, so buttons pressed while it is high
+    // still count
+    fn strobe(&mut self, high: bool) {
+        if self.strobe && !high {
+            self.shift = self.latched();
+        }
+        self.strobe = high;
+    }
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        let data = self.peek(ppu);
+        if !self.strobe {
+            self.shift = (self.shift >> 1) | 1 << 23;
+        }
+        data
+    }
+
+    fn peek(&self, _ppu: &Ppu) -> u8 {
+        let shift = if self.strobe {
+            self.latched()
+        } else {
+            self.shift
+        };
+        0x40 | (shift & 1) as u8
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.strobe);
+        state.u32(self.shift);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.strobe = state.bool()?;
+        self.shift = state.u32()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::joypad::Button;
+
+    #[test]
+    fn reads_both_players_then_the_signature() {
+        let ppu = Ppu::new();
+        let mut port = FourScore::new(1);
+        port.pad_mut(0).set_button(Button::A, true);
+        port.pad_mut(1).set_button(Button::Start, true);
+        port.write(1);
+        port.write(0);
+        let bits: Vec<_> = (0..26).map(|_| port.read(&ppu) & 1).collect();
+        let mut expected = vec![0; 24];
+        expected[0] = 1;
+        expected[8 + Button::Start as usize] = 1;
+        expected[16 + 5] = 1;
+        expected.extend([1, 1]);
+        assert_eq!(bits, expected);
+    }
+}
@@ -0,0 +1,31 @@
+use std::any::Any;
+
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+// something plugged into a controller port. 0x4016 reads the first port,
+// 0x4017 the second, and writes to 0x4016 reach both
+pub trait InputDevice: Any + Send {
+    // the OUT0 line, which latches the device's state while high
+    fn strobe(&mut self, high: bool);
+
+    // the whole byte written to 0x4016; OUT1 and OUT2 only go to the
+    // expansion port, so most devices just watch the strobe
+    fn write(&mut self, data: u8) {
+        self.strobe(data & 1 != 0);
+    }
+
+    // a CPU read of the port, which may shift out the next bit
+    fn read(&mut self, ppu: &Ppu) -> u8;
+
+    // what read would return, without the side effects
+    fn peek(&self, ppu: &Ppu) -> u8;
+
+    // input the player holds is live and stays out of save states, only
+    // the latched state goes in
+    fn save_state(&self, _state: &mut StateWriter) {}
+
+    fn load_state(&mut self, _state: &mut StateReader) -> Result<(), StateError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::input::InputDevice;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+// bit positions in the order the controller shifts them out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Button {
+    A,
+    B,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+// a standard controller: while the strobe bit written to 0x4016 is high
+// the shift register keeps reloading from the buttons, once it drops each
+// read shifts out the next button
+#[derive(Debug, Clone, Default)]
+pub struct Joypad {
+    buttons: u8,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Joypad {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        let bit = 1 << button as u8;
+        if pressed {
+            self.buttons |= bit;
+        } else {
+            self.buttons &= !bit;
+        }
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    pub fn pressed(&self, button: Button) -> bool {
+        self.buttons & (1 << button as u8) != 0
+    }
+
+    // all eight buttons, A in bit 0
+    pub(crate) fn buttons(&self) -> u8 {
+        self.buttons
+    }
+}
+
+impl InputDevice for Joypad {
+    fn strobe(&mut self, high: bool) {
+        self.strobe = high;
+        if self.strobe {
+            self.shift = self.buttons;
+        }
+    }
+
+    // official controllers read 1 once all eight buttons are out
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        let data = self.peek(ppu);
+        if !self.strobe {
+            self.shift = (self.shift >> 1) | 0x80;
+        }
+        data
+    }
+
+    // the upper bits are open bus, usually the 0x40 of the address
+    fn peek(&self, _ppu: &Ppu) -> u8 {
+        0x40 | (self.shift & 1)
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.strobe);
+        state.u8(self.shift);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.strobe = state.bool()?;
+        self.shift = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shifts_out_buttons_in_order() {
+        let ppu = Ppu::new();
+        let mut joypad = Joypad::new();
+        joypad.set_button(Button::A, true);
+        joypad.set_button(Button::Start, true);
+        joypad.set_button(Button::Right, true);
+        joypad.write(1);
+        joypad.write(0);
+        let bits: Vec<_> = (0..10).map(|_| joypad.read(&ppu) & 1).collect();
+        assert_eq!(bits, vec![1, 0, 0, 1, 0, 0, 0, 1, 1, 1]);
+    }
+
+    #[test]
+    fn strobe_high_keeps_returning_a() {
+        let ppu = Ppu::new();
+        let mut joypad = Joypad::new();
+        joypad.write(1);
+        joypad.set_button(Button::A, true);
+        assert_eq!(joypad.read(&ppu), 0x41);
+        assert_eq!(joypad.read(&ppu), 0x41);
+        joypad.set_button(Button::A, false);
+        assert_eq!(joypad.read(&ppu), 0x40);
+        assert!(!joypad.pressed(Button::A));
+    }
+}
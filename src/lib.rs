@@ -1 +1,38 @@
+pub mod apu;
+pub mod bus;
+pub mod bus_trace;
+pub mod cartridge;
+pub mod checksum;
+pub mod chr;
 pub mod cpu;
+pub mod crash;
+pub mod debug;
+pub mod disasm;
+pub mod four_score;
+pub mod input;
+pub mod joypad;
+pub mod library;
+pub mod mapper;
+pub mod nes;
+pub mod paddle;
+pub mod patch;
+pub(crate) mod png;
+pub mod ppu;
+pub mod state;
+pub mod stats;
+pub mod trace;
+pub mod video;
+pub mod wav;
+pub mod zapper;
+
+// the supported surface: everything a frontend needs is re-exported here,
+// the modules above keep tooling (patching, tracing, CHR sheets) reachable
+pub use apu::Apu;
+pub use bus::{Bus, MapRange, Mem, Region};
+pub use cartridge::{Mirroring, Rom, RomError, RomHeader};
+pub use cpu::{CpuError, RegisterFile, RunExit, RunLimits, StepInfo, CPU};
+pub use input::InputDevice;
+pub use joypad::{Button, Joypad};
+pub use mapper::Mapper;
+pub use nes::Nes;
+pub use ppu::{Frame, FrameMailbox, PixelFormat, Ppu};
@@ -0,0 +1,130 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cartridge::{Rom, RomHeader};
+use crate::stats::{PlayStats, StatsStore};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub header: RomHeader,
+    // over PRG and CHR ROM only, see Rom::content_crc32
+    pub crc32: u32,
+    // zeroed unless scanned with scan_with_stats
+    pub stats: PlayStats,
+}
+
+// walks dir and its subdirectories for .nes files, sorted by path; files
+// that cannot be read or parsed are left out, and symlinked directories are
+// not followed so a link back up the tree cannot loop
+pub fn scan(dir: &Path) -> Vec<RomEntry> {
+    let mut entries = Vec::new();
+    scan_into(dir, &mut entries);
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    entries
+}
+
+// scan, with each entry's playtime and statistics filled in from store
+pub fn scan_with_stats(dir: &Path, store: &StatsStore) -> Vec<RomEntry> {
+    let mut entries = scan(dir);
+    for entry in &mut entries {
+        entry.stats = store.get(entry.crc32);
+    }
+    entries
+}
+
+fn scan_into(dir: &Path, entries: &mut Vec<RomEntry>) {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in read_dir.flatten() {
+        let path = entry.path();
+        // file_type does not follow symlinks, unlike Path::is_dir
+        if entry.file_type().is_ok_and(|file_type| file_type.is_dir()) {
+            scan_into(&path, entries);
+        } else if has_nes_extension(&path) {
+            if let Some(rom) = fs::read(&path)
+                .ok()
+                .and_then(|raw| Rom::from_bytes(&raw).ok())
+            {
+                entries.push(RomEntry {
+                    header: *rom.header(),
+                    crc32: rom.content_crc32(),
+                    stats: PlayStats::default(),
+                    path,
+                });
+            }
+        }
+    }
+}
+
+fn has_nes_extension(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::ines;
+
+    fn write_rom(path: &Path, mapper: u8) {
+        fs::write(path, ines(mapper << 4, 0, &[0xea; 0x4000], &[])).unwrap();
+    }
+
+    #[test]
+    fn finds_roms_in_subdirectories() {
+        let dir = std::env::temp_dir().join(format!("nes-library-test-{}", std::process::id()));
+        fs::create_dir_all(dir.join("mmc1")).unwrap();
+        write_rom(&dir.join("b.nes"), 0);
+        write_rom(&dir.join("mmc1/a.NES"), 1);
+        fs::write(dir.join("broken.nes"), b"NES").unwrap();
+        fs::write(dir.join("notes.txt"), b"hi").unwrap();
+
+        let entries = scan(&dir);
+        let found: Vec<_> = entries
+            .iter()
+            .map(|entry| (entry.path.strip_prefix(&dir).unwrap(), entry.header.mapper))
+            .collect();
+        assert_eq!(
+            found,
+            vec![(Path::new("b.nes"), 0), (Path::new("mmc1/a.NES"), 1)]
+        );
+        assert_eq!(entries[0].crc32, entries[1].crc32);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn attaches_stats_by_crc() {
+        let dir = std::env::temp_dir().join(format!("nes-library-stats-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        write_rom(&dir.join("a.nes"), 0);
+        let crc32 = scan(&dir)[0].crc32;
+        let mut store = StatsStore::load(&dir.join("stats.txt")).unwrap();
+        store.record_session(crc32, 120);
+
+        let entries = scan_with_stats(&dir, &store);
+        assert_eq!(entries[0].stats.frames, 120);
+        assert_eq!(scan(&dir)[0].stats, PlayStats::default());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_follow_directory_symlinks() {
+        let dir = std::env::temp_dir().join(format!("nes-library-cycle-{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).unwrap();
+        write_rom(&dir.join("sub/a.nes"), 0);
+        std::os::unix::fs::symlink(&dir, dir.join("sub/loop")).unwrap();
+
+        let entries = scan(&dir);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].path, dir.join("sub/a.nes"));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn missing_directory_is_empty() {
+        assert!(scan(Path::new("/nonexistent/nes-library")).is_empty());
+    }
+}
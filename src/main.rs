@@ -1,6 +1,232 @@
-use nes::cpu::CPU;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+
+use nes::apu::CPU_CLOCK_RATE;
+use nes::cartridge::{self, Rom, NES_TAG};
+use nes::checksum::crc32;
+use nes::cpu::{opcodes, CpuError, RunExit, RunLimits, CPU};
+use nes::patch;
+use nes::wav::WavWriter;
+
+fn usage() -> ! {
+    eprintln!(
+        "usage: nes [--no-patch] [--no-sav] [--illegal-opcodes] [--skip-unknown] [--wav <out.wav>]"
+    );
+    eprintln!("           <program>");
+    eprintln!("       nes info <rom.nes>");
+    eprintln!("       nes dump-opcodes [--format json]");
+    process::exit(2);
+}
+
+fn read_file(path: &Path) -> Vec<u8> {
+    std::fs::read(path).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    })
+}
+
+// runs a second of CPU time at a time, so battery RAM reaches the .sav file
+// even when the game never stops on its own
+fn run_saving_sram(cpu: &mut CPU, sav_path: &Path) -> Result<(), CpuError> {
+    let limits = RunLimits {
+        max_cycles: Some(CPU_CLOCK_RATE as u64),
+        stop_on_brk: true,
+        ..RunLimits::default()
+    };
+    let mut saved = cpu.bus().sram().map(<[u8]>::to_vec);
+    loop {
+        let exit = cpu.run_with_limits(limits);
+        if let Some(sram) = cpu.bus().sram() {
+            if saved.as_deref() != Some(sram) {
+                if let Err(err) = std::fs::write(sav_path, sram) {
+                    eprintln!("{}: {}", sav_path.display(), err);
+                }
+                saved = Some(sram.to_vec());
+            }
+        }
+        if exit? != RunExit::LimitReached {
+            return Ok(());
+        }
+    }
+}
+
+type Wav = Arc<Mutex<(WavWriter<BufWriter<File>>, io::Result<()>)>>;
+
+// every sample the APU makes goes to the file, whatever the host's speed
+fn record_wav(cpu: &mut CPU, path: &Path) -> io::Result<Wav> {
+    let apu = cpu.bus().apu();
+    let out = BufWriter::new(File::create(path)?);
+    let writer = WavWriter::new(
+        out,
+        apu.sample_rate(),
+        apu.channel_layout().channels() as u16,
+    )?;
+    let wav = Arc::new(Mutex::new((writer, Ok(()))));
+    let sink = Arc::clone(&wav);
+    cpu.bus_mut().apu_mut().set_sample_sink(move |sample| {
+        let (writer, result) = &mut *sink.lock().unwrap();
+        if result.is_ok() {
+            *result = writer.write(sample);
+        }
+    });
+    Ok(wav)
+}
+
+fn finish_wav(cpu: &mut CPU, wav: Wav) -> io::Result<()> {
+    cpu.bus_mut().apu_mut().clear_sample_sink();
+    let (writer, result) = Arc::try_unwrap(wav)
+        .ok()
+        .expect("sample sink still holds the WAV writer")
+        .into_inner()
+        .unwrap();
+    result?;
+    writer.finish().map(drop)
+}
+
+fn info(path: PathBuf) {
+    let raw = read_file(&path);
+    let rom = Rom::from_bytes(&raw).unwrap_or_else(|err| {
+        eprintln!("{}: {}", path.display(), err);
+        process::exit(1);
+    });
+    let header = rom.header();
+    let yes_no = |flag: bool| if flag { "yes" } else { "no" };
+    let kib = |size: usize| format!("{} KiB", size as f64 / 1024.0);
+
+    println!("format:    {:?}", header.format);
+    println!(
+        "mapper:    {}.{} ({})",
+        header.mapper,
+        header.submapper,
+        cartridge::mapper_name(header.mapper).unwrap_or("unknown")
+    );
+    println!("prg rom:   {}", kib(header.prg_rom_size));
+    println!("chr rom:   {}", kib(header.chr_rom_size));
+    println!(
+        "prg ram:   {} + {} battery-backed",
+        kib(header.prg_ram_size),
+        kib(header.prg_nvram_size)
+    );
+    println!(
+        "chr ram:   {} + {} battery-backed",
+        kib(header.chr_ram_size),
+        kib(header.chr_nvram_size)
+    );
+    println!("mirroring: {:?}", header.mirroring);
+    println!("timing:    {:?}", header.timing);
+    println!("battery:   {}", yes_no(header.battery));
+    println!("trainer:   {}", yes_no(header.trainer));
+    println!("pc-10:     {}", yes_no(header.playchoice));
+    println!("crc32:     {:08X}", crc32(&raw));
+    println!("rom crc32: {:08X}", rom.content_crc32());
+}
 
 fn main() {
-    let cpu = CPU::new();
-    print!("{}", cpu.flag_zero());
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().map(String::as_str) == Some("info") {
+        args.next();
+        match (args.next(), args.next()) {
+            (Some(path), None) => info(PathBuf::from(path)),
+            _ => usage(),
+        }
+        return;
+    }
+    if args.peek().map(String::as_str) == Some("dump-opcodes") {
+        args.next();
+        // JSON is the only format so far
+        match (args.next().as_deref(), args.next().as_deref(), args.next()) {
+            (None, _, _) | (Some("--format"), Some("json"), None) => {
+                print!("{}", opcodes::to_json())
+            }
+            _ => usage(),
+        }
+        return;
+    }
+
+    let mut apply_patch = true;
+    let mut use_sav = true;
+    let mut illegal_opcodes = false;
+    let mut skip_unknown = false;
+    let mut wav_path = None;
+    let mut path = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--wav" => wav_path = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            "--no-patch" => apply_patch = false,
+            "--no-sav" => use_sav = false,
+            "--illegal-opcodes" => illegal_opcodes = true,
+            "--skip-unknown" => skip_unknown = true,
+            _ if path.is_none() => path = Some(PathBuf::from(arg)),
+            _ => usage(),
+        }
+    }
+    let path = path.unwrap_or_else(|| usage());
+
+    let mut program = read_file(&path);
+
+    if apply_patch {
+        if let Some((patch_path, format)) = patch::find_sidecar(&path) {
+            let patched = std::fs::read(&patch_path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| format.apply(&program, &data).map_err(|err| err.to_string()));
+            match patched {
+                Ok(patched) => program = patched,
+                Err(err) => {
+                    eprintln!("{}: {}", patch_path.display(), err);
+                    process::exit(1);
+                }
+            }
+        }
+    }
+
+    let mut cpu = CPU::new();
+    cpu.set_illegal_opcodes(illegal_opcodes);
+    cpu.set_skip_unknown_opcodes(skip_unknown);
+    let wav = wav_path.as_deref().map(|wav_path| {
+        record_wav(&mut cpu, wav_path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", wav_path.display(), err);
+            process::exit(1);
+        })
+    });
+    let result = if program.starts_with(&NES_TAG) {
+        // battery saves live next to the ROM as game.sav
+        let sav_path = path.with_extension("sav");
+        Rom::from_bytes(&program)
+            .and_then(|rom| cpu.load(rom))
+            .map_err(CpuError::from)
+            .and_then(|()| {
+                if use_sav && cpu.bus().sram().is_some() {
+                    if let Ok(sav) = std::fs::read(&sav_path) {
+                        cpu.bus_mut().load_sram(&sav);
+                    }
+                }
+                cpu.reset();
+                if use_sav && cpu.bus().sram().is_some() {
+                    run_saving_sram(&mut cpu, &sav_path)
+                } else {
+                    cpu.run()
+                }
+            })
+    } else {
+        cpu.load_and_run(program)
+    };
+    if let (Some(wav), Some(wav_path)) = (wav, &wav_path) {
+        if let Err(err) = finish_wav(&mut cpu, wav) {
+            eprintln!("{}: {}", wav_path.display(), err);
+        }
+    }
+    if cpu.skipped_opcodes() > 0 {
+        eprintln!(
+            "warning: skipped {} unknown opcodes as NOP",
+            cpu.skipped_opcodes()
+        );
+    }
+    if let Err(err) = result {
+        eprintln!("{err}");
+        process::exit(1);
+    }
+    println!("{:?}", cpu.registers());
 }
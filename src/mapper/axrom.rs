@@ -0,0 +1,102 @@
+use super::{bank_offset, Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+
+// mapper 7: a switchable 32 KiB PRG bank (bits 0-2) and single-screen
+// mirroring picked by bit 4
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    bank: u8,
+}
+
+impl Axrom {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr) -> Self {
+        Axrom {
+            prg_rom,
+            chr,
+            bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        bank_offset(
+            (self.bank & 0b0111) as usize,
+            PRG_BANK_SIZE,
+            (addr & 0x7FFF) as usize,
+            self.prg_rom.len(),
+        )
+    }
+}
+
+impl Mapper for Axrom {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, _addr: u16, data: u8) {
+        self.bank = data;
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        self.prg_offset(addr) / PRG_BANK_SIZE
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank & 0b1_0000 == 0 {
+            Mirroring::SingleScreenLower
+        } else {
+            Mirroring::SingleScreenUpper
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+        state.u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)?;
+        self.bank = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn switches_32k_bank_and_mirroring() {
+        let prg_rom = (0..4).flat_map(|bank| vec![bank; PRG_BANK_SIZE]).collect();
+        let mut mapper = Axrom::new(prg_rom, Chr::new(Vec::new(), 0));
+        assert_eq!(mapper.read_prg(0xFFFF), 0);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+        mapper.write_prg(0x8000, 0b1_0010);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xFFFF)), (2, 2));
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+    }
+}
@@ -0,0 +1,145 @@
+use super::{bank_offset, Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// mapper 71, Camerica/Codemasters: UxROM-like banking selected through
+// 0xC000-0xFFFF. The Fire Hawk board (submapper 1) also picks a
+// single-screen nametable with bit 4 of writes to 0x8000-0x9FFF; other
+// boards have hardwired mirroring and ignore those writes
+pub struct Camerica {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    fire_hawk: bool,
+    bank: u8,
+}
+
+impl Camerica {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr, mirroring: Mirroring, submapper: u8) -> Self {
+        Camerica {
+            prg_rom,
+            chr,
+            mirroring,
+            fire_hawk: submapper == 1,
+            bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank as usize,
+            _ => (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1),
+        };
+        bank_offset(
+            bank,
+            PRG_BANK_SIZE,
+            (addr & 0x3FFF) as usize,
+            self.prg_rom.len(),
+        )
+    }
+}
+
+impl Mapper for Camerica {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x8000..=0x9FFF if self.fire_hawk => {
+                self.mirroring = if data & 0b1_0000 == 0 {
+                    Mirroring::SingleScreenLower
+                } else {
+                    Mirroring::SingleScreenUpper
+                }
+            }
+            0xC000..=0xFFFF => self.bank = data,
+            _ => {}
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        self.prg_offset(addr) / PRG_BANK_SIZE
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+        state.u8(self.bank);
+        state.mirroring(self.mirroring);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)?;
+        self.bank = state.u8()?;
+        self.mirroring = state.mirroring()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn camerica(submapper: u8) -> Camerica {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank; PRG_BANK_SIZE]).collect();
+        Camerica::new(
+            prg_rom,
+            Chr::new(Vec::new(), 0),
+            Mirroring::Vertical,
+            submapper,
+        )
+    }
+
+    #[test]
+    fn switches_bank_through_upper_half() {
+        let mut mapper = camerica(0);
+        mapper.write_prg(0x8000, 3);
+        assert_eq!(mapper.read_prg(0x8000), 0);
+        mapper.write_prg(0xC000, 3);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xC000)), (3, 7));
+    }
+
+    #[test]
+    fn fire_hawk_selects_single_screen() {
+        let mut mapper = camerica(1);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+        mapper.write_prg(0x9000, 0b1_0000);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenUpper);
+        mapper.write_prg(0x9000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenLower);
+    }
+
+    #[test]
+    fn other_boards_keep_header_mirroring() {
+        let mut mapper = camerica(0);
+        mapper.write_prg(0x9000, 0b1_0000);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+}
@@ -0,0 +1,106 @@
+use super::{bank_offset, Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// mapper 3: NROM PRG with a switchable 8 KiB CHR bank, any write into ROM
+// selects the bank
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr, mirroring: Mirroring) -> Self {
+        Cnrom {
+            prg_rom,
+            chr,
+            mirroring,
+            bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        (addr - 0x8000) as usize % self.prg_rom.len()
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        bank_offset(
+            self.bank as usize,
+            CHR_BANK_SIZE,
+            addr as usize,
+            self.chr.len(),
+        )
+    }
+}
+
+impl Mapper for Cnrom {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, _addr: u16, data: u8) {
+        self.bank = data;
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    // no PRG banking, the whole ROM is bank 0
+    fn prg_bank(&self, _addr: u16) -> usize {
+        0
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(self.chr_offset(addr), data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+        state.u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)?;
+        self.bank = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn switches_chr_bank() {
+        let chr_rom = (0..4).flat_map(|bank| vec![bank; CHR_BANK_SIZE]).collect();
+        let mut mapper = Cnrom::new(vec![0; 0x8000], Chr::new(chr_rom, 0), Mirroring::Vertical);
+        assert_eq!(mapper.read_chr(0x1FFF), 0);
+        mapper.write_prg(0x8000, 2);
+        assert_eq!(mapper.read_chr(0x0000), 2);
+        // out-of-range banks wrap
+        mapper.write_prg(0xFFFF, 7);
+        assert_eq!(mapper.read_chr(0x1000), 3);
+    }
+}
@@ -0,0 +1,272 @@
+use super::{bank_offset, Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+// a 1 shifted in from the top reaches bit 0 on the fourth write
+const SHIFT_RESET: u8 = 0b1_0000;
+
+// mapper 1: registers are loaded one bit at a time through a 5-bit serial
+// shift register; writing a byte with bit 7 set resets it
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    shift: u8,
+    // mirroring (bits 0-1), PRG bank mode (bits 2-3), CHR bank mode (bit 4)
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    // bit 4 disables PRG RAM on some boards, which is not emulated
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr) -> Self {
+        Mmc1 {
+            prg_rom,
+            chr,
+            shift: SHIFT_RESET,
+            // powers up with the last PRG bank fixed at 0xC000
+            control: 0b0_1100,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, value: u8) {
+        match addr {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = (self.prg_bank & 0b1111) as usize;
+        let last = (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1);
+        let len = self.prg_rom.len();
+        match (self.control >> 2) & 0b11 {
+            // 32 KiB at a time, ignoring the low bank bit
+            0 | 1 => bank_offset(bank & !1, PRG_BANK_SIZE, (addr - 0x8000) as usize, len),
+            // first bank fixed at 0x8000, 0xC000 switchable
+            2 => match addr {
+                0x8000..=0xBFFF => bank_offset(0, PRG_BANK_SIZE, (addr - 0x8000) as usize, len),
+                _ => bank_offset(bank, PRG_BANK_SIZE, (addr - 0xC000) as usize, len),
+            },
+            // 0x8000 switchable, last bank fixed at 0xC000
+            _ => match addr {
+                0x8000..=0xBFFF => bank_offset(bank, PRG_BANK_SIZE, (addr - 0x8000) as usize, len),
+                _ => bank_offset(last, PRG_BANK_SIZE, (addr - 0xC000) as usize, len),
+            },
+        }
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let len = self.chr.len();
+        if self.control & 0b1_0000 == 0 {
+            // 8 KiB at a time, ignoring the low bank bit
+            bank_offset(
+                (self.chr_bank_0 & !1) as usize,
+                CHR_BANK_SIZE,
+                addr as usize,
+                len,
+            )
+        } else if addr < 0x1000 {
+            bank_offset(self.chr_bank_0 as usize, CHR_BANK_SIZE, addr as usize, len)
+        } else {
+            bank_offset(
+                self.chr_bank_1 as usize,
+                CHR_BANK_SIZE,
+                (addr - 0x1000) as usize,
+                len,
+            )
+        }
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        if data & 0b1000_0000 != 0 {
+            self.shift = SHIFT_RESET;
+            self.control |= 0b0_1100;
+            return;
+        }
+        let complete = self.shift & 1 != 0;
+        self.shift = (self.shift >> 1) | ((data & 1) << 4);
+        if complete {
+            // only the address of the fifth write picks the register
+            self.write_register(addr, self.shift);
+            self.shift = SHIFT_RESET;
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        self.prg_offset(addr) / PRG_BANK_SIZE
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(self.chr_offset(addr), data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & 0b11 {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+        state.u8(self.shift);
+        state.u8(self.control);
+        state.u8(self.chr_bank_0);
+        state.u8(self.chr_bank_1);
+        state.u8(self.prg_bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)?;
+        // every register is 5 bits wide
+        self.shift = state.u8_in(0..=0x1F, "MMC1 shift register")?;
+        self.control = state.u8_in(0..=0x1F, "MMC1 control")?;
+        self.chr_bank_0 = state.u8_in(0..=0x1F, "MMC1 CHR bank")?;
+        self.chr_bank_1 = state.u8_in(0..=0x1F, "MMC1 CHR bank")?;
+        self.prg_bank = state.u8_in(0..=0x1F, "MMC1 PRG bank")?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 8 PRG banks and 8 CHR banks, each byte holding its bank number
+    fn mmc1() -> Mmc1 {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank; PRG_BANK_SIZE]).collect();
+        let chr_rom = (0..8).flat_map(|bank| vec![bank; CHR_BANK_SIZE]).collect();
+        Mmc1::new(prg_rom, Chr::new(chr_rom, 0))
+    }
+
+    fn write_serial(mmc1: &mut Mmc1, addr: u16, value: u8) {
+        for bit in 0..5 {
+            mmc1.write_prg(addr, (value >> bit) & 1);
+        }
+    }
+
+    #[test]
+    fn powers_up_with_last_bank_fixed() {
+        let mmc1 = mmc1();
+        assert_eq!(mmc1.read_prg(0x8000), 0);
+        assert_eq!(mmc1.read_prg(0xFFFF), 7);
+    }
+
+    #[test]
+    fn switches_prg_bank_at_8000() {
+        let mut mmc1 = mmc1();
+        write_serial(&mut mmc1, 0xE000, 3);
+        assert_eq!(mmc1.read_prg(0x8000), 3);
+        assert_eq!(mmc1.read_prg(0xC000), 7);
+    }
+
+    #[test]
+    fn fixes_first_bank_in_mode_2() {
+        let mut mmc1 = mmc1();
+        write_serial(&mut mmc1, 0x8000, 0b0_1000);
+        write_serial(&mut mmc1, 0xE000, 5);
+        assert_eq!(mmc1.read_prg(0x8000), 0);
+        assert_eq!(mmc1.read_prg(0xC000), 5);
+    }
+
+    #[test]
+    fn switches_32k_ignoring_low_bit() {
+        let mut mmc1 = mmc1();
+        write_serial(&mut mmc1, 0x8000, 0b0_0000);
+        write_serial(&mut mmc1, 0xE000, 5);
+        assert_eq!(mmc1.read_prg(0x8000), 4);
+        assert_eq!(mmc1.read_prg(0xC000), 5);
+    }
+
+    #[test]
+    fn switches_chr_banks() {
+        let mut mmc1 = mmc1();
+        // two 4 KiB banks
+        write_serial(&mut mmc1, 0x8000, 0b1_1100);
+        write_serial(&mut mmc1, 0xA000, 3);
+        write_serial(&mut mmc1, 0xC000, 6);
+        assert_eq!(mmc1.read_chr(0x0000), 3);
+        assert_eq!(mmc1.read_chr(0x1000), 6);
+
+        // one 8 KiB bank
+        write_serial(&mut mmc1, 0x8000, 0b0_1100);
+        assert_eq!(mmc1.read_chr(0x0000), 2);
+        assert_eq!(mmc1.read_chr(0x1000), 3);
+    }
+
+    #[test]
+    fn controls_mirroring() {
+        let mut mmc1 = mmc1();
+        write_serial(&mut mmc1, 0x8000, 0b0_1110);
+        assert_eq!(mmc1.mirroring(), Mirroring::Vertical);
+        write_serial(&mut mmc1, 0x8000, 0b0_1101);
+        assert_eq!(mmc1.mirroring(), Mirroring::SingleScreenUpper);
+    }
+
+    #[test]
+    fn reset_bit_clears_shift_register() {
+        let mut mmc1 = mmc1();
+        mmc1.write_prg(0xE000, 1);
+        mmc1.write_prg(0xE000, 1);
+        mmc1.write_prg(0x8000, 0x80);
+        write_serial(&mut mmc1, 0xE000, 2);
+        assert_eq!(mmc1.read_prg(0x8000), 2);
+    }
+
+    #[test]
+    fn restores_banks_and_partial_write_from_state() {
+        let mut mmc1 = mmc1();
+        write_serial(&mut mmc1, 0xE000, 3);
+        // two bits of the next write already shifted in
+        mmc1.write_prg(0xE000, 1);
+        mmc1.write_prg(0xE000, 0);
+        let mut state = StateWriter::new(0);
+        mmc1.save_state(&mut state);
+        let data = state.finish();
+
+        let mut restored = self::mmc1();
+        let mut state = StateReader::new(&data, 0).unwrap();
+        restored.load_state(&mut state).unwrap();
+        assert_eq!(state.finish(), Ok(()));
+        assert_eq!(restored.read_prg(0x8000), 3);
+        // the remaining three bits complete 0b00101
+        for bit in [1, 0, 0] {
+            restored.write_prg(0xE000, bit);
+        }
+        assert_eq!(restored.read_prg(0x8000), 5);
+    }
+}
@@ -0,0 +1,155 @@
+mod axrom;
+mod camerica;
+mod cnrom;
+mod mmc1;
+mod nrom;
+mod rambo1;
+mod uxrom;
+
+use crate::cartridge::{mapper_name, Mirroring, Rom, RomError};
+use crate::state::{StateError, StateReader, StateWriter};
+
+pub(crate) use axrom::Axrom;
+pub(crate) use camerica::Camerica;
+pub(crate) use cnrom::Cnrom;
+pub(crate) use mmc1::Mmc1;
+pub(crate) use nrom::Nrom;
+pub(crate) use rambo1::Rambo1;
+pub(crate) use uxrom::Uxrom;
+
+// the cartridge side of both buses: PRG at CPU 0x8000-0xFFFF and CHR at
+// PPU 0x0000-0x1FFF, addresses are passed through unchanged
+pub trait Mapper: Send {
+    fn read_prg(&self, addr: u16) -> u8;
+
+    // writes into the ROM area usually land in mapper registers
+    fn write_prg(&mut self, addr: u16, data: u8);
+
+    // writes straight into the ROM byte currently mapped at addr
+    fn poke_prg(&mut self, addr: u16, data: u8);
+
+    // the PRG ROM bank mapped at addr right now, in the board's own bank size
+    fn prg_bank(&self, addr: u16) -> usize;
+
+    fn read_chr(&self, addr: u16) -> u8;
+
+    fn write_chr(&mut self, addr: u16, data: u8);
+
+    fn mirroring(&self) -> Mirroring;
+
+    // the whole CHR, ignoring banking
+    fn chr(&self) -> &Chr;
+
+    fn chr_mut(&mut self) -> &mut Chr;
+
+    // called with the cycles each CPU instruction took
+    fn clock_cpu(&mut self, _cycles: u16) {}
+
+    // called once per rendered scanline by the PPU
+    fn clock_scanline(&mut self) {}
+
+    // level-triggered IRQ line into the CPU
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // bank registers and CHR RAM, everything but the ROM itself
+    fn save_state(&self, state: &mut StateWriter);
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError>;
+}
+
+pub const SUPPORTED: [u16; 7] = [0, 1, 2, 3, 7, 64, 71];
+
+pub fn for_rom(rom: Rom) -> Result<Box<dyn Mapper>, RomError> {
+    let header = *rom.header();
+    let chr = Chr::new(rom.chr_rom, header.chr_ram_size);
+    match header.mapper {
+        0 => Ok(Box::new(Nrom::new(rom.prg_rom, chr, header.mirroring))),
+        1 => Ok(Box::new(Mmc1::new(rom.prg_rom, chr))),
+        2 => Ok(Box::new(Uxrom::new(rom.prg_rom, chr, header.mirroring))),
+        3 => Ok(Box::new(Cnrom::new(rom.prg_rom, chr, header.mirroring))),
+        7 => Ok(Box::new(Axrom::new(rom.prg_rom, chr))),
+        64 => Ok(Box::new(Rambo1::new(rom.prg_rom, chr))),
+        71 => Ok(Box::new(Camerica::new(
+            rom.prg_rom,
+            chr,
+            header.mirroring,
+            header.submapper,
+        ))),
+        mapper => Err(RomError::UnsupportedMapper {
+            mapper,
+            name: mapper_name(mapper),
+            supported: &SUPPORTED,
+        }),
+    }
+}
+
+// CHR ROM, or CHR RAM when the cartridge has no CHR ROM
+pub struct Chr {
+    data: Vec<u8>,
+    writable: bool,
+}
+
+impl Chr {
+    pub(crate) fn new(chr_rom: Vec<u8>, ram_size: usize) -> Self {
+        if chr_rom.is_empty() {
+            Chr {
+                data: vec![0; ram_size.max(0x2000)],
+                writable: true,
+            }
+        } else {
+            Chr {
+                data: chr_rom,
+                writable: false,
+            }
+        }
+    }
+
+    fn read(&self, offset: usize) -> u8 {
+        self.data[offset % self.data.len()]
+    }
+
+    fn write(&mut self, offset: usize, data: u8) {
+        if self.writable {
+            let len = self.data.len();
+            self.data[offset % len] = data;
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    // only CHR RAM changes while running
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bytes(if self.writable { &self.data } else { &[] });
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        if self.writable {
+            state.bytes_into(&mut self.data)
+        } else {
+            state.bytes_into(&mut [])
+        }
+    }
+
+    pub fn is_ram(&self) -> bool {
+        self.writable
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    // writable even when it is ROM, for tools that edit graphics
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+}
+
+// offset of byte `offset` within bank `bank`, with out-of-range banks
+// wrapping around the way unconnected address lines do
+fn bank_offset(bank: usize, bank_size: usize, offset: usize, len: usize) -> usize {
+    (bank * bank_size + offset) % len
+}
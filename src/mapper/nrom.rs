@@ -0,0 +1,122 @@
+use super::{Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+// mapper 0: no bank switching, a 16 KiB PRG ROM is mirrored at 0xC000
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr, mirroring: Mirroring) -> Self {
+        Nrom {
+            prg_rom,
+            chr,
+            mirroring,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        (addr - 0x8000) as usize % self.prg_rom.len()
+    }
+}
+
+// a 32 KiB PRG ROM of one byte and CHR RAM, for tests that need some mapper
+#[cfg(test)]
+impl Nrom {
+    pub(crate) fn filled(prg_byte: u8) -> Self {
+        Nrom::new(
+            vec![prg_byte; 0x8000],
+            Chr::new(Vec::new(), 0),
+            Mirroring::Horizontal,
+        )
+    }
+
+    pub(crate) fn blank(mirroring: Mirroring) -> Self {
+        Nrom {
+            mirroring,
+            ..Nrom::filled(0)
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, _addr: u16, _data: u8) {}
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    // no PRG banking, the whole ROM is bank 0
+    fn prg_bank(&self, _addr: u16) -> usize {
+        0
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn mirrors_16k_prg_rom() {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x3FFC] = 0x12;
+        let nrom = Nrom::new(prg_rom, Chr::new(Vec::new(), 0), Mirroring::Vertical);
+        assert_eq!(nrom.read_prg(0xBFFC), 0x12);
+        assert_eq!(nrom.read_prg(0xFFFC), 0x12);
+    }
+
+    #[test]
+    fn chr_ram_is_writable_and_chr_rom_is_not() {
+        let mut nrom = Nrom::new(
+            vec![0; 0x4000],
+            Chr::new(Vec::new(), 0),
+            Mirroring::Vertical,
+        );
+        nrom.write_chr(0x0123, 0x45);
+        assert_eq!(nrom.read_chr(0x0123), 0x45);
+
+        let mut nrom = Nrom::new(
+            vec![0; 0x4000],
+            Chr::new(vec![0x11; 0x2000], 0),
+            Mirroring::Vertical,
+        );
+        nrom.write_chr(0x0123, 0x45);
+        assert_eq!(nrom.read_chr(0x0123), 0x11);
+    }
+}
@@ -0,0 +1,314 @@
+use super::{bank_offset, Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const CPU_CYCLES_PER_CLOCK: u8 = 4;
+
+// mapper 64, Tengen RAMBO-1: MMC3-like banking with an extra PRG bank, 1 KiB
+// CHR banks for the whole pattern table and an IRQ counter that can be
+// clocked by scanlines or by every fourth CPU cycle
+pub struct Rambo1 {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    // register index (bits 0-3), 1 KiB CHR mode (bit 5), PRG mode (bit 6),
+    // CHR A12 inversion (bit 7)
+    bank_select: u8,
+    // R0-R5, R8, R9 select CHR banks, R6, R7 and R15 select PRG banks
+    banks: [u8; 16],
+    mirroring: Mirroring,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_cpu_mode: bool,
+    irq_prescaler: u8,
+    irq_pending: bool,
+}
+
+impl Rambo1 {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr) -> Self {
+        Rambo1 {
+            prg_rom,
+            chr,
+            bank_select: 0,
+            banks: [0; 16],
+            mirroring: Mirroring::Vertical,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_cpu_mode: false,
+            irq_prescaler: 0,
+            irq_pending: false,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let prg_mode = self.bank_select & 0b0100_0000 != 0;
+        let last = (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1);
+        let bank = match (addr, prg_mode) {
+            (0x8000..=0x9FFF, false) | (0xA000..=0xBFFF, true) => self.banks[6] as usize,
+            (0xA000..=0xBFFF, false) | (0xC000..=0xDFFF, true) => self.banks[7] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => self.banks[15] as usize,
+            _ => last,
+        };
+        bank_offset(
+            bank,
+            PRG_BANK_SIZE,
+            (addr & 0x1FFF) as usize,
+            self.prg_rom.len(),
+        )
+    }
+
+    fn chr_offset(&self, addr: u16) -> usize {
+        let addr = if self.bank_select & 0b1000_0000 != 0 {
+            addr ^ 0x1000
+        } else {
+            addr
+        };
+        let one_k = self.bank_select & 0b0010_0000 != 0;
+        let r = &self.banks;
+        let bank = match (addr / CHR_BANK_SIZE as u16, one_k) {
+            (0, false) => r[0] & !1,
+            (1, false) => r[0] | 1,
+            (2, false) => r[1] & !1,
+            (3, false) => r[1] | 1,
+            (0, true) => r[0],
+            (1, true) => r[8],
+            (2, true) => r[1],
+            (3, true) => r[9],
+            (slot, _) => r[slot as usize - 2],
+        };
+        bank_offset(
+            bank as usize,
+            CHR_BANK_SIZE,
+            (addr & 0x03FF) as usize,
+            self.chr.len(),
+        )
+    }
+
+    fn clock_irq_counter(&mut self) {
+        if self.irq_reload {
+            self.irq_reload = false;
+            // a reload after writing $C001 counts one extra clock
+            self.irq_counter = self.irq_latch.saturating_add(1);
+        } else if self.irq_counter == 0 {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        if self.irq_counter == 0 && self.irq_enabled {
+            self.irq_pending = true;
+        }
+    }
+}
+
+impl Mapper for Rambo1 {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, addr: u16, data: u8) {
+        match (addr, addr & 1 == 0) {
+            (0x8000..=0x9FFF, true) => self.bank_select = data,
+            (0x8000..=0x9FFF, false) => self.banks[(self.bank_select & 0b1111) as usize] = data,
+            (0xA000..=0xBFFF, true) => {
+                self.mirroring = if data & 1 == 0 {
+                    Mirroring::Vertical
+                } else {
+                    Mirroring::Horizontal
+                }
+            }
+            (0xC000..=0xDFFF, true) => self.irq_latch = data,
+            (0xC000..=0xDFFF, false) => {
+                self.irq_cpu_mode = data & 1 != 0;
+                self.irq_prescaler = 0;
+                self.irq_reload = true;
+            }
+            (0xE000..=0xFFFF, true) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            (0xE000..=0xFFFF, false) => self.irq_enabled = true,
+            _ => {}
+        }
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        self.prg_offset(addr) / PRG_BANK_SIZE
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(self.chr_offset(addr))
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(self.chr_offset(addr), data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn clock_cpu(&mut self, cycles: u16) {
+        if !self.irq_cpu_mode {
+            return;
+        }
+        for _ in 0..cycles {
+            self.irq_prescaler += 1;
+            if self.irq_prescaler == CPU_CYCLES_PER_CLOCK {
+                self.irq_prescaler = 0;
+                self.clock_irq_counter();
+            }
+        }
+    }
+
+    fn clock_scanline(&mut self) {
+        if !self.irq_cpu_mode {
+            self.clock_irq_counter();
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+        state.u8(self.bank_select);
+        state.bytes(&self.banks);
+        state.mirroring(self.mirroring);
+        state.u8(self.irq_latch);
+        state.u8(self.irq_counter);
+        state.bool(self.irq_reload);
+        state.bool(self.irq_enabled);
+        state.bool(self.irq_cpu_mode);
+        state.u8(self.irq_prescaler);
+        state.bool(self.irq_pending);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)?;
+        self.bank_select = state.u8()?;
+        state.bytes_into(&mut self.banks)?;
+        self.mirroring = state.mirroring()?;
+        self.irq_latch = state.u8()?;
+        self.irq_counter = state.u8()?;
+        self.irq_reload = state.bool()?;
+        self.irq_enabled = state.bool()?;
+        self.irq_cpu_mode = state.bool()?;
+        self.irq_prescaler = state.u8()?;
+        self.irq_pending = state.bool()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // 16 PRG banks and 32 CHR banks, each byte holding its bank number
+    fn rambo1() -> Rambo1 {
+        let prg_rom = (0..16).flat_map(|bank| vec![bank; PRG_BANK_SIZE]).collect();
+        let chr_rom = (0..32).flat_map(|bank| vec![bank; CHR_BANK_SIZE]).collect();
+        Rambo1::new(prg_rom, Chr::new(chr_rom, 0))
+    }
+
+    fn set_bank(mapper: &mut Rambo1, register: u8, bank: u8) {
+        let mode = mapper.bank_select & 0b1110_0000;
+        mapper.write_prg(0x8000, mode | register);
+        mapper.write_prg(0x8001, bank);
+    }
+
+    #[test]
+    fn switches_prg_banks_in_both_modes() {
+        let mut mapper = rambo1();
+        set_bank(&mut mapper, 6, 1);
+        set_bank(&mut mapper, 7, 2);
+        set_bank(&mut mapper, 15, 3);
+        let banks =
+            |mapper: &Rambo1| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mapper.read_prg(addr));
+        assert_eq!(banks(&mapper), [1, 2, 3, 15]);
+
+        mapper.write_prg(0x8000, 0b0100_0000);
+        assert_eq!(banks(&mapper), [3, 1, 2, 15]);
+    }
+
+    #[test]
+    fn switches_chr_banks() {
+        let mut mapper = rambo1();
+        for (register, bank) in [(0, 4), (1, 6), (2, 10), (3, 11), (4, 12), (5, 13)] {
+            set_bank(&mut mapper, register, bank);
+        }
+        set_bank(&mut mapper, 8, 20);
+        set_bank(&mut mapper, 9, 21);
+        let slots = |mapper: &Rambo1| {
+            (0..8)
+                .map(|slot| mapper.read_chr(slot * 0x400))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(slots(&mapper), vec![4, 5, 6, 7, 10, 11, 12, 13]);
+
+        // 1 KiB mode brings in R8 and R9
+        mapper.write_prg(0x8000, 0b0010_0000);
+        assert_eq!(slots(&mapper), vec![4, 20, 6, 21, 10, 11, 12, 13]);
+
+        // A12 inversion swaps the pattern table halves
+        mapper.write_prg(0x8000, 0b1010_0000);
+        assert_eq!(slots(&mapper), vec![10, 11, 12, 13, 4, 20, 6, 21]);
+    }
+
+    #[test]
+    fn controls_mirroring() {
+        let mut mapper = rambo1();
+        mapper.write_prg(0xA000, 1);
+        assert_eq!(mapper.mirroring(), Mirroring::Horizontal);
+        mapper.write_prg(0xA000, 0);
+        assert_eq!(mapper.mirroring(), Mirroring::Vertical);
+    }
+
+    #[test]
+    fn counts_cpu_cycles_for_irq() {
+        let mut mapper = rambo1();
+        mapper.write_prg(0xC000, 2);
+        mapper.write_prg(0xC001, 1);
+        mapper.write_prg(0xE001, 0);
+        // reload to 3, then 2, 1, 0 at four cycles per clock
+        mapper.clock_cpu(15);
+        assert!(!mapper.irq());
+        mapper.clock_cpu(1);
+        assert!(mapper.irq());
+
+        mapper.write_prg(0xE000, 0);
+        assert!(!mapper.irq());
+    }
+
+    #[test]
+    fn counts_scanlines_for_irq() {
+        let mut mapper = rambo1();
+        mapper.write_prg(0xC000, 1);
+        mapper.write_prg(0xC001, 0);
+        mapper.write_prg(0xE001, 0);
+        mapper.clock_cpu(100);
+        mapper.clock_scanline();
+        mapper.clock_scanline();
+        assert!(!mapper.irq());
+        mapper.clock_scanline();
+        assert!(mapper.irq());
+    }
+}
@@ -0,0 +1,102 @@
+use super::{bank_offset, Chr, Mapper};
+use crate::cartridge::Mirroring;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// mapper 2: a switchable 16 KiB bank at 0x8000 and the last bank fixed at
+// 0xC000, any write into ROM selects the bank
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: Chr,
+    mirroring: Mirroring,
+    bank: u8,
+}
+
+impl Uxrom {
+    pub fn new(prg_rom: Vec<u8>, chr: Chr, mirroring: Mirroring) -> Self {
+        Uxrom {
+            prg_rom,
+            chr,
+            mirroring,
+            bank: 0,
+        }
+    }
+
+    fn prg_offset(&self, addr: u16) -> usize {
+        let bank = match addr {
+            0x8000..=0xBFFF => self.bank as usize,
+            _ => (self.prg_rom.len() / PRG_BANK_SIZE).saturating_sub(1),
+        };
+        bank_offset(
+            bank,
+            PRG_BANK_SIZE,
+            (addr & 0x3FFF) as usize,
+            self.prg_rom.len(),
+        )
+    }
+}
+
+impl Mapper for Uxrom {
+    fn read_prg(&self, addr: u16) -> u8 {
+        self.prg_rom[self.prg_offset(addr)]
+    }
+
+    fn write_prg(&mut self, _addr: u16, data: u8) {
+        self.bank = data;
+    }
+
+    fn poke_prg(&mut self, addr: u16, data: u8) {
+        let offset = self.prg_offset(addr);
+        self.prg_rom[offset] = data;
+    }
+
+    fn prg_bank(&self, addr: u16) -> usize {
+        self.prg_offset(addr) / PRG_BANK_SIZE
+    }
+
+    fn read_chr(&self, addr: u16) -> u8 {
+        self.chr.read(addr as usize)
+    }
+
+    fn write_chr(&mut self, addr: u16, data: u8) {
+        self.chr.write(addr as usize, data);
+    }
+
+    fn chr(&self) -> &Chr {
+        &self.chr
+    }
+
+    fn chr_mut(&mut self) -> &mut Chr {
+        &mut self.chr
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        self.chr.save_state(state);
+        state.u8(self.bank);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.chr.load_state(state)?;
+        self.bank = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn switches_low_bank_and_fixes_last() {
+        let prg_rom = (0..8).flat_map(|bank| vec![bank; PRG_BANK_SIZE]).collect();
+        let mut mapper = Uxrom::new(prg_rom, Chr::new(Vec::new(), 0), Mirroring::Vertical);
+        assert_eq!((mapper.read_prg(0x8000), mapper.read_prg(0xC000)), (0, 7));
+        mapper.write_prg(0x8000, 5);
+        assert_eq!((mapper.read_prg(0xBFFF), mapper.read_prg(0xFFFF)), (5, 7));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::cartridge::{Rom, RomError};
+use crate::cpu::{CpuError, Interrupt, CPU};
+use crate::input::InputDevice;
+use crate::joypad::Joypad;
+use crate::ppu::{Frame, FrameMailbox, IndexedFrame, PixelFormat};
+use crate::state::{StateError, StateReader, StateWriter};
+use crate::zapper::Zapper;
+use std::sync::Arc;
+
+// the console as a whole, for frontends that think in frames rather than
+// instructions
+pub struct Nes {
+    cpu: CPU,
+    // identifies the cartridge in save states
+    rom_crc32: u32,
+    nmis: u64,
+    frames: u64,
+    // frames run ahead of the real one, and the picture from there
+    run_ahead: u8,
+    shown: Option<Frame>,
+}
+
+impl Nes {
+    // switched on, so the PPU starts out ignoring some writes
+    pub fn new() -> Self {
+        let mut cpu = CPU::new();
+        cpu.bus_mut().ppu_mut().power_up();
+        Nes {
+            cpu,
+            rom_crc32: 0,
+            nmis: 0,
+            frames: 0,
+            run_ahead: 0,
+            shown: None,
+        }
+    }
+
+    // loads a cartridge and presses reset
+    pub fn load(&mut self, rom: Rom) -> Result<(), RomError> {
+        let rom_crc32 = rom.content_crc32();
+        self.cpu.load(rom)?;
+        self.rom_crc32 = rom_crc32;
+        self.cpu.reset();
+        self.shown = None;
+        Ok(())
+    }
+
+    pub fn reset(&mut self) {
+        self.cpu.reset();
+        self.shown = None;
+    }
+
+    // hides a game's own input lag: after each real frame the console
+    // runs on this many frames with the same input, keeps that picture
+    // and goes back. 0 turns it off
+    pub fn set_run_ahead(&mut self, frames: u8) {
+        self.run_ahead = frames;
+        self.shown = None;
+    }
+
+    // runs instructions until the PPU reaches VBlank, so each call yields
+    // one finished picture
+    pub fn run_frame(&mut self) -> Result<&Frame, CpuError> {
+        if self.run_ahead == 0 {
+            self.step_frame()?;
+            return Ok(self.frame());
+        }
+        // only the picture that is shown reaches the mailbox
+        let mailbox = self.cpu.bus_mut().ppu_mut().take_frame_mailbox();
+        let real = self.step_frame();
+        if real.is_ok() {
+            self.look_ahead(&mailbox);
+        }
+        self.cpu.bus_mut().ppu_mut().put_frame_mailbox(mailbox);
+        real?;
+        Ok(self.frame())
+    }
+
+    fn step_frame(&mut self) -> Result<(), CpuError> {
+        loop {
+            if self.cpu.step()?.interrupt == Some(Interrupt::Nmi) {
+                self.nmis += 1;
+            }
+            if self.cpu.bus_mut().take_frame_complete() {
+                self.frames += 1;
+                return Ok(());
+            }
+        }
+    }
+
+    // a crash out there shows up again once the real frames get to it,
+    // until then the real picture is shown
+    fn look_ahead(&mut self, mailbox: &Option<Arc<FrameMailbox>>) {
+        let state = self.save_state();
+        let (nmis, frames) = (self.nmis, self.frames);
+        self.cpu.bus_mut().apu_mut().set_muted(true);
+        let mut ahead = Ok(());
+        for frame in 1..=self.run_ahead {
+            if frame == self.run_ahead {
+                let ppu = self.cpu.bus_mut().ppu_mut();
+                ppu.put_frame_mailbox(mailbox.clone());
+            }
+            ahead = self.step_frame();
+            if ahead.is_err() {
+                break;
+            }
+        }
+        self.cpu.bus_mut().apu_mut().set_muted(false);
+        match ahead {
+            Ok(()) => {
+                let picture = self.cpu.bus().ppu().frame();
+                let shown = self.shown.get_or_insert_with(Frame::new);
+                shown.data.copy_from_slice(&picture.data);
+            }
+            Err(_) => self.shown = None,
+        }
+        StateReader::new(&state, self.rom_crc32)
+            .and_then(|mut reader| self.cpu.load_state(&mut reader))
+            .expect("run-ahead reloads its own state");
+        (self.nmis, self.frames) = (nmis, frames);
+    }
+
+    // None unless the cartridge has a battery
+    pub fn sram(&self) -> Option<&[u8]> {
+        self.cpu.bus().sram()
+    }
+
+    pub fn load_sram(&mut self, data: &[u8]) {
+        self.cpu.bus_mut().load_sram(data);
+    }
+
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new(self.rom_crc32);
+        self.cpu.save_state(&mut state);
+        state.finish()
+    }
+
+    // a snapshot that fails part way through leaves the console as it was
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut state = StateReader::new(data, self.rom_crc32)?;
+        let backup = self.save_state();
+        let result = self
+            .cpu
+            .load_state(&mut state)
+            .and_then(|()| state.finish());
+        if result.is_err() {
+            let mut state = StateReader::new(&backup, self.rom_crc32)?;
+            self.cpu.load_state(&mut state)?;
+        } else {
+            self.shown = None;
+        }
+        result
+    }
+
+    // with run-ahead, the picture from the frames ahead
+    pub fn frame(&self) -> &Frame {
+        match &self.shown {
+            Some(shown) => shown,
+            None => self.cpu.bus().ppu().frame(),
+        }
+    }
+
+    // the same frame as palette indices, for recordings and comparisons
+    pub fn indexed_frame(&self) -> &IndexedFrame {
+        self.cpu.bus().ppu().indexed_frame()
+    }
+
+    // the last frame in whatever format the frontend draws with
+    pub fn write_frame(&self, format: PixelFormat, out: &mut Vec<u8>) {
+        match format {
+            PixelFormat::Indexed8 => self.indexed_frame().write_indexed8(out),
+            _ => self.frame().write_rgb(format, out),
+        }
+    }
+
+    // frames run since power on, for playtime statistics
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    // NMIs taken inside run_frame since power on
+    pub fn nmis(&self) -> u64 {
+        self.nmis
+    }
+
+    // port 0 or 1, standard controllers until something else goes in
+    pub fn plug(&mut self, port: usize, device: impl InputDevice) {
+        self.cpu.bus_mut().plug(port, device)
+    }
+
+    pub fn device_mut<T: InputDevice>(&mut self, port: usize) -> Option<&mut T> {
+        self.cpu.bus_mut().device_mut(port)
+    }
+
+    pub fn joypad_mut(&mut self, port: usize) -> Option<&mut Joypad> {
+        self.device_mut(port)
+    }
+
+    // the light gun is normally in the second port
+    pub fn zapper_mut(&mut self) -> Option<&mut Zapper> {
+        self.device_mut(1)
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+}
+
+impl Default for Nes {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,92 @@
+use crate::input::InputDevice;
+use crate::ppu::Ppu;
+use crate::state::{StateError, StateReader, StateWriter};
+
+const BUTTON: u8 = 0b0000_1000;
+const DATA: u8 = 0b0001_0000;
+
+// the Arkanoid controller: the strobe latches the knob's 8-bit position,
+// which then shifts out MSB first and inverted on D4, next to the button
+// on D3
+#[derive(Debug, Clone, Default)]
+pub struct Paddle {
+    position: u8,
+    button: bool,
+    strobe: bool,
+    shift: u8,
+}
+
+impl Paddle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // games expect roughly 0x62 at the far left to 0xF2 at the far right
+    pub fn set_position(&mut self, position: u8) {
+        self.position = position;
+    }
+
+    pub fn set_button(&mut self, pressed: bool) {
+        self.button = pressed;
+    }
+}
+
+impl InputDevice for Paddle {
+    fn strobe(&mut self, high: bool) {
+        self.strobe = high;
+        if self.strobe {
+            self.shift = self.position;
+        }
+    }
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        let data = self.peek(ppu);
+        if !self.strobe {
+            self.shift <<= 1;
+        }
+        data
+    }
+
+    fn peek(&self, _ppu: &Ppu) -> u8 {
+        let mut data = 0;
+        if self.shift & 0x80 == 0 {
+            data |= DATA;
+        }
+        if self.button {
+            data |= BUTTON;
+        }
+        data
+    }
+
+    fn save_state(&self, state: &mut StateWriter) {
+        state.bool(self.strobe);
+        state.u8(self.shift);
+    }
+
+    fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.strobe = state.bool()?;
+        self.shift = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn shifts_out_position_inverted() {
+        let ppu = Ppu::new();
+        let mut paddle = Paddle::new();
+        paddle.set_position(0b1010_0011);
+        paddle.write(1);
+        paddle.write(0);
+        let bits: Vec<_> = (0..8).map(|_| paddle.read(&ppu) & DATA != 0).collect();
+        assert_eq!(
+            bits,
+            vec![false, true, false, true, true, true, false, false]
+        );
+        paddle.set_button(true);
+        assert_eq!(paddle.read(&ppu), DATA | BUTTON);
+    }
+}
@@ -0,0 +1,444 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::cartridge::{Rom, RomError};
+use crate::checksum::crc32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatchError {
+    BadHeader,
+    Truncated,
+    OutOfBounds,
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+    PatchChecksum { expected: u32, actual: u32 },
+    BadRom(RomError),
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchError::BadHeader => write!(f, "not an IPS/BPS patch"),
+            PatchError::Truncated => write!(f, "patch ends unexpectedly"),
+            PatchError::OutOfBounds => write!(f, "patch refers to data outside the file"),
+            PatchError::SourceChecksum { expected, actual } => write!(
+                f,
+                "patch is for a different ROM (crc32 {actual:08x}, expected {expected:08x})"
+            ),
+            PatchError::TargetChecksum { expected, actual } => write!(
+                f,
+                "patched ROM is corrupt (crc32 {actual:08x}, expected {expected:08x})"
+            ),
+            PatchError::PatchChecksum { expected, actual } => write!(
+                f,
+                "patch file is corrupt (crc32 {actual:08x}, expected {expected:08x})"
+            ),
+            PatchError::BadRom(err) => write!(f, "patched ROM is invalid: {err}"),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0 }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(len).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<usize, PatchError> {
+        let b = self.bytes(2)?;
+        Ok((b[0] as usize) << 8 | b[1] as usize)
+    }
+
+    fn u24_be(&mut self) -> Result<usize, PatchError> {
+        let b = self.bytes(3)?;
+        Ok((b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize)
+    }
+
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut data: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let x = self.byte()?;
+            data = (x as usize & 0x7f)
+                .checked_mul(shift)
+                .and_then(|v| data.checked_add(v))
+                .ok_or(PatchError::OutOfBounds)?;
+            if x & 0x80 != 0 {
+                return Ok(data);
+            }
+            shift = shift.checked_shl(7).ok_or(PatchError::OutOfBounds)?;
+            data = data.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+
+    fn remaining(&self) -> usize {
+        self.data.len() - self.pos
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchFormat {
+    Ips,
+    Bps,
+}
+
+impl PatchFormat {
+    pub fn apply(self, rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+        match self {
+            PatchFormat::Ips => apply_ips(rom, patch),
+            PatchFormat::Bps => apply_bps(rom, patch),
+        }
+    }
+}
+
+// patches apply to the whole file, header included
+impl Rom {
+    pub fn apply_ips(&self, patch: &[u8]) -> Result<Rom, PatchError> {
+        Rom::from_bytes(&apply_ips(&self.to_bytes(), patch)?).map_err(PatchError::BadRom)
+    }
+
+    pub fn apply_bps(&self, patch: &[u8]) -> Result<Rom, PatchError> {
+        Rom::from_bytes(&apply_bps(&self.to_bytes(), patch)?).map_err(PatchError::BadRom)
+    }
+}
+
+// looks for `game.ips` or `game.bps` next to `game.nes`
+pub fn find_sidecar(rom_path: &Path) -> Option<(PathBuf, PatchFormat)> {
+    [("ips", PatchFormat::Ips), ("bps", PatchFormat::Bps)]
+        .into_iter()
+        .map(|(ext, format)| (rom_path.with_extension(ext), format))
+        .find(|(path, _)| path.is_file())
+}
+
+const IPS_EOF: usize = 0x454F46;
+
+pub fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut reader = Reader::new(patch);
+    if reader.bytes(5)? != b"PATCH" {
+        return Err(PatchError::BadHeader);
+    }
+
+    let mut out = rom.to_vec();
+    loop {
+        let offset = reader.u24_be()?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let size = reader.u16_be()?;
+        let (len, data) = if size == 0 {
+            // RLE record: a run of a single byte
+            let len = reader.u16_be()?;
+            (len, None)
+        } else {
+            (size, Some(reader.bytes(size)?))
+        };
+        if out.len() < offset + len {
+            out.resize(offset + len, 0);
+        }
+        match data {
+            Some(data) => out[offset..offset + len].copy_from_slice(data),
+            None => out[offset..offset + len].fill(reader.byte()?),
+        }
+    }
+
+    // optional truncation extension
+    if reader.remaining() >= 3 {
+        let len = reader.u24_be()?;
+        out.truncate(len);
+    }
+    Ok(out)
+}
+
+pub fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < 4 + 12 || &patch[..4] != b"BPS1" {
+        return Err(PatchError::BadHeader);
+    }
+
+    let footer = &patch[patch.len() - 12..];
+    let word =
+        |i: usize| u32::from_le_bytes([footer[i], footer[i + 1], footer[i + 2], footer[i + 3]]);
+    let (source_crc, target_crc, patch_crc) = (word(0), word(4), word(8));
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc {
+        return Err(PatchError::PatchChecksum {
+            expected: patch_crc,
+            actual,
+        });
+    }
+    let actual = crc32(rom);
+    if actual != source_crc {
+        return Err(PatchError::SourceChecksum {
+            expected: source_crc,
+            actual,
+        });
+    }
+
+    let mut reader = Reader::new(&patch[4..patch.len() - 12]);
+    let source_size = reader.varint()?;
+    let target_size = reader.varint()?;
+    let metadata_size = reader.varint()?;
+    reader.bytes(metadata_size)?;
+    if source_size != rom.len() {
+        return Err(PatchError::OutOfBounds);
+    }
+
+    // the target size comes from the patch, so don't trust it for allocation
+    let mut out: Vec<u8> = Vec::with_capacity(target_size.min(rom.len() + patch.len()));
+    let mut source_offset: isize = 0;
+    let mut target_offset: isize = 0;
+    while reader.remaining() > 0 {
+        let data = reader.varint()?;
+        let len = (data >> 2) + 1;
+        if out.len() + len > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+        match data & 3 {
+            // SourceRead
+            0 => {
+                let start = out.len();
+                let bytes = rom.get(start..start + len).ok_or(PatchError::OutOfBounds)?;
+                out.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(reader.bytes(len)?),
+            // SourceCopy
+            2 => {
+                source_offset = advance(source_offset, relative_offset(reader.varint()?))?;
+                let start = usize::try_from(source_offset).map_err(|_| PatchError::OutOfBounds)?;
+                let end = start.checked_add(len).ok_or(PatchError::OutOfBounds)?;
+                let bytes = rom.get(start..end).ok_or(PatchError::OutOfBounds)?;
+                out.extend_from_slice(bytes);
+                source_offset = advance(source_offset, len as isize)?;
+            }
+            // TargetCopy, may overlap the bytes being written
+            _ => {
+                target_offset = advance(target_offset, relative_offset(reader.varint()?))?;
+                for _ in 0..len {
+                    let byte = usize::try_from(target_offset)
+                        .ok()
+                        .and_then(|i| out.get(i).copied())
+                        .ok_or(PatchError::OutOfBounds)?;
+                    out.push(byte);
+                    target_offset = advance(target_offset, 1)?;
+                }
+            }
+        }
+    }
+
+    if out.len() != target_size {
+        return Err(PatchError::Truncated);
+    }
+    let actual = crc32(&out);
+    if actual != target_crc {
+        return Err(PatchError::TargetChecksum {
+            expected: target_crc,
+            actual,
+        });
+    }
+    Ok(out)
+}
+
+fn advance(offset: isize, by: isize) -> Result<isize, PatchError> {
+    offset.checked_add(by).ok_or(PatchError::OutOfBounds)
+}
+
+fn relative_offset(data: usize) -> isize {
+    let magnitude = (data >> 1) as isize;
+    if data & 1 != 0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn varint(mut data: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (data & 0x7f) as u8;
+            data >>= 7;
+            if data == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            data -= 1;
+        }
+    }
+
+    fn bps(source: &[u8], target: &[u8], actions: &[u8]) -> Vec<u8> {
+        let mut patch = b"BPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn applies_ips_records() {
+        let rom = vec![0u8; 8];
+        let patch = b"PATCH\x00\x00\x02\x00\x02\xaa\xbbEOF";
+        assert_eq!(
+            apply_ips(&rom, patch).unwrap(),
+            vec![0, 0, 0xaa, 0xbb, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn applies_ips_rle_and_grows_file() {
+        let rom = vec![0u8; 4];
+        let patch = b"PATCH\x00\x00\x03\x00\x00\x00\x03\x11EOF";
+        assert_eq!(
+            apply_ips(&rom, patch).unwrap(),
+            vec![0, 0, 0, 0x11, 0x11, 0x11]
+        );
+    }
+
+    #[test]
+    fn applies_ips_truncation() {
+        let rom = vec![0u8; 8];
+        let patch = b"PATCHEOF\x00\x00\x02";
+        assert_eq!(apply_ips(&rom, patch).unwrap(), vec![0, 0]);
+    }
+
+    #[test]
+    fn rejects_bad_ips() {
+        assert_eq!(apply_ips(&[], b"PTCH"), Err(PatchError::Truncated));
+        assert_eq!(apply_ips(&[], b"HELLO"), Err(PatchError::BadHeader));
+        assert_eq!(
+            apply_ips(&[], b"PATCH\x00\x00\x00\x00\x04\x01"),
+            Err(PatchError::Truncated)
+        );
+    }
+
+    #[test]
+    fn finds_sidecar_patch() {
+        let dir = std::env::temp_dir().join(format!("nes-sidecar-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.nes");
+        assert_eq!(find_sidecar(&rom), None);
+
+        std::fs::write(dir.join("game.bps"), b"").unwrap();
+        assert_eq!(
+            find_sidecar(&rom),
+            Some((dir.join("game.bps"), PatchFormat::Bps))
+        );
+
+        std::fs::write(dir.join("game.ips"), b"").unwrap();
+        assert_eq!(
+            find_sidecar(&rom),
+            Some((dir.join("game.ips"), PatchFormat::Ips))
+        );
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn applies_bps_actions() {
+        let source = b"hello world".to_vec();
+        let target = b"hello hello!!!!".to_vec();
+        let mut actions = Vec::new();
+        // SourceRead 6 bytes: "hello "
+        varint(5 << 2, &mut actions);
+        // SourceCopy 5 bytes from offset 0: "hello"
+        varint((4 << 2) | 2, &mut actions);
+        varint(0, &mut actions);
+        // TargetRead 1 byte: "!"
+        varint(1, &mut actions);
+        actions.push(b'!');
+        // TargetCopy 3 bytes from offset 11, overlapping itself: "!!!"
+        varint((2 << 2) | 3, &mut actions);
+        varint(11 << 1, &mut actions);
+        let patch = bps(&source, &target, &actions);
+        assert_eq!(apply_bps(&source, &patch).unwrap(), target);
+    }
+
+    #[test]
+    fn rejects_bps_for_wrong_source() {
+        let source = b"abcd".to_vec();
+        let mut actions = Vec::new();
+        varint(3 << 2, &mut actions);
+        let patch = bps(&source, &source, &actions);
+        assert!(matches!(
+            apply_bps(b"abce", &patch),
+            Err(PatchError::SourceChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_corrupt_bps() {
+        let source = b"abcd".to_vec();
+        let mut actions = Vec::new();
+        varint(3 << 2, &mut actions);
+        let mut patch = bps(&source, &source, &actions);
+        patch[5] ^= 0xff;
+        assert!(matches!(
+            apply_bps(&source, &patch),
+            Err(PatchError::PatchChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_bps_with_huge_sizes_and_offsets() {
+        let source = b"abcd".to_vec();
+        let mut patch = b"BPS1".to_vec();
+        varint(source.len(), &mut patch);
+        varint(usize::MAX >> 8, &mut patch);
+        varint(0, &mut patch);
+        // SourceCopy from an offset far past the end of the source
+        varint(2, &mut patch);
+        varint(usize::MAX & !1, &mut patch);
+        patch.extend_from_slice(&crc32(&source).to_le_bytes());
+        patch.extend_from_slice(&[0; 4]);
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(apply_bps(&source, &patch), Err(PatchError::OutOfBounds));
+    }
+
+    #[test]
+    fn patches_rom_including_header() {
+        let raw = crate::cartridge::ines(0, 0, &[0; 0x4000], &[]);
+        let rom = Rom::from_bytes(&raw).unwrap();
+        // set mapper 2 in flags 6 and write the first PRG byte
+        let patch = b"PATCH\x00\x00\x06\x00\x01\x20\x00\x00\x10\x00\x01\xeaEOF";
+        let patched = rom.apply_ips(patch).unwrap();
+        assert_eq!(patched.header().mapper, 2);
+        assert_eq!(patched.prg_rom[0], 0xea);
+
+        let mut actions = Vec::new();
+        varint((raw.len() - 1) << 2, &mut actions);
+        let patch = bps(&raw, &raw, &actions);
+        assert_eq!(rom.apply_bps(&patch).unwrap(), rom);
+
+        let patch = b"PATCH\x00\x00\x00\x00\x01\x00EOF";
+        assert_eq!(
+            rom.apply_ips(patch),
+            Err(PatchError::BadRom(RomError::BadHeader))
+        );
+    }
+}
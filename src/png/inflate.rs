@@ -0,0 +1,287 @@
+use super::PngError;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// the order code length code lengths are stored in
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+// reads deflate's LSB-first bit stream
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn bit(&mut self) -> Result<u32, PngError> {
+        let byte = *self.data.get(self.pos).ok_or(PngError::Truncated)?;
+        let bit = (byte >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(bit as u32)
+    }
+
+    fn bits(&mut self, count: u8) -> Result<u32, PngError> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+// canonical Huffman code, as symbol counts per code length plus the
+// symbols ordered by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut symbols = Vec::with_capacity(lengths.len());
+        for len in 1..16 {
+            for (symbol, _) in lengths.iter().enumerate().filter(|&(_, &l)| l == len) {
+                symbols.push(symbol as u16);
+            }
+        }
+        Huffman { counts, symbols }
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, PngError> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= reader.bit()? as i32;
+            let count = self.counts[len] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(PngError::BadData)
+    }
+}
+
+// decompresses a zlib stream
+// fails with BadData as soon as the output grows past limit, so a small
+// stream cannot expand into gigabytes
+pub fn inflate(data: &[u8], limit: usize) -> Result<Vec<u8>, PngError> {
+    let header = data.get(..2).ok_or(PngError::Truncated)?;
+    if header[0] & 0x0F != 8 || u16::from_be_bytes([header[0], header[1]]) % 31 != 0 {
+        return Err(PngError::BadData);
+    }
+    let mut reader = BitReader {
+        data: &data[2..],
+        pos: 0,
+        bit: 0,
+    };
+    let mut out = Vec::new();
+    loop {
+        let last = reader.bit()? == 1;
+        match reader.bits(2)? {
+            0 => stored(&mut reader, &mut out, limit)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(&mut reader, &mut out, limit, &literals, &distances)?
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(&mut reader)?;
+                codes(&mut reader, &mut out, limit, &literals, &distances)?
+            }
+            _ => return Err(PngError::BadData),
+        }
+        if out.len() > limit {
+            return Err(PngError::BadData);
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+fn stored(reader: &mut BitReader, out: &mut Vec<u8>, limit: usize) -> Result<(), PngError> {
+    reader.align();
+    let header = reader
+        .data
+        .get(reader.pos..reader.pos + 4)
+        .ok_or(PngError::Truncated)?;
+    let len = u16::from_le_bytes([header[0], header[1]]);
+    if !len != u16::from_le_bytes([header[2], header[3]]) {
+        return Err(PngError::BadData);
+    }
+    let start = reader.pos + 4;
+    let bytes = reader
+        .data
+        .get(start..start + len as usize)
+        .ok_or(PngError::Truncated)?;
+    if out.len() + bytes.len() > limit {
+        return Err(PngError::BadData);
+    }
+    out.extend_from_slice(bytes);
+    reader.pos = start + len as usize;
+    Ok(())
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), PngError> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_count = reader.bits(4)? as usize + 4;
+
+    let mut code_lengths = [0; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths);
+
+    let mut lengths = Vec::with_capacity(literal_count + distance_count);
+    while lengths.len() < literal_count + distance_count {
+        let (value, repeat) = match code_lengths.decode(reader)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or(PngError::BadData)?;
+                (previous, 3 + reader.bits(2)?)
+            }
+            17 => (0, 3 + reader.bits(3)?),
+            _ => (0, 11 + reader.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(value, repeat as usize));
+    }
+    if lengths.len() != literal_count + distance_count {
+        return Err(PngError::BadData);
+    }
+    Ok((
+        Huffman::new(&lengths[..literal_count]),
+        Huffman::new(&lengths[literal_count..]),
+    ))
+}
+
+fn codes(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    limit: usize,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> Result<(), PngError> {
+    loop {
+        // a symbol adds at most 258 bytes, so checking once per symbol is enough
+        if out.len() > limit {
+            return Err(PngError::BadData);
+        }
+        let symbol = literals.decode(reader)? as usize;
+        match symbol {
+            0..=255 => out.push(symbol as u8),
+            256 => return Ok(()),
+            _ => {
+                let index = symbol - 257;
+                if index >= LENGTH_BASE.len() {
+                    return Err(PngError::BadData);
+                }
+                let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index])? as usize;
+                let index = distances.decode(reader)? as usize;
+                if index >= DISTANCE_BASE.len() {
+                    return Err(PngError::BadData);
+                }
+                let distance =
+                    DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index])? as usize;
+                if distance > out.len() {
+                    return Err(PngError::BadData);
+                }
+                // copies byte by byte since the source may overlap the output
+                let start = out.len() - distance;
+                for i in 0..len {
+                    out.push(out[start + i]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn inflates_stored_block() {
+        let data = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert_eq!(inflate(&data, 128).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn inflates_fixed_huffman_with_back_references() {
+        let data = [
+            0x78, 0x9C, 0x4B, 0x4C, 0x4A, 0x4E, 0x84, 0x21, 0x00, 0x1D, 0xE0, 0x04, 0x99,
+        ];
+        assert_eq!(inflate(&data, 128).unwrap(), b"abcabcabcabc");
+    }
+
+    #[test]
+    fn inflates_dynamic_huffman() {
+        let data = [
+            0x78, 0x01, 0x05, 0xC1, 0x01, 0x01, 0x00, 0x30, 0x0C, 0xC3, 0x20, 0xAD, 0xA4, 0xBB,
+            0x7F, 0x0B, 0x07, 0x00, 0x00, 0x00, 0x00, 0x80, 0xAA, 0xAA, 0xAA, 0xAA, 0xDA, 0xB6,
+            0x6D, 0x77, 0xF7, 0x3E, 0x95, 0x81, 0x1A, 0x53,
+        ];
+        let mut expected = vec![b'a'; 40];
+        expected.extend([b'b'; 18]);
+        expected.extend([b'c'; 7]);
+        expected.extend(b"ddde");
+        assert_eq!(inflate(&data, 128).unwrap(), expected);
+    }
+
+    #[test]
+    fn stops_at_output_limit() {
+        let data = [
+            0x78, 0x9C, 0x4B, 0x4C, 0x4A, 0x4E, 0x84, 0x21, 0x00, 0x1D, 0xE0, 0x04, 0x99,
+        ];
+        assert_eq!(inflate(&data, 12).unwrap().len(), 12);
+        assert_eq!(inflate(&data, 11), Err(PngError::BadData));
+        let stored = [0x78, 0x01, 0x01, 0x03, 0x00, 0xFC, 0xFF, b'a', b'b', b'c'];
+        assert_eq!(inflate(&stored, 2), Err(PngError::BadData));
+    }
+
+    #[test]
+    fn rejects_non_deflate_stream() {
+        assert_eq!(inflate(&[0x79, 0x01], 128), Err(PngError::BadData));
+    }
+}
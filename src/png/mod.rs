@@ -0,0 +1,382 @@
+mod inflate;
+
+use std::error::Error;
+use std::fmt;
+
+use crate::checksum::{adler32, crc32};
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+const COLOR_GRAY: u8 = 0;
+const COLOR_RGB: u8 = 2;
+const COLOR_PALETTE: u8 = 3;
+const COLOR_GRAY_ALPHA: u8 = 4;
+const COLOR_RGBA: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PngError {
+    BadSignature,
+    Truncated,
+    BadChecksum { chunk: [u8; 4] },
+    BadData,
+    Unsupported(&'static str),
+}
+
+impl fmt::Display for PngError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PngError::BadSignature => write!(f, "not a PNG file"),
+            PngError::Truncated => write!(f, "PNG file ends unexpectedly"),
+            PngError::BadChecksum { chunk } => {
+                write!(f, "{} chunk is corrupt", String::from_utf8_lossy(chunk))
+            }
+            PngError::BadData => write!(f, "PNG image data is corrupt"),
+            PngError::Unsupported(what) => write!(f, "unsupported PNG: {what}"),
+        }
+    }
+}
+
+impl Error for PngError {}
+
+// an 8-bit RGB picture, row by row from the top left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub rgb: Vec<u8>,
+}
+
+// writes an RGB PNG with uncompressed deflate blocks
+pub fn encode(image: &Image) -> Vec<u8> {
+    let mut raw = Vec::with_capacity((image.width * 3 + 1) * image.height);
+    for row in image.rgb.chunks_exact(image.width * 3) {
+        // filter type 0, no filtering
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = raw.chunks(0xFFFF).peekable();
+    if blocks.peek().is_none() {
+        zlib.extend([1, 0, 0, 0xFF, 0xFF]);
+    }
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend(len.to_le_bytes());
+        zlib.extend((!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend((image.width as u32).to_be_bytes());
+    header.extend((image.height as u32).to_be_bytes());
+    header.extend([8, COLOR_RGB, 0, 0, 0]);
+
+    let mut png = SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend((data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend(crc.to_be_bytes());
+}
+
+// reads any non-interlaced PNG into RGB, dropping alpha
+pub fn decode(data: &[u8]) -> Result<Image, PngError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(PngError::BadSignature);
+    }
+    let mut pos = SIGNATURE.len();
+    let mut header = None;
+    let mut palette = Vec::new();
+    let mut compressed = Vec::new();
+    loop {
+        let (kind, body, next) = chunk(data, pos)?;
+        match &kind {
+            b"IHDR" => header = Some(Header::parse(body)?),
+            b"PLTE" => palette = body.to_vec(),
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        pos = next;
+    }
+    let header = header.ok_or(PngError::BadData)?;
+    let raw = inflate::inflate(&compressed, header.raw_size()?)?;
+    let pixels = unfilter(&header, &raw)?;
+    header.to_rgb(&pixels, &palette)
+}
+
+// (width, height) from the header alone, to turn down an image before
+// decompressing it
+pub fn dimensions(data: &[u8]) -> Result<(usize, usize), PngError> {
+    if !data.starts_with(&SIGNATURE) {
+        return Err(PngError::BadSignature);
+    }
+    match chunk(data, SIGNATURE.len())? {
+        (kind, body, _) if &kind == b"IHDR" => {
+            let header = Header::parse(body)?;
+            Ok((header.width, header.height))
+        }
+        _ => Err(PngError::BadData),
+    }
+}
+
+// the kind and body of the chunk at pos, and where the next one starts
+fn chunk(data: &[u8], pos: usize) -> Result<([u8; 4], &[u8], usize), PngError> {
+    let len_bytes = data.get(pos..pos + 4).ok_or(PngError::Truncated)?;
+    let len = u32::from_be_bytes(len_bytes.try_into().unwrap()) as usize;
+    let end = pos + 8 + len;
+    let chunk = data.get(pos + 4..end).ok_or(PngError::Truncated)?;
+    let crc = data.get(end..end + 4).ok_or(PngError::Truncated)?;
+    let kind: [u8; 4] = chunk[..4].try_into().unwrap();
+    if crc32(chunk) != u32::from_be_bytes(crc.try_into().unwrap()) {
+        return Err(PngError::BadChecksum { chunk: kind });
+    }
+    Ok((kind, &chunk[4..], end + 4))
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color: u8,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, PngError> {
+        if data.len() != 13 {
+            return Err(PngError::BadData);
+        }
+        let header = Header {
+            width: u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize,
+            height: u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize,
+            depth: data[8],
+            color: data[9],
+        };
+        if header.width == 0 || header.height == 0 {
+            return Err(PngError::BadData);
+        }
+        if data[12] != 0 {
+            return Err(PngError::Unsupported("interlacing"));
+        }
+        let depth_ok = match header.color {
+            COLOR_GRAY => matches!(header.depth, 1 | 2 | 4 | 8 | 16),
+            COLOR_PALETTE => matches!(header.depth, 1 | 2 | 4 | 8),
+            COLOR_RGB | COLOR_GRAY_ALPHA | COLOR_RGBA => matches!(header.depth, 8 | 16),
+            _ => return Err(PngError::BadData),
+        };
+        if !depth_ok {
+            return Err(PngError::BadData);
+        }
+        Ok(header)
+    }
+
+    fn channels(&self) -> usize {
+        match self.color {
+            COLOR_RGB => 3,
+            COLOR_GRAY_ALPHA => 2,
+            COLOR_RGBA => 4,
+            _ => 1,
+        }
+    }
+
+    fn bits_per_pixel(&self) -> usize {
+        self.channels() * self.depth as usize
+    }
+
+    fn stride(&self) -> usize {
+        (self.width * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    // the decompressed size, every row with its filter byte
+    fn raw_size(&self) -> Result<usize, PngError> {
+        (self.stride() + 1)
+            .checked_mul(self.height)
+            .ok_or(PngError::BadData)
+    }
+
+    // one sample per channel, scaled to 8 bits except palette indices
+    fn sample(&self, row: &[u8], index: usize) -> u8 {
+        match self.depth {
+            16 => row[index * 2],
+            8 => row[index],
+            depth => {
+                let bit = index * depth as usize;
+                let shift = 8 - depth as usize - bit % 8;
+                let value = (row[bit / 8] >> shift) & ((1 << depth) - 1);
+                if self.color == COLOR_PALETTE {
+                    value
+                } else {
+                    (value as u16 * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    }
+
+    fn to_rgb(&self, pixels: &[u8], palette: &[u8]) -> Result<Image, PngError> {
+        let channels = self.channels();
+        let mut rgb = Vec::with_capacity(self.width * self.height * 3);
+        for row in pixels.chunks_exact(self.stride()) {
+            for x in 0..self.width {
+                let sample = |channel| self.sample(row, x * channels + channel);
+                match self.color {
+                    COLOR_GRAY | COLOR_GRAY_ALPHA => rgb.extend([sample(0); 3]),
+                    COLOR_PALETTE => {
+                        let index = sample(0) as usize * 3;
+                        let color = palette.get(index..index + 3).ok_or(PngError::BadData)?;
+                        rgb.extend_from_slice(color);
+                    }
+                    _ => rgb.extend([sample(0), sample(1), sample(2)]),
+                }
+            }
+        }
+        Ok(Image {
+            width: self.width,
+            height: self.height,
+            rgb,
+        })
+    }
+}
+
+// undoes the per-row filters, leaving packed rows without filter bytes
+fn unfilter(header: &Header, raw: &[u8]) -> Result<Vec<u8>, PngError> {
+    let stride = header.stride();
+    if raw.len() < header.raw_size()? {
+        return Err(PngError::Truncated);
+    }
+    // filters work on whole bytes, rounding sub-byte pixels up
+    let bpp = header.bits_per_pixel().div_ceil(8);
+    let mut pixels = vec![0u8; stride * header.height];
+    for y in 0..header.height {
+        let line = &raw[y * (stride + 1)..(y + 1) * (stride + 1)];
+        let (before, current) = pixels.split_at_mut(y * stride);
+        let previous = before.get(before.len().wrapping_sub(stride)..);
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = previous.map_or(0, |row| row[x]);
+            let c = if x >= bpp {
+                previous.map_or(0, |row| row[x - bpp])
+            } else {
+                0
+            };
+            let predictor = match line[0] {
+                0 => 0,
+                1 => a,
+                2 => b,
+                3 => ((a as u16 + b as u16) / 2) as u8,
+                4 => paeth(a, b, c),
+                _ => return Err(PngError::BadData),
+            };
+            current[x] = line[x + 1].wrapping_add(predictor);
+        }
+    }
+    Ok(pixels)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_rgb_image() {
+        let image = Image {
+            width: 3,
+            height: 2,
+            rgb: (0..18).map(|i| i * 13).collect(),
+        };
+        assert_eq!(decode(&encode(&image)).unwrap(), image);
+    }
+
+    #[test]
+    fn rejects_corrupt_chunk() {
+        let image = Image {
+            width: 1,
+            height: 1,
+            rgb: vec![1, 2, 3],
+        };
+        let mut png = encode(&image);
+        png[20] ^= 1;
+        assert_eq!(decode(&png), Err(PngError::BadChecksum { chunk: *b"IHDR" }));
+        assert_eq!(decode(b"GIF89a"), Err(PngError::BadSignature));
+    }
+
+    #[test]
+    fn rejects_empty_and_oversized_dimensions() {
+        let ihdr = |width: u32, height: u32| {
+            let mut data = width.to_be_bytes().to_vec();
+            data.extend(height.to_be_bytes());
+            data.extend([16, COLOR_RGBA, 0, 0, 0]);
+            data
+        };
+        assert!(matches!(Header::parse(&ihdr(0, 1)), Err(PngError::BadData)));
+        assert!(matches!(Header::parse(&ihdr(1, 0)), Err(PngError::BadData)));
+        let header = Header::parse(&ihdr(u32::MAX, u32::MAX)).unwrap();
+        assert_eq!(unfilter(&header, &[]), Err(PngError::BadData));
+    }
+
+    #[test]
+    fn decodes_filtered_2_bit_palette_image() {
+        // 4x2, indices 0 1 2 3 over 3 2 1 0, the second row Up-filtered
+        let png = [
+            0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48,
+            0x44, 0x52, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x02, 0x02, 0x03, 0x00, 0x00,
+            0x00, 0x02, 0xC6, 0x95, 0xF0, 0x00, 0x00, 0x00, 0x0C, 0x50, 0x4C, 0x54, 0x45, 0x00,
+            0x00, 0x00, 0x55, 0x55, 0x55, 0xAA, 0xAA, 0xAA, 0xFF, 0x00, 0x00, 0x7F, 0x59, 0x70,
+            0x2E, 0x00, 0x00, 0x00, 0x0C, 0x49, 0x44, 0x41, 0x54, 0x78, 0xDA, 0x63, 0x94, 0x66,
+            0x3A, 0x09, 0x00, 0x01, 0x26, 0x00, 0xE8, 0x3F, 0x96, 0x38, 0xEE, 0x00, 0x00, 0x00,
+            0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE, 0x42, 0x60, 0x82,
+        ];
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height), (4, 2));
+        assert_eq!(
+            &image.rgb[..12],
+            &[0, 0, 0, 85, 85, 85, 170, 170, 170, 255, 0, 0]
+        );
+        assert_eq!(
+            &image.rgb[12..],
+            &[255, 0, 0, 170, 170, 170, 85, 85, 85, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn rejects_image_data_larger_than_header() {
+        let image = Image {
+            width: 16,
+            height: 16,
+            rgb: vec![7; 16 * 16 * 3],
+        };
+        let mut png = encode(&image);
+        // shrink IHDR to 1x1 and fix its checksum
+        png[16..24].copy_from_slice(&[0, 0, 0, 1, 0, 0, 0, 1]);
+        let crc = crc32(&png[12..29]);
+        png[29..33].copy_from_slice(&crc.to_be_bytes());
+        assert_eq!(dimensions(&png), Ok((1, 1)));
+        assert_eq!(decode(&png), Err(PngError::BadData));
+    }
+}
@@ -0,0 +1,166 @@
+pub const WIDTH: usize = 256;
+pub const HEIGHT: usize = 240;
+
+// what a frontend wants its pixels in. Rgb565 is little-endian and
+// Indexed8 holds the system palette index without emphasis
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PixelFormat {
+    Rgb888,
+    Rgba8888,
+    Bgra8888,
+    Rgb565,
+    Indexed8,
+}
+
+impl PixelFormat {
+    pub fn bytes_per_pixel(self) -> usize {
+        match self {
+            PixelFormat::Rgb888 => 3,
+            PixelFormat::Rgba8888 | PixelFormat::Bgra8888 => 4,
+            PixelFormat::Rgb565 => 2,
+            PixelFormat::Indexed8 => 1,
+        }
+    }
+}
+
+// one picture as packed RGB, row by row from the top left
+#[derive(Clone)]
+pub struct Frame {
+    pub data: Vec<u8>,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Frame {
+            data: vec![0; WIDTH * HEIGHT * 3],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, rgb: (u8, u8, u8)) {
+        let base = (y * WIDTH + x) * 3;
+        self.data[base..base + 3].copy_from_slice(&[rgb.0, rgb.1, rgb.2]);
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let base = (y * WIDTH + x) * 3;
+        (self.data[base], self.data[base + 1], self.data[base + 2])
+    }
+
+    // replaces out with the picture in format, reusing its allocation.
+    // Indexed8 needs the palette indices, see IndexedFrame::write_indexed8
+    pub fn write_rgb(&self, format: PixelFormat, out: &mut Vec<u8>) {
+        out.clear();
+        out.reserve(WIDTH * HEIGHT * format.bytes_per_pixel());
+        for rgb in self.data.chunks_exact(3) {
+            let (r, g, b) = (rgb[0], rgb[1], rgb[2]);
+            match format {
+                PixelFormat::Rgb888 => out.extend_from_slice(rgb),
+                PixelFormat::Rgba8888 => out.extend_from_slice(&[r, g, b, 0xFF]),
+                PixelFormat::Bgra8888 => out.extend_from_slice(&[b, g, r, 0xFF]),
+                PixelFormat::Rgb565 => {
+                    let pixel = (r as u16 >> 3) << 11 | (g as u16 >> 2) << 5 | b as u16 >> 3;
+                    out.extend_from_slice(&pixel.to_le_bytes());
+                }
+                PixelFormat::Indexed8 => panic!("Frame has no palette indices"),
+            }
+        }
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// the same picture before the palette lookup: each pixel holds the 6-bit
+// system palette index in bits 0-5 and PPUMASK's emphasis bits in 6-8
+#[derive(Clone)]
+pub struct IndexedFrame {
+    pub data: Vec<u16>,
+}
+
+impl IndexedFrame {
+    pub fn new() -> Self {
+        IndexedFrame {
+            data: vec![0; WIDTH * HEIGHT],
+        }
+    }
+
+    pub fn set_pixel(&mut self, x: usize, y: usize, index: u16) {
+        self.data[y * WIDTH + x] = index;
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> u16 {
+        self.data[y * WIDTH + x]
+    }
+
+    pub fn palette_index(&self, x: usize, y: usize) -> u8 {
+        (self.pixel(x, y) & 0x3F) as u8
+    }
+
+    // red, green and blue emphasis as in PPUMASK bits 5-7
+    pub fn emphasis(&self, x: usize, y: usize) -> u8 {
+        (self.pixel(x, y) >> 6) as u8
+    }
+
+    pub fn write_indexed8(&self, out: &mut Vec<u8>) {
+        out.clear();
+        out.extend(self.data.iter().map(|&pixel| (pixel & 0x3F) as u8));
+    }
+}
+
+impl Default for IndexedFrame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn stores_pixels_row_major() {
+        let mut frame = Frame::new();
+        frame.set_pixel(1, 2, (10, 20, 30));
+        assert_eq!(frame.pixel(1, 2), (10, 20, 30));
+        assert_eq!(&frame.data[(2 * WIDTH + 1) * 3..][..3], &[10, 20, 30]);
+    }
+
+    #[test]
+    fn converts_to_rgb_formats() {
+        let mut frame = Frame::new();
+        frame.set_pixel(0, 0, (0xF8, 0xFC, 0x08));
+        let mut out = vec![0xAA; 7];
+        frame.write_rgb(PixelFormat::Rgba8888, &mut out);
+        assert_eq!(out.len(), WIDTH * HEIGHT * 4);
+        assert_eq!(&out[..8], &[0xF8, 0xFC, 0x08, 0xFF, 0, 0, 0, 0xFF]);
+        frame.write_rgb(PixelFormat::Bgra8888, &mut out);
+        assert_eq!(&out[..4], &[0x08, 0xFC, 0xF8, 0xFF]);
+        frame.write_rgb(PixelFormat::Rgb565, &mut out);
+        assert_eq!(out.len(), WIDTH * HEIGHT * 2);
+        assert_eq!(&out[..2], &0xFFE1u16.to_le_bytes());
+        frame.write_rgb(PixelFormat::Rgb888, &mut out);
+        assert_eq!(out, frame.data);
+    }
+
+    #[test]
+    fn drops_emphasis_for_indexed8() {
+        let mut frame = IndexedFrame::new();
+        frame.set_pixel(1, 0, 0b111 << 6 | 0x16);
+        let mut out = Vec::new();
+        frame.write_indexed8(&mut out);
+        assert_eq!(out.len(), WIDTH * HEIGHT);
+        assert_eq!(&out[..2], &[0, 0x16]);
+    }
+
+    #[test]
+    fn splits_index_and_emphasis() {
+        let mut frame = IndexedFrame::new();
+        frame.set_pixel(3, 4, 0b101 << 6 | 0x2A);
+        assert_eq!(frame.palette_index(3, 4), 0x2A);
+        assert_eq!(frame.emphasis(3, 4), 0b101);
+        assert_eq!(frame.data[4 * WIDTH + 3], 0b101 << 6 | 0x2A);
+    }
+}
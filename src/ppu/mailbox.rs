@@ -0,0 +1,110 @@
+use super::Frame;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, TryLockError};
+
+// hands finished frames to a rendering thread. A published frame is never
+// written again, so readers always see a whole one, and the emulation
+// thread only ever tries the lock to swap a pointer: if a reader holds it,
+// that frame is skipped rather than waited on
+pub struct FrameMailbox {
+    latest: Mutex<Arc<Frame>>,
+    published: AtomicU64,
+}
+
+impl FrameMailbox {
+    pub fn new() -> Self {
+        FrameMailbox {
+            latest: Mutex::new(Arc::new(Frame::new())),
+            published: AtomicU64::new(0),
+        }
+    }
+
+    // the last complete frame
+    pub fn latest(&self) -> Arc<Frame> {
+        Arc::clone(&self.latest.lock().unwrap())
+    }
+
+    // how many frames have been published, to spot a new one
+    pub fn published(&self) -> u64 {
+        self.published.load(Ordering::Acquire)
+    }
+
+    // spare is the frame the previous call swapped out, reused once no
+    // reader holds on to it
+    pub(super) fn publish(&self, frame: &Frame, spare: &mut Option<Arc<Frame>>) {
+        let mut next = match spare.take() {
+            Some(mut old) => match Arc::get_mut(&mut old) {
+                Some(buffer) => {
+                    buffer.data.copy_from_slice(&frame.data);
+                    old
+                }
+                None => Arc::new(frame.clone()),
+            },
+            None => Arc::new(frame.clone()),
+        };
+        match self.latest.try_lock() {
+            Ok(mut latest) => std::mem::swap(&mut *latest, &mut next),
+            Err(TryLockError::WouldBlock) => {
+                *spare = Some(next);
+                return;
+            }
+            Err(TryLockError::Poisoned(err)) => panic!("{err}"),
+        }
+        *spare = Some(next);
+        self.published.fetch_add(1, Ordering::Release);
+    }
+}
+
+impl Default for FrameMailbox {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn filled(value: u8) -> Frame {
+        let mut frame = Frame::new();
+        frame.data.fill(value);
+        frame
+    }
+
+    #[test]
+    fn keeps_the_last_frame() {
+        let mailbox = FrameMailbox::new();
+        let mut spare = None;
+        mailbox.publish(&filled(1), &mut spare);
+        mailbox.publish(&filled(2), &mut spare);
+        assert_eq!(mailbox.published(), 2);
+        assert!(mailbox.latest().data.iter().all(|&byte| byte == 2));
+    }
+
+    #[test]
+    fn held_frames_stay_whole() {
+        let mailbox = FrameMailbox::new();
+        let mut spare = None;
+        mailbox.publish(&filled(1), &mut spare);
+        let held = mailbox.latest();
+        mailbox.publish(&filled(2), &mut spare);
+        mailbox.publish(&filled(3), &mut spare);
+        assert!(held.data.iter().all(|&byte| byte == 1));
+        assert!(mailbox.latest().data.iter().all(|&byte| byte == 3));
+    }
+
+    #[test]
+    fn skips_frames_while_a_reader_has_the_lock() {
+        let mailbox = FrameMailbox::new();
+        let mut spare = None;
+        mailbox.publish(&filled(1), &mut spare);
+        {
+            let _reader = mailbox.latest.lock().unwrap();
+            mailbox.publish(&filled(2), &mut spare);
+        }
+        assert_eq!(mailbox.published(), 1);
+        assert!(mailbox.latest().data.iter().all(|&byte| byte == 1));
+        mailbox.publish(&filled(3), &mut spare);
+        assert!(mailbox.latest().data.iter().all(|&byte| byte == 3));
+    }
+}
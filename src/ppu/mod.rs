@@ -0,0 +1,587 @@
+mod frame;
+mod mailbox;
+mod palette;
+mod render;
+mod timing;
+
+use crate::cartridge::Mirroring;
+use crate::mapper::Mapper;
+use crate::state::{StateError, StateReader, StateWriter};
+use std::sync::Arc;
+use timing::{DOTS_PER_SCANLINE, PRE_RENDER_SCANLINE, VBLANK_SCANLINE};
+
+pub use frame::{Frame, IndexedFrame, PixelFormat, HEIGHT, WIDTH};
+pub use mailbox::FrameMailbox;
+pub use palette::SYSTEM_PALETTE;
+
+// runs before each visible line is drawn, with the line number
+pub type ScanlineCallback = Box<dyn FnMut(&mut Ppu, u16) + Send>;
+
+const CHR_END: u16 = 0x1FFF;
+const NAMETABLES: u16 = 0x2000;
+const NAMETABLES_END: u16 = 0x3EFF;
+const PALETTE: u16 = 0x3F00;
+
+const CTRL_VRAM_INCREMENT: u8 = 0b0000_0100;
+const CTRL_NMI: u8 = 0b1000_0000;
+const STATUS_SPRITE_OVERFLOW: u8 = 0b0010_0000;
+const STATUS_SPRITE_ZERO_HIT: u8 = 0b0100_0000;
+const STATUS_VBLANK: u8 = 0b1000_0000;
+// about 3000 CPU cycles without a refresh before an OAM row is lost
+const OAM_DECAY_DOTS: u64 = 9000;
+// what a decayed row reads back as
+const OAM_DECAYED: u8 = 0x10;
+
+// the 2C02 as seen through its eight registers at 0x2000-0x2007:
+//   0 PPUCTRL   (write)      4 OAMDATA   (read/write)
+//   1 PPUMASK   (write)      5 PPUSCROLL (write x2)
+//   2 PPUSTATUS (read)       6 PPUADDR   (write x2)
+//   3 OAMADDR   (write)      7 PPUDATA   (read/write)
+// pattern tables live on the cartridge, so accesses that reach them take
+// the mapper along
+pub struct Ppu {
+    ctrl: u8,
+    mask: u8,
+    status: u8,
+    oam_addr: u8,
+    oam: [u8; 256],
+    // opt-in: OAM rows left alone too long with rendering off lose their
+    // contents, as the DRAM on the console does
+    oam_decay: bool,
+    // the dot count each 8-byte OAM row was last read or written at
+    oam_row_access: [u64; 32],
+    // dots run since power on, the clock OAM decay is measured against;
+    // neither is in save states, loading one refreshes every row
+    dots: u64,
+    // 2 KiB on the console, 4 KiB when the cartridge adds four-screen VRAM
+    vram: [u8; 0x1000],
+    palette: [u8; 32],
+    // the internal scroll registers, both laid out as 0yyy NNYY YYYX XXXX
+    // (fine y, nametable, coarse y, coarse x): v is the current VRAM
+    // address, t the one PPUSCROLL/PPUADDR build up and rendering reloads
+    // v from
+    v: u16,
+    t: u16,
+    fine_x: u8,
+    // PPUSCROLL and PPUADDR share one first/second write toggle
+    write_latch: bool,
+    // PPUDATA reads below the palette return the previous read's value
+    read_buffer: u8,
+    // the last value driven onto the PPU data bus, seen in unused bits
+    open_bus: u8,
+    frame: Frame,
+    // not part of save states, the next frame drawn fills it in again
+    indexed_frame: IndexedFrame,
+    // 341 dots on each of 262 scanlines, 240 of them visible
+    scanline: u16,
+    dot: u16,
+    odd_frame: bool,
+    nmi_pending: bool,
+    frame_complete: bool,
+    // PPUSTATUS was read on the dot VBlank starts; the tick after the read
+    // always consumes it, so it never needs to be in a save state
+    suppress_vblank: bool,
+    // from power-up to the first pre-render line PPUCTRL, PPUMASK,
+    // PPUSCROLL and PPUADDR ignore writes, about 29658 CPU cycles
+    warming_up: bool,
+    scanline_callback: Option<ScanlineCallback>,
+    // frame is drawn into line by line; complete ones go to the mailbox
+    mailbox: Option<Arc<FrameMailbox>>,
+    spare_frame: Option<Arc<Frame>>,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Ppu {
+            ctrl: 0,
+            mask: 0,
+            status: 0,
+            oam_addr: 0,
+            oam: [0; 256],
+            oam_decay: false,
+            oam_row_access: [0; 32],
+            dots: 0,
+            vram: [0; 0x1000],
+            palette: [0; 32],
+            v: 0,
+            t: 0,
+            fine_x: 0,
+            write_latch: false,
+            read_buffer: 0,
+            open_bus: 0,
+            frame: Frame::new(),
+            indexed_frame: IndexedFrame::new(),
+            scanline: 0,
+            dot: 0,
+            odd_frame: false,
+            nmi_pending: false,
+            frame_complete: false,
+            suppress_vblank: false,
+            warming_up: false,
+            scanline_callback: None,
+            mailbox: None,
+            spare_frame: None,
+        }
+    }
+
+    // the state after switching the console on. Memory contents are
+    // unspecified and left alone; sprite overflow usually reads set, and
+    // VBlank, which is random, starts clear so the usual two VBlank waits
+    // end after the write-ignore window
+    pub fn power_up(&mut self) {
+        self.ctrl = 0;
+        self.mask = 0;
+        self.status = STATUS_SPRITE_OVERFLOW;
+        self.oam_addr = 0;
+        self.v = 0;
+        self.t = 0;
+        self.fine_x = 0;
+        self.write_latch = false;
+        self.read_buffer = 0;
+        self.scanline = 0;
+        self.dot = 0;
+        self.odd_frame = false;
+        self.nmi_pending = false;
+        self.warming_up = true;
+    }
+
+    pub fn warming_up(&self) -> bool {
+        self.warming_up
+    }
+
+    pub fn ctrl(&self) -> u8 {
+        self.ctrl
+    }
+
+    pub fn mask(&self) -> u8 {
+        self.mask
+    }
+
+    pub fn status(&self) -> u8 {
+        self.status
+    }
+
+    // the scroll position last set through PPUSCROLL/PPUADDR
+    pub fn scroll(&self) -> (u8, u8) {
+        let x = (self.t & 0x1F) << 3 | self.fine_x as u16;
+        let y = (self.t >> 5 & 0x1F) << 3 | self.t >> 12;
+        (x as u8, y as u8)
+    }
+
+    // for raster effects without a mapper IRQ: takes effect on the next
+    // line drawn, like a PPUSCROLL pair followed by the PPUADDR trick that
+    // also moves the vertical scroll mid-frame
+    pub fn set_scroll(&mut self, x: u8, y: u8) {
+        let (x, y) = (x as u16, y as u16);
+        self.t = (self.t & 0x0C00) | ((y & 0x07) << 12) | ((y >> 3) << 5) | (x >> 3);
+        self.fine_x = x as u8 & 0x07;
+        self.v = self.t;
+    }
+
+    pub fn set_mask(&mut self, mask: u8) {
+        self.mask = mask;
+    }
+
+    pub fn set_scanline_callback(&mut self, callback: impl FnMut(&mut Ppu, u16) + Send + 'static) {
+        self.scanline_callback = Some(Box::new(callback));
+    }
+
+    pub fn clear_scanline_callback(&mut self) {
+        self.scanline_callback = None;
+    }
+
+    // publishes each frame as VBlank starts, for a rendering thread
+    pub fn set_frame_mailbox(&mut self, mailbox: Arc<FrameMailbox>) {
+        self.mailbox = Some(mailbox);
+        self.spare_frame = None;
+    }
+
+    pub fn clear_frame_mailbox(&mut self) {
+        self.mailbox = None;
+        self.spare_frame = None;
+    }
+
+    // run-ahead holds the mailbox back while running frames it won't show
+    pub(crate) fn take_frame_mailbox(&mut self) -> Option<Arc<FrameMailbox>> {
+        self.mailbox.take()
+    }
+
+    pub(crate) fn put_frame_mailbox(&mut self, mailbox: Option<Arc<FrameMailbox>>) {
+        self.mailbox = mailbox;
+    }
+
+    pub fn vram_addr(&self) -> u16 {
+        self.v
+    }
+
+    pub fn sprite_overflow(&self) -> bool {
+        self.status & STATUS_SPRITE_OVERFLOW != 0
+    }
+
+    pub fn sprite_zero_hit(&self) -> bool {
+        self.status & STATUS_SPRITE_ZERO_HIT != 0
+    }
+
+    pub fn oam(&self) -> &[u8; 256] {
+        &self.oam
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frame
+    }
+
+    // the last frame as system palette indices with emphasis bits
+    pub fn indexed_frame(&self) -> &IndexedFrame {
+        &self.indexed_frame
+    }
+
+    pub fn read_register(&mut self, addr: u16, mapper: &dyn Mapper) -> u8 {
+        let value = match addr & 0x7 {
+            2 => {
+                let value = (self.status & 0b1110_0000) | (self.open_bus & 0b0001_1111);
+                self.status &= !STATUS_VBLANK;
+                self.write_latch = false;
+                // racing the flag: a read on the dot it sets sees it clear
+                // and it never sets this frame, one just after clears it
+                // before the NMI goes out
+                if self.scanline == VBLANK_SCANLINE {
+                    match self.dot {
+                        1 => self.suppress_vblank = true,
+                        2 | 3 => self.nmi_pending = false,
+                        _ => {}
+                    }
+                }
+                value
+            }
+            4 => {
+                self.refresh_oam_row(self.oam_addr);
+                self.oam[self.oam_addr as usize]
+            }
+            7 => {
+                let addr = self.v & 0x3FFF;
+                let data = self.read_memory(addr, mapper);
+                let value = if addr >= PALETTE {
+                    // palette reads are immediate, the buffer picks up the
+                    // nametable byte underneath
+                    self.read_buffer = self.read_memory(addr - 0x1000, mapper);
+                    (data & 0b0011_1111) | (self.open_bus & 0b1100_0000)
+                } else {
+                    std::mem::replace(&mut self.read_buffer, data)
+                };
+                self.increment_addr();
+                value
+            }
+            _ => self.open_bus,
+        };
+        self.open_bus = value;
+        value
+    }
+
+    // reads a register without clearing flags or advancing addresses
+    pub fn peek_register(&self, addr: u16, mapper: &dyn Mapper) -> u8 {
+        match addr & 0x7 {
+            2 => (self.status & 0b1110_0000) | (self.open_bus & 0b0001_1111),
+            4 => self.oam[self.oam_addr as usize],
+            7 if self.v & 0x3FFF >= PALETTE => self.read_memory(self.v, mapper),
+            7 => self.read_buffer,
+            _ => self.open_bus,
+        }
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8, mapper: &mut dyn Mapper) {
+        self.open_bus = data;
+        if self.warming_up && matches!(addr & 0x7, 0 | 1 | 5 | 6) {
+            return;
+        }
+        match addr & 0x7 {
+            0 => {
+                // enabling NMIs during VBlank raises one straight away
+                if self.ctrl & CTRL_NMI == 0
+                    && data & CTRL_NMI != 0
+                    && self.status & STATUS_VBLANK != 0
+                {
+                    self.nmi_pending = true;
+                }
+                self.ctrl = data;
+                self.t = (self.t & !0x0C00) | ((data as u16 & 0b11) << 10);
+            }
+            1 => self.mask = data,
+            3 => self.oam_addr = data,
+            4 => self.write_oam(data),
+            5 => {
+                let data = data as u16;
+                if self.write_latch {
+                    self.t = (self.t & !0x73E0) | ((data & 0x07) << 12) | ((data >> 3) << 5);
+                } else {
+                    self.t = (self.t & !0x001F) | (data >> 3);
+                    self.fine_x = data as u8 & 0x07;
+                }
+                self.write_latch = !self.write_latch;
+            }
+            6 => {
+                if self.write_latch {
+                    self.t = (self.t & 0xFF00) | data as u16;
+                    self.v = self.t;
+                } else {
+                    self.t = ((data as u16 & 0x3F) << 8) | (self.t & 0x00FF);
+                }
+                self.write_latch = !self.write_latch;
+            }
+            7 => {
+                self.write_memory(self.v, data, mapper);
+                self.increment_addr();
+            }
+            // PPUSTATUS is read-only
+            _ => {}
+        }
+    }
+
+    // writes at OAMADDR and advances it, for both OAMDATA and OAM DMA
+    pub fn write_oam(&mut self, data: u8) {
+        self.refresh_oam_row(self.oam_addr);
+        self.oam[self.oam_addr as usize] = data;
+        self.oam_addr = self.oam_addr.wrapping_add(1);
+    }
+
+    pub fn set_oam_decay(&mut self, enabled: bool) {
+        self.oam_decay = enabled;
+        self.oam_row_access = [self.dots; 32];
+    }
+
+    // an access refreshes the row holding addr, unless it already decayed
+    fn refresh_oam_row(&mut self, addr: u8) {
+        let row = addr as usize / 8;
+        if self.oam_decay && self.dots - self.oam_row_access[row] > OAM_DECAY_DOTS {
+            self.oam[row * 8..row * 8 + 8].fill(OAM_DECAYED);
+        }
+        self.oam_row_access[row] = self.dots;
+    }
+
+    // sprite evaluation reads all of OAM on every rendered line
+    pub(super) fn refresh_all_oam(&mut self) {
+        for row in 0..32 {
+            self.refresh_oam_row(row * 8);
+        }
+    }
+
+    fn increment_addr(&mut self) {
+        let step = if self.ctrl & CTRL_VRAM_INCREMENT != 0 {
+            32
+        } else {
+            1
+        };
+        self.v = self.v.wrapping_add(step) & 0x7FFF;
+    }
+
+    // PPU memory map:
+    //   [0x0000 .. 0x1FFF] pattern tables, on the cartridge
+    //   [0x2000 .. 0x3EFF] nametables, mirrored as the cartridge wires them
+    //   [0x3F00 .. 0x3FFF] palette RAM, mirrored every 32 bytes
+    pub fn read_memory(&self, addr: u16, mapper: &dyn Mapper) -> u8 {
+        match addr & 0x3FFF {
+            addr @ 0..=CHR_END => mapper.read_chr(addr),
+            addr @ NAMETABLES..=NAMETABLES_END => {
+                self.vram[nametable_offset(addr, mapper.mirroring())]
+            }
+            addr => self.palette[palette_offset(addr)],
+        }
+    }
+
+    pub fn write_memory(&mut self, addr: u16, data: u8, mapper: &mut dyn Mapper) {
+        match addr & 0x3FFF {
+            addr @ 0..=CHR_END => mapper.write_chr(addr, data),
+            addr @ NAMETABLES..=NAMETABLES_END => {
+                self.vram[nametable_offset(addr, mapper.mirroring())] = data
+            }
+            addr => self.palette[palette_offset(addr)] = data,
+        }
+    }
+}
+
+impl Default for Ppu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// maps the four logical nametables onto the physical 1 KiB pages
+fn nametable_offset(addr: u16, mirroring: Mirroring) -> usize {
+    let addr = (addr - NAMETABLES) & 0x0FFF;
+    let table = addr / 0x400;
+    let page = match (mirroring, table) {
+        (Mirroring::Horizontal, _) => table / 2,
+        (Mirroring::Vertical, _) => table % 2,
+        (Mirroring::FourScreen, _) => table,
+        (Mirroring::SingleScreenLower, _) => 0,
+        (Mirroring::SingleScreenUpper, _) => 1,
+    };
+    (page * 0x400 + (addr & 0x3FF)) as usize
+}
+
+// the backdrop entries of the sprite palettes mirror the background ones
+fn palette_offset(addr: u16) -> usize {
+    let offset = (addr & 0x1F) as usize;
+    match offset {
+        0x10 | 0x14 | 0x18 | 0x1C => offset - 0x10,
+        _ => offset,
+    }
+}
+
+impl Ppu {
+    pub(crate) fn save_state(&self, state: &mut StateWriter) {
+        state.u8(self.ctrl);
+        state.u8(self.mask);
+        state.u8(self.status);
+        state.u8(self.oam_addr);
+        state.bytes(&self.oam);
+        state.bytes(&self.vram);
+        state.bytes(&self.palette);
+        state.u16(self.v);
+        state.u16(self.t);
+        state.u8(self.fine_x);
+        state.bool(self.write_latch);
+        state.u8(self.read_buffer);
+        state.u8(self.open_bus);
+        state.bytes(&self.frame.data);
+        state.u16(self.scanline);
+        state.u16(self.dot);
+        state.bool(self.odd_frame);
+        state.bool(self.nmi_pending);
+        state.bool(self.frame_complete);
+        state.bool(self.warming_up);
+    }
+
+    pub(crate) fn load_state(&mut self, state: &mut StateReader) -> Result<(), StateError> {
+        self.ctrl = state.u8()?;
+        self.mask = state.u8()?;
+        self.status = state.u8()?;
+        self.oam_addr = state.u8()?;
+        state.bytes_into(&mut self.oam)?;
+        state.bytes_into(&mut self.vram)?;
+        state.bytes_into(&mut self.palette)?;
+        self.v = state.u16_in(0..=0x7FFF, "PPU address")?;
+        self.t = state.u16_in(0..=0x7FFF, "PPU temporary address")?;
+        self.fine_x = state.u8_in(0..=7, "PPU fine X scroll")?;
+        self.write_latch = state.bool()?;
+        self.read_buffer = state.u8()?;
+        self.open_bus = state.u8()?;
+        state.bytes_into(&mut self.frame.data)?;
+        self.scanline = state.u16_in(0..=PRE_RENDER_SCANLINE, "PPU scanline")?;
+        self.dot = state.u16_in(0..=DOTS_PER_SCANLINE - 1, "PPU dot")?;
+        self.odd_frame = state.bool()?;
+        self.nmi_pending = state.bool()?;
+        self.frame_complete = state.bool()?;
+        self.warming_up = state.bool()?;
+        self.oam_row_access = [self.dots; 32];
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::Nrom;
+
+    fn set_addr(ppu: &mut Ppu, mapper: &mut dyn Mapper, addr: u16) {
+        ppu.write_register(0x2006, (addr >> 8) as u8, mapper);
+        ppu.write_register(0x2006, addr as u8, mapper);
+    }
+
+    #[test]
+    fn buffers_ppudata_reads() {
+        let mut mapper = Nrom::blank(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        set_addr(&mut ppu, &mut mapper, 0x2400);
+        ppu.write_register(0x2007, 0x11, &mut mapper);
+        ppu.write_register(0x2007, 0x22, &mut mapper);
+
+        set_addr(&mut ppu, &mut mapper, 0x2400);
+        ppu.read_register(0x2007, &mapper);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x11);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x22);
+    }
+
+    #[test]
+    fn reads_palette_without_buffer() {
+        let mut mapper = Nrom::blank(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        set_addr(&mut ppu, &mut mapper, 0x3F10);
+        ppu.write_register(0x2007, 0x0F, &mut mapper);
+        set_addr(&mut ppu, &mut mapper, 0x3F00);
+        assert_eq!(ppu.read_register(0x2007, &mapper), 0x0F);
+    }
+
+    #[test]
+    fn increments_by_32() {
+        let mut mapper = Nrom::blank(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, CTRL_VRAM_INCREMENT, &mut mapper);
+        set_addr(&mut ppu, &mut mapper, 0x2000);
+        ppu.write_register(0x2007, 0x01, &mut mapper);
+        ppu.write_register(0x2007, 0x02, &mut mapper);
+        assert_eq!(ppu.read_memory(0x2020, &mapper), 0x02);
+    }
+
+    #[test]
+    fn mirrors_nametables() {
+        let mut mapper = Nrom::blank(Mirroring::Vertical);
+        let mut ppu = Ppu::new();
+        ppu.write_memory(0x2005, 0x33, &mut mapper);
+        assert_eq!(ppu.read_memory(0x2805, &mapper), 0x33);
+        assert_eq!(ppu.read_memory(0x2405, &mapper), 0x00);
+        // 0x3000-0x3EFF mirrors 0x2000-0x2EFF
+        assert_eq!(ppu.read_memory(0x3005, &mapper), 0x33);
+
+        let mapper = Nrom::blank(Mirroring::Horizontal);
+        assert_eq!(ppu.read_memory(0x2405, &mapper), 0x33);
+    }
+
+    #[test]
+    fn status_read_clears_vblank_and_latch() {
+        let mut mapper = Nrom::blank(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.status = STATUS_VBLANK;
+        ppu.write_register(0x2005, 0x12, &mut mapper);
+        assert_eq!(
+            ppu.peek_register(0x2002, &mapper) & STATUS_VBLANK,
+            STATUS_VBLANK
+        );
+        assert_eq!(
+            ppu.read_register(0x2002, &mapper) & STATUS_VBLANK,
+            STATUS_VBLANK
+        );
+        assert_eq!(ppu.read_register(0x2002, &mapper) & STATUS_VBLANK, 0);
+
+        // the latch reset makes the next write the x scroll again
+        ppu.write_register(0x2005, 0x34, &mut mapper);
+        assert_eq!(ppu.scroll(), (0x34, 0));
+    }
+
+    #[test]
+    fn writes_oam_through_oamdata() {
+        let mut mapper = Nrom::blank(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2003, 0xFF, &mut mapper);
+        ppu.write_register(0x2004, 0xAA, &mut mapper);
+        ppu.write_register(0x2004, 0xBB, &mut mapper);
+        assert_eq!((ppu.oam()[0xFF], ppu.oam()[0]), (0xAA, 0xBB));
+    }
+
+    #[test]
+    fn builds_scroll_in_t_and_copies_on_second_ppuaddr_write() {
+        let mut mapper = Nrom::blank(Mirroring::Horizontal);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, 0b10, &mut mapper);
+        ppu.write_register(0x2005, 0x7D, &mut mapper);
+        ppu.write_register(0x2005, 0x5E, &mut mapper);
+        // fine y 6, nametable 2, coarse y 11, coarse x 15
+        assert_eq!(ppu.t, 0b0110_1001_0110_1111);
+        assert_eq!(ppu.fine_x, 0b101);
+        assert_eq!(ppu.scroll(), (0x7D, 0x5E));
+
+        ppu.write_register(0x2006, 0x3D, &mut mapper);
+        assert_eq!(ppu.v, 0);
+        ppu.write_register(0x2006, 0xF0, &mut mapper);
+        assert_eq!((ppu.t, ppu.v), (0x3DF0, 0x3DF0));
+    }
+}
@@ -0,0 +1,559 @@
+use super::frame::{HEIGHT, WIDTH};
+use super::palette::SYSTEM_PALETTE;
+use super::{palette_offset, Ppu, STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_ZERO_HIT};
+use crate::mapper::Mapper;
+
+const CTRL_SPRITE_TABLE: u8 = 0b0000_1000;
+const CTRL_BACKGROUND_TABLE: u8 = 0b0001_0000;
+const CTRL_SPRITE_SIZE: u8 = 0b0010_0000;
+const MASK_GREYSCALE: u8 = 0b0000_0001;
+const MASK_BACKGROUND_LEFT: u8 = 0b0000_0010;
+const MASK_SPRITES_LEFT: u8 = 0b0000_0100;
+const MASK_BACKGROUND: u8 = 0b0000_1000;
+const MASK_SPRITES: u8 = 0b0001_0000;
+const MASK_EMPHASIS: u8 = 0b1110_0000;
+const SPRITE_PRIORITY: u8 = 0b0010_0000;
+const SPRITE_FLIP_HORIZONTAL: u8 = 0b0100_0000;
+const SPRITE_FLIP_VERTICAL: u8 = 0b1000_0000;
+
+// one sprite's pattern row on the scanline being drawn
+struct SpriteRow {
+    x: u8,
+    low: u8,
+    high: u8,
+    attributes: u8,
+    zero: bool,
+}
+
+impl SpriteRow {
+    // 2-bit pixel value at screen column x, 0 where transparent or outside
+    fn value(&self, x: usize) -> u8 {
+        match x.checked_sub(self.x as usize) {
+            Some(column) if column < 8 => {
+                let bit = 7 - column;
+                ((self.high >> bit) & 1) << 1 | ((self.low >> bit) & 1)
+            }
+            _ => 0,
+        }
+    }
+}
+
+// palette RAM index of the frontmost opaque sprite pixel at x, and whether
+// that sprite sits behind the background
+fn sprite_color(sprites: &[SpriteRow], x: usize) -> Option<(u8, bool)> {
+    sprites.iter().find_map(|sprite| {
+        let value = sprite.value(x);
+        if value == 0 {
+            return None;
+        }
+        let palette = sprite.attributes & 0b11;
+        Some((
+            0x10 + palette * 4 + value,
+            sprite.attributes & SPRITE_PRIORITY != 0,
+        ))
+    })
+}
+
+impl Ppu {
+    // draws the whole picture into the frame buffer
+    pub fn render(&mut self, mapper: &dyn Mapper) {
+        self.start_frame();
+        for y in 0..HEIGHT {
+            self.render_scanline(y, mapper);
+        }
+    }
+
+    // the pre-render line clears the flags and reloads the vertical scroll
+    pub(crate) fn start_frame(&mut self) {
+        self.status &= !(STATUS_SPRITE_OVERFLOW | STATUS_SPRITE_ZERO_HIT);
+        self.reload_vertical_scroll();
+    }
+
+    pub(super) fn reload_vertical_scroll(&mut self) {
+        if self.rendering_enabled() {
+            self.v = (self.v & !0x7BE0) | (self.t & 0x7BE0);
+        }
+    }
+
+    pub(super) fn rendering_enabled(&self) -> bool {
+        self.mask & (MASK_BACKGROUND | MASK_SPRITES) != 0
+    }
+
+    // draws one visible line, reloading the horizontal scroll from t
+    // first and stepping v down a line after, as the PPU does around dot 256
+    pub(crate) fn render_scanline(&mut self, y: usize, mapper: &dyn Mapper) {
+        // taken out for the call so the callback can borrow the PPU
+        if let Some(mut callback) = self.scanline_callback.take() {
+            callback(self, y as u16);
+            self.scanline_callback.get_or_insert(callback);
+        }
+        if self.rendering_enabled() {
+            self.v = (self.v & !0x041F) | (self.t & 0x041F);
+        }
+        if self.rendering_enabled() {
+            self.refresh_all_oam();
+        }
+        let sprites = self.evaluate_sprites(y, mapper);
+        for x in 0..WIDTH {
+            let show_background = self.mask & MASK_BACKGROUND != 0
+                && (x >= 8 || self.mask & MASK_BACKGROUND_LEFT != 0);
+            let show_sprites =
+                self.mask & MASK_SPRITES != 0 && (x >= 8 || self.mask & MASK_SPRITES_LEFT != 0);
+            let background = if show_background {
+                self.background_color(x, mapper)
+            } else {
+                0
+            };
+            let sprite = if show_sprites {
+                sprite_color(&sprites, x)
+            } else {
+                None
+            };
+            // sprite 0 hits wherever both layers are opaque, whatever the
+            // priorities, though never in the last column
+            if show_background
+                && show_sprites
+                && background != 0
+                && x != WIDTH - 1
+                && sprites
+                    .first()
+                    .is_some_and(|sprite| sprite.zero && sprite.value(x) != 0)
+            {
+                self.status |= STATUS_SPRITE_ZERO_HIT;
+            }
+            let color = match sprite {
+                Some((color, behind)) if !behind || background == 0 => color,
+                _ => background,
+            };
+            let index = self.palette_index(color);
+            self.frame.set_pixel(x, y, SYSTEM_PALETTE[index as usize]);
+            let emphasis = (self.mask & MASK_EMPHASIS) as u16;
+            self.indexed_frame
+                .set_pixel(x, y, emphasis << 1 | index as u16);
+        }
+        if self.rendering_enabled() {
+            self.increment_y();
+        }
+    }
+
+    fn increment_y(&mut self) {
+        if self.v & 0x7000 != 0x7000 {
+            self.v += 0x1000;
+            return;
+        }
+        self.v &= !0x7000;
+        let coarse_y = match (self.v >> 5) & 0x1F {
+            // the last row of tiles, wrap into the next nametable down
+            29 => {
+                self.v ^= 0x0800;
+                0
+            }
+            // rows 30 and 31 hold attributes, scrolling into them wraps
+            // without switching nametables
+            31 => 0,
+            y => y + 1,
+        };
+        self.v = (self.v & !0x03E0) | (coarse_y << 5);
+    }
+
+    // picks the first eight sprites in OAM order that cover scanline y and
+    // fetches their pattern row, flagging overflow when there are more
+    fn evaluate_sprites(&mut self, y: usize, mapper: &dyn Mapper) -> Vec<SpriteRow> {
+        let height = if self.ctrl & CTRL_SPRITE_SIZE != 0 {
+            16
+        } else {
+            8
+        };
+        let mut sprites = Vec::with_capacity(8);
+        for (index, sprite) in self.oam.chunks_exact(4).enumerate() {
+            // sprites are drawn one line below their OAM y
+            let row = y as isize - sprite[0] as isize - 1;
+            if !(0..height).contains(&row) {
+                continue;
+            }
+            if sprites.len() == 8 {
+                self.status |= STATUS_SPRITE_OVERFLOW;
+                break;
+            }
+            let (tile, attributes, x) = (sprite[1], sprite[2], sprite[3]);
+            let row = if attributes & SPRITE_FLIP_VERTICAL != 0 {
+                height - 1 - row
+            } else {
+                row
+            } as u16;
+            let addr = if height == 16 {
+                // bit 0 of the tile picks the table, the bottom half is the
+                // next tile
+                let table = (tile as u16 & 1) * 0x1000;
+                table + (tile as u16 & 0xFE) * 16 + (row / 8) * 16 + row % 8
+            } else {
+                let table = if self.ctrl & CTRL_SPRITE_TABLE != 0 {
+                    0x1000
+                } else {
+                    0
+                };
+                table + tile as u16 * 16 + row
+            };
+            let (mut low, mut high) = (
+                self.read_memory(addr, mapper),
+                self.read_memory(addr + 8, mapper),
+            );
+            if attributes & SPRITE_FLIP_HORIZONTAL != 0 {
+                low = low.reverse_bits();
+                high = high.reverse_bits();
+            }
+            sprites.push(SpriteRow {
+                x,
+                low,
+                high,
+                attributes,
+                zero: index == 0,
+            });
+        }
+        sprites
+    }
+
+    // index into palette RAM of the background at column x of the line v
+    // points at, 0 for the transparent backdrop
+    fn background_color(&self, x: usize, mapper: &dyn Mapper) -> u8 {
+        // pixel column counted from the left edge of v's nametable, past
+        // 255 it is in the horizontally adjacent one
+        let column = (self.v & 0x1F) as usize * 8 + self.fine_x as usize + x;
+        let col = (column / 8) % 32;
+        let table = ((self.v >> 10) & 0b11) as usize ^ ((column / 256) & 1);
+        let row = ((self.v >> 5) & 0x1F) as usize;
+        let fine_y = (self.v >> 12) as usize;
+
+        let base = 0x2000 + table as u16 * 0x400;
+        let tile = self.read_memory(base + (row * 32 + col) as u16, mapper);
+        let attribute = self.read_memory(base + 0x3C0 + (row / 4 * 8 + col / 4) as u16, mapper);
+        let shift = (row % 4 / 2) * 4 + (col % 4 / 2) * 2;
+        let palette = (attribute >> shift) & 0b11;
+
+        let pattern = if self.ctrl & CTRL_BACKGROUND_TABLE != 0 {
+            0x1000
+        } else {
+            0
+        };
+        let addr = pattern + tile as u16 * 16 + fine_y as u16;
+        let bit = 7 - column % 8;
+        let low = (self.read_memory(addr, mapper) >> bit) & 1;
+        let high = (self.read_memory(addr + 8, mapper) >> bit) & 1;
+        match (high << 1) | low {
+            0 => 0,
+            value => palette * 4 + value,
+        }
+    }
+
+    // the system palette entry palette RAM holds for color
+    fn palette_index(&self, color: u8) -> u8 {
+        let entry = self.palette[palette_offset(color as u16)] & 0x3F;
+        if self.mask & MASK_GREYSCALE != 0 {
+            entry & 0x30
+        } else {
+            entry
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cartridge::Mirroring;
+    use crate::mapper::{Chr, Nrom};
+
+    // CHR with tile 1 solid color 1, tile 2 solid color 3 and tile 3 only
+    // its top left pixel in color 1
+    fn mapper() -> Nrom {
+        let mut chr_rom = vec![0; 0x2000];
+        chr_rom[16..24].fill(0xFF);
+        chr_rom[32..48].fill(0xFF);
+        chr_rom[48] = 0b1000_0000;
+        Nrom::new(vec![0; 0x8000], Chr::new(chr_rom, 0), Mirroring::Vertical)
+    }
+
+    fn ppu(mapper: &mut Nrom) -> Ppu {
+        let mut ppu = Ppu::new();
+        ppu.mask = MASK_BACKGROUND | MASK_BACKGROUND_LEFT;
+        for (i, color) in [0x0F, 0x01, 0x02, 0x03, 0x0F, 0x11, 0x12, 0x13]
+            .into_iter()
+            .enumerate()
+        {
+            ppu.write_memory(0x3F00 + i as u16, color, mapper);
+        }
+        ppu
+    }
+
+    #[test]
+    fn draws_tiles_with_attribute_palettes() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_memory(0x2000, 1, &mut mapper);
+        // the second 16x16 area (tiles 2-3 of row 0) uses palette 1
+        ppu.write_memory(0x2002, 2, &mut mapper);
+        ppu.write_memory(0x23C0, 0b0000_0100, &mut mapper);
+        ppu.render(&mapper);
+
+        assert_eq!(ppu.frame().pixel(0, 0), SYSTEM_PALETTE[0x01]);
+        assert_eq!(ppu.frame().pixel(16, 7), SYSTEM_PALETTE[0x13]);
+        assert_eq!(ppu.frame().pixel(8, 0), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn keeps_palette_indices_with_emphasis() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_memory(0x2002, 2, &mut mapper);
+        ppu.write_memory(0x23C0, 0b0000_0100, &mut mapper);
+        ppu.mask |= 0b1010_0000;
+        ppu.render(&mapper);
+
+        let indexed = ppu.indexed_frame();
+        assert_eq!(indexed.palette_index(16, 0), 0x13);
+        assert_eq!(indexed.palette_index(0, 0), 0x0F);
+        assert_eq!(indexed.emphasis(16, 0), 0b101);
+
+        ppu.mask |= MASK_GREYSCALE;
+        ppu.render(&mapper);
+        assert_eq!(ppu.indexed_frame().palette_index(16, 0), 0x10);
+    }
+
+    #[test]
+    fn scrolls_into_next_nametable() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_memory(0x2400, 2, &mut mapper);
+        ppu.write_register(0x2005, 8, &mut mapper);
+        ppu.write_register(0x2005, 0, &mut mapper);
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(248, 0), SYSTEM_PALETTE[0x03]);
+        assert_eq!(ppu.frame().pixel(240, 0), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn hides_background_in_left_column() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_memory(0x2000, 1, &mut mapper);
+        ppu.mask = MASK_BACKGROUND;
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(0, 0), SYSTEM_PALETTE[0x0F]);
+    }
+
+    fn place_sprite(ppu: &mut Ppu, index: usize, sprite: [u8; 4]) {
+        ppu.oam[index * 4..index * 4 + 4].copy_from_slice(&sprite);
+    }
+
+    fn sprite_ppu(mapper: &mut Nrom) -> Ppu {
+        let mut ppu = ppu(mapper);
+        ppu.mask |= MASK_SPRITES | MASK_SPRITES_LEFT;
+        ppu.write_memory(0x3F11, 0x21, mapper);
+        ppu.write_memory(0x3F13, 0x23, mapper);
+        ppu.write_memory(0x3F17, 0x27, mapper);
+        // park every sprite below the picture
+        ppu.oam.fill(0xFF);
+        ppu
+    }
+
+    #[test]
+    fn draws_sprites_one_line_down() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        place_sprite(&mut ppu, 0, [9, 1, 0, 20]);
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(20, 9), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(ppu.frame().pixel(20, 10), SYSTEM_PALETTE[0x21]);
+        assert_eq!(ppu.frame().pixel(27, 17), SYSTEM_PALETTE[0x21]);
+        assert_eq!(ppu.frame().pixel(28, 10), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn lower_oam_index_wins_and_priority_yields_to_background() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        place_sprite(&mut ppu, 0, [0, 1, 0, 0]);
+        place_sprite(&mut ppu, 1, [0, 2, 1, 4]);
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(4, 1), SYSTEM_PALETTE[0x21]);
+        assert_eq!(ppu.frame().pixel(8, 1), SYSTEM_PALETTE[0x27]);
+
+        ppu.write_memory(0x2000, 1, &mut mapper);
+        place_sprite(&mut ppu, 0, [0, 2, SPRITE_PRIORITY, 0]);
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(0, 1), SYSTEM_PALETTE[0x01]);
+        // a transparent background lets the sprite through
+        assert_eq!(ppu.frame().pixel(8, 1), SYSTEM_PALETTE[0x27]);
+    }
+
+    #[test]
+    fn flips_sprites() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        place_sprite(
+            &mut ppu,
+            0,
+            [0, 3, SPRITE_FLIP_HORIZONTAL | SPRITE_FLIP_VERTICAL, 0],
+        );
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(0, 1), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(ppu.frame().pixel(7, 8), SYSTEM_PALETTE[0x21]);
+    }
+
+    #[test]
+    fn draws_8x16_sprites_from_tile_pairs() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        ppu.ctrl |= CTRL_SPRITE_SIZE;
+        // tiles 2 and 3 from the first table
+        place_sprite(&mut ppu, 0, [0, 2, 0, 0]);
+        ppu.render(&mapper);
+        assert_eq!(ppu.frame().pixel(1, 1), SYSTEM_PALETTE[0x23]);
+        assert_eq!(ppu.frame().pixel(0, 9), SYSTEM_PALETTE[0x21]);
+        assert_eq!(ppu.frame().pixel(1, 9), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn flags_more_than_eight_sprites_on_a_line() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        for i in 0..8 {
+            place_sprite(&mut ppu, i, [50, 1, 0, i as u8 * 10]);
+        }
+        ppu.render(&mapper);
+        assert!(!ppu.sprite_overflow());
+        place_sprite(&mut ppu, 8, [55, 1, 0, 200]);
+        ppu.render(&mapper);
+        assert!(ppu.sprite_overflow());
+        // the ninth sprite is dropped on the lines it shares
+        assert_eq!(ppu.frame().pixel(200, 57), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(ppu.frame().pixel(200, 62), SYSTEM_PALETTE[0x21]);
+    }
+
+    #[test]
+    fn detects_sprite_zero_hit() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        // background tile at column 2, sprite 0 behind it and sprite 1
+        // over it
+        ppu.write_memory(0x2002, 1, &mut mapper);
+        place_sprite(&mut ppu, 1, [0, 1, 0, 16]);
+        place_sprite(&mut ppu, 0, [0, 1, SPRITE_PRIORITY, 16]);
+        ppu.render(&mapper);
+        assert!(ppu.sprite_zero_hit());
+
+        // a transparent background means no hit
+        place_sprite(&mut ppu, 0, [0, 1, 0, 40]);
+        ppu.render(&mapper);
+        assert!(!ppu.sprite_zero_hit());
+    }
+
+    #[test]
+    fn left_column_masking_blocks_sprite_zero_hit() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        ppu.write_memory(0x2000, 1, &mut mapper);
+        place_sprite(&mut ppu, 0, [0, 1, 0, 0]);
+        ppu.render(&mapper);
+        assert!(ppu.sprite_zero_hit());
+
+        ppu.mask &= !MASK_SPRITES_LEFT;
+        ppu.render(&mapper);
+        assert!(!ppu.sprite_zero_hit());
+
+        ppu.mask |= MASK_SPRITES_LEFT;
+        ppu.mask &= !MASK_BACKGROUND_LEFT;
+        ppu.render(&mapper);
+        assert!(!ppu.sprite_zero_hit());
+    }
+
+    #[test]
+    fn never_hits_in_last_column() {
+        let mut mapper = mapper();
+        let mut ppu = sprite_ppu(&mut mapper);
+        ppu.write_memory(0x201F, 1, &mut mapper);
+        place_sprite(&mut ppu, 0, [0, 3, 0, 255]);
+        ppu.render(&mapper);
+        assert!(!ppu.sprite_zero_hit());
+    }
+
+    #[test]
+    fn wraps_vertically_past_row_29() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // Vertical mirroring: 0x2800 is the same page as 0x2000
+        ppu.write_memory(0x2000, 2, &mut mapper);
+        ppu.write_register(0x2005, 0, &mut mapper);
+        ppu.write_register(0x2005, 232, &mut mapper);
+        ppu.render(&mapper);
+        // tile row 29 fills the first 8 lines, then row 0 follows
+        assert_eq!(ppu.frame().pixel(0, 7), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(ppu.frame().pixel(0, 8), SYSTEM_PALETTE[0x03]);
+    }
+
+    #[test]
+    fn mid_frame_scroll_change_splits_the_screen() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // tile 2 in column 1 of the first two rows
+        ppu.write_memory(0x2001, 2, &mut mapper);
+        ppu.write_memory(0x2021, 2, &mut mapper);
+        ppu.start_frame();
+        for y in 0..8 {
+            ppu.render_scanline(y, &mapper);
+        }
+        // a PPUSCROLL x write only reaches v at the next line's reload
+        ppu.write_register(0x2005, 8, &mut mapper);
+        ppu.write_register(0x2005, 0, &mut mapper);
+        for y in 8..16 {
+            ppu.render_scanline(y, &mapper);
+        }
+        assert_eq!(ppu.frame().pixel(8, 0), SYSTEM_PALETTE[0x03]);
+        assert_eq!(ppu.frame().pixel(0, 8), SYSTEM_PALETTE[0x03]);
+        assert_eq!(ppu.frame().pixel(8, 8), SYSTEM_PALETTE[0x0F]);
+    }
+
+    #[test]
+    fn ppuaddr_write_sets_vertical_scroll_mid_frame() {
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        // tile row 10 holds tile 2
+        ppu.write_memory(0x2000 + 10 * 32, 2, &mut mapper);
+        ppu.start_frame();
+        ppu.render_scanline(0, &mapper);
+        // point v at coarse y 10, fine y 0
+        ppu.write_register(0x2006, 0x01, &mut mapper);
+        ppu.write_register(0x2006, 0x40, &mut mapper);
+        ppu.render_scanline(1, &mapper);
+        assert_eq!(ppu.frame().pixel(0, 0), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(ppu.frame().pixel(0, 1), SYSTEM_PALETTE[0x03]);
+    }
+
+    #[test]
+    fn scanline_callback_splits_the_screen() {
+        use std::sync::atomic::{AtomicU16, Ordering};
+        use std::sync::Arc;
+
+        let mut mapper = mapper();
+        let mut ppu = ppu(&mut mapper);
+        ppu.write_memory(0x2000 + 10 * 32, 2, &mut mapper);
+        let lines = Arc::new(AtomicU16::new(0));
+        let seen = lines.clone();
+        // from line 100 on, show the nametable from y = 80
+        ppu.set_scanline_callback(move |ppu, line| {
+            seen.fetch_add(1, Ordering::Relaxed);
+            if line == 100 {
+                ppu.set_scroll(0, 80);
+            }
+        });
+        ppu.render(&mapper);
+        assert_eq!(lines.load(Ordering::Relaxed), 240);
+        assert_eq!(ppu.frame().pixel(0, 99), SYSTEM_PALETTE[0x0F]);
+        assert_eq!(ppu.frame().pixel(0, 100), SYSTEM_PALETTE[0x03]);
+        assert_eq!(ppu.frame().pixel(0, 107), SYSTEM_PALETTE[0x03]);
+        assert_eq!(ppu.frame().pixel(0, 108), SYSTEM_PALETTE[0x0F]);
+
+        ppu.clear_scanline_callback();
+        ppu.render(&mapper);
+        assert_eq!(lines.load(Ordering::Relaxed), 240);
+    }
+}
@@ -0,0 +1,251 @@
+use super::{Ppu, CTRL_NMI, STATUS_SPRITE_OVERFLOW, STATUS_SPRITE_ZERO_HIT, STATUS_VBLANK};
+use crate::mapper::Mapper;
+
+pub(super) const DOTS_PER_SCANLINE: u16 = 341;
+const VISIBLE_SCANLINES: u16 = 240;
+pub(super) const VBLANK_SCANLINE: u16 = 241;
+pub(super) const PRE_RENDER_SCANLINE: u16 = 261;
+
+impl Ppu {
+    // runs the PPU for a number of dots, three per CPU cycle on NTSC
+    pub fn tick(&mut self, dots: u32, mapper: &mut dyn Mapper) {
+        for _ in 0..dots {
+            self.step_dot(mapper);
+        }
+    }
+
+    fn step_dot(&mut self, mapper: &mut dyn Mapper) {
+        self.dots += 1;
+        match (self.scanline, self.dot) {
+            // lines are drawn whole, so register writes during the previous
+            // line's HBlank land on this one
+            (line, 1) if line < VISIBLE_SCANLINES => self.render_scanline(line as usize, mapper),
+            (VBLANK_SCANLINE, 1) => {
+                self.frame_complete = true;
+                if let Some(mailbox) = &self.mailbox {
+                    mailbox.publish(&self.frame, &mut self.spare_frame);
+                }
+                if !std::mem::take(&mut self.suppress_vblank) {
+                    self.status |= STATUS_VBLANK;
+                    if self.ctrl & CTRL_NMI != 0 {
+                        self.nmi_pending = true;
+                    }
+                }
+            }
+            (PRE_RENDER_SCANLINE, 1) => {
+                self.status &= !(STATUS_VBLANK | STATUS_SPRITE_ZERO_HIT | STATUS_SPRITE_OVERFLOW);
+                self.warming_up = false;
+            }
+            (PRE_RENDER_SCANLINE, 304) => self.reload_vertical_scroll(),
+            _ => {}
+        }
+        // roughly where the sprite fetches raise A12, which MMC3-style
+        // counters watch
+        if self.dot == 260
+            && (self.scanline < VISIBLE_SCANLINES || self.scanline == PRE_RENDER_SCANLINE)
+            && self.rendering_enabled()
+        {
+            mapper.clock_scanline();
+        }
+
+        self.dot += 1;
+        // odd frames drop the last dot of the pre-render line while
+        // rendering
+        if self.scanline == PRE_RENDER_SCANLINE
+            && self.dot == DOTS_PER_SCANLINE - 1
+            && self.odd_frame
+            && self.rendering_enabled()
+        {
+            self.dot += 1;
+        }
+        if self.dot == DOTS_PER_SCANLINE {
+            self.dot = 0;
+            self.scanline += 1;
+            if self.scanline > PRE_RENDER_SCANLINE {
+                self.scanline = 0;
+                self.odd_frame = !self.odd_frame;
+            }
+        }
+    }
+
+    pub fn scanline(&self) -> u16 {
+        self.scanline
+    }
+
+    pub fn dot(&self) -> u16 {
+        self.dot
+    }
+
+    pub(crate) fn take_nmi(&mut self) -> bool {
+        std::mem::take(&mut self.nmi_pending)
+    }
+
+    // true once per frame, when VBlank starts
+    pub(crate) fn take_frame_complete(&mut self) -> bool {
+        std::mem::take(&mut self.frame_complete)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::Nrom;
+
+    fn dots_to(scanline: u32, dot: u32) -> u32 {
+        scanline * DOTS_PER_SCANLINE as u32 + dot
+    }
+
+    #[test]
+    fn sets_vblank_on_scanline_241() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 1), &mut mapper);
+        assert_eq!(ppu.status() & STATUS_VBLANK, 0);
+        ppu.tick(1, &mut mapper);
+        assert_eq!(ppu.status() & STATUS_VBLANK, STATUS_VBLANK);
+        assert!(ppu.take_frame_complete());
+        assert!(!ppu.take_frame_complete());
+        // NMIs are off by default
+        assert!(!ppu.take_nmi());
+
+        ppu.tick(
+            dots_to((PRE_RENDER_SCANLINE - VBLANK_SCANLINE) as u32, 0),
+            &mut mapper,
+        );
+        assert_eq!(ppu.status() & STATUS_VBLANK, 0);
+    }
+
+    #[test]
+    fn raises_nmi_at_vblank_when_enabled() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 2), &mut mapper);
+        assert!(ppu.take_nmi());
+    }
+
+    #[test]
+    fn enabling_nmi_during_vblank_raises_one() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 2), &mut mapper);
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        assert!(ppu.take_nmi());
+        // rewriting the bit does not raise another
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        assert!(!ppu.take_nmi());
+    }
+
+    #[test]
+    fn reading_status_clears_vblank_and_write_toggle() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 10), &mut mapper);
+        ppu.write_register(0x2005, 0x08, &mut mapper);
+        assert_eq!(
+            ppu.read_register(0x2002, &mapper) & STATUS_VBLANK,
+            STATUS_VBLANK
+        );
+        assert_eq!(ppu.status() & STATUS_VBLANK, 0);
+        // the next PPUSCROLL write is a first write again
+        ppu.write_register(0x2005, 0x10, &mut mapper);
+        assert_eq!(ppu.scroll().0, 0x10);
+    }
+
+    #[test]
+    fn reading_status_as_vblank_sets_suppresses_it() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 1), &mut mapper);
+        assert_eq!(ppu.read_register(0x2002, &mapper) & STATUS_VBLANK, 0);
+        ppu.tick(3, &mut mapper);
+        assert_eq!(ppu.status() & STATUS_VBLANK, 0);
+        assert!(!ppu.take_nmi());
+        assert!(ppu.take_frame_complete());
+    }
+
+    #[test]
+    fn reading_status_just_after_vblank_sets_cancels_nmi() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 2), &mut mapper);
+        assert_eq!(
+            ppu.read_register(0x2002, &mapper) & STATUS_VBLANK,
+            STATUS_VBLANK
+        );
+        assert!(!ppu.take_nmi());
+
+        // a few dots later the NMI stands
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        ppu.tick(dots_to(VBLANK_SCANLINE as u32, 10), &mut mapper);
+        ppu.read_register(0x2002, &mapper);
+        assert!(ppu.take_nmi());
+    }
+
+    #[test]
+    fn oam_decays_only_when_enabled_and_left_alone() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2004, 0x42, &mut mapper);
+        ppu.tick(20_000, &mut mapper);
+        ppu.write_register(0x2003, 0, &mut mapper);
+        assert_eq!(ppu.read_register(0x2004, &mapper), 0x42);
+
+        ppu.set_oam_decay(true);
+        ppu.tick(1000, &mut mapper);
+        assert_eq!(ppu.read_register(0x2004, &mapper), 0x42);
+        // the whole row goes at once
+        ppu.tick(20_000, &mut mapper);
+        assert_eq!(ppu.read_register(0x2004, &mapper), 0x10);
+        assert_eq!(ppu.oam()[..8], [0x10; 8]);
+    }
+
+    #[test]
+    fn rendering_keeps_oam_refreshed() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.set_oam_decay(true);
+        ppu.write_register(0x2004, 0x42, &mut mapper);
+        ppu.write_register(0x2001, 0b0001_0000, &mut mapper);
+        ppu.tick(dots_to(PRE_RENDER_SCANLINE as u32 + 1, 0) * 2, &mut mapper);
+        ppu.write_register(0x2003, 0, &mut mapper);
+        assert_eq!(ppu.read_register(0x2004, &mapper), 0x42);
+    }
+
+    #[test]
+    fn ignores_some_writes_after_power_up() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.power_up();
+        assert_eq!(ppu.status(), STATUS_SPRITE_OVERFLOW);
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        ppu.write_register(0x2001, 0b0001_1000, &mut mapper);
+        ppu.write_register(0x2005, 0x08, &mut mapper);
+        ppu.write_register(0x2003, 0x20, &mut mapper);
+        assert_eq!((ppu.ctrl(), ppu.mask(), ppu.scroll()), (0, 0, (0, 0)));
+        assert_eq!(ppu.peek_register(0x2004, &mapper), ppu.oam()[0x20]);
+
+        // about 29658 CPU cycles in
+        ppu.tick(dots_to(PRE_RENDER_SCANLINE as u32, 1), &mut mapper);
+        assert!(ppu.warming_up());
+        ppu.tick(1, &mut mapper);
+        assert!(!ppu.warming_up());
+        ppu.write_register(0x2000, CTRL_NMI, &mut mapper);
+        assert_eq!(ppu.ctrl(), CTRL_NMI);
+    }
+
+    #[test]
+    fn skips_a_dot_on_odd_frames_while_rendering() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2001, 0b0000_1000, &mut mapper);
+        let frame = dots_to(PRE_RENDER_SCANLINE as u32 + 1, 0);
+        ppu.tick(frame, &mut mapper);
+        assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+        ppu.tick(frame - 1, &mut mapper);
+        assert_eq!((ppu.scanline(), ppu.dot()), (0, 0));
+    }
+}
@@ -0,0 +1,329 @@
+use std::error::Error;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::cartridge::Mirroring;
+
+const MAGIC: &[u8; 8] = b"NESSTATE";
+// bumped whenever the layout of any component changes
+pub const STATE_VERSION: u16 = 5;
+
+// a state written in another version of the format. Nothing converts
+// between versions, so such states have to be made again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StateVersionError {
+    pub found: u16,
+}
+
+impl fmt::Display for StateVersionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let age = if self.found < STATE_VERSION {
+            "older"
+        } else {
+            "newer"
+        };
+        write!(
+            f,
+            "save state version {} is {age} than this build, which only reads version {STATE_VERSION}",
+            self.found
+        )
+    }
+}
+
+impl Error for StateVersionError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    BadMagic,
+    UnsupportedVersion(StateVersionError),
+    // the snapshot was taken with a different cartridge
+    WrongRom { expected: u32, actual: u32 },
+    Truncated,
+    BadData,
+    // a field holds a value the hardware cannot have, such as a sequencer
+    // step past the end of its table
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(err) => write!(f, "{err}"),
+            StateError::WrongRom { expected, actual } => write!(
+                f,
+                "save state is for ROM {actual:08X}, but {expected:08X} is loaded"
+            ),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::BadData => write!(f, "save state is corrupt"),
+            StateError::InvalidValue(field) => write!(f, "save state has an invalid {field}"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+// little-endian fields one after the other, each component writing and
+// reading its own in the same order
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new(rom_crc32: u32) -> Self {
+        let mut writer = StateWriter {
+            data: MAGIC.to_vec(),
+        };
+        writer.u16(STATE_VERSION);
+        writer.u32(rom_crc32);
+        writer
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.data.extend(value.to_le_bytes());
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    // length-prefixed
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.data.extend(bytes);
+    }
+
+    pub fn mirroring(&mut self, mirroring: Mirroring) {
+        self.u8(match mirroring {
+            Mirroring::Horizontal => 0,
+            Mirroring::Vertical => 1,
+            Mirroring::FourScreen => 2,
+            Mirroring::SingleScreenLower => 3,
+            Mirroring::SingleScreenUpper => 4,
+        });
+    }
+}
+
+pub struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    // checks the header against the loaded cartridge
+    pub(crate) fn new(data: &'a [u8], rom_crc32: u32) -> Result<Self, StateError> {
+        let mut reader = StateReader { data };
+        if reader.take(MAGIC.len()).map_err(|_| StateError::BadMagic)? != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        let version = reader.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(StateVersionError {
+                found: version,
+            }));
+        }
+        let actual = reader.u32()?;
+        if actual != rom_crc32 {
+            return Err(StateError::WrongRom {
+                expected: rom_crc32,
+                actual,
+            });
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), StateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(StateError::BadData)
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(head)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // for fields that index a table or bound a counter
+    pub fn u8_in(
+        &mut self,
+        range: RangeInclusive<u8>,
+        field: &'static str,
+    ) -> Result<u8, StateError> {
+        check(self.u8()?, range, field)
+    }
+
+    pub fn u16_in(
+        &mut self,
+        range: RangeInclusive<u16>,
+        field: &'static str,
+    ) -> Result<u16, StateError> {
+        check(self.u16()?, range, field)
+    }
+
+    pub fn u32_in(
+        &mut self,
+        range: RangeInclusive<u32>,
+        field: &'static str,
+    ) -> Result<u32, StateError> {
+        check(self.u32()?, range, field)
+    }
+
+    pub fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::BadData),
+        }
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], StateError> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    // into a buffer whose size is fixed by the hardware
+    pub fn bytes_into(&mut self, buf: &mut [u8]) -> Result<(), StateError> {
+        let bytes = self.bytes()?;
+        if bytes.len() != buf.len() {
+            return Err(StateError::BadData);
+        }
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn mirroring(&mut self) -> Result<Mirroring, StateError> {
+        match self.u8()? {
+            0 => Ok(Mirroring::Horizontal),
+            1 => Ok(Mirroring::Vertical),
+            2 => Ok(Mirroring::FourScreen),
+            3 => Ok(Mirroring::SingleScreenLower),
+            4 => Ok(Mirroring::SingleScreenUpper),
+            _ => Err(StateError::BadData),
+        }
+    }
+}
+
+fn check<T: PartialOrd>(
+    value: T,
+    range: RangeInclusive<T>,
+    field: &'static str,
+) -> Result<T, StateError> {
+    if range.contains(&value) {
+        Ok(value)
+    } else {
+        Err(StateError::InvalidValue(field))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn round_trips_fields() {
+        let mut writer = StateWriter::new(0x1234);
+        writer.u8(1);
+        writer.u16(0x0203);
+        writer.u64(u64::MAX);
+        writer.bool(true);
+        writer.bytes(&[4, 5]);
+        writer.mirroring(Mirroring::SingleScreenUpper);
+        let data = writer.finish();
+
+        let mut reader = StateReader::new(&data, 0x1234).unwrap();
+        assert_eq!(reader.u8(), Ok(1));
+        assert_eq!(reader.u16(), Ok(0x0203));
+        assert_eq!(reader.u64(), Ok(u64::MAX));
+        assert_eq!(reader.bool(), Ok(true));
+        let mut buf = [0; 2];
+        reader.bytes_into(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5]);
+        assert_eq!(reader.mirroring(), Ok(Mirroring::SingleScreenUpper));
+        assert_eq!(reader.finish(), Ok(()));
+    }
+
+    #[test]
+    fn rejects_foreign_snapshots() {
+        let data = StateWriter::new(0x1234).finish();
+        assert!(matches!(
+            StateReader::new(&data, 0x9999),
+            Err(StateError::WrongRom {
+                expected: 0x9999,
+                actual: 0x1234
+            })
+        ));
+        assert!(matches!(
+            StateReader::new(b"NESSTAT", 0),
+            Err(StateError::BadMagic)
+        ));
+
+        let mut data = data;
+        data[8] = 99;
+        assert!(matches!(
+            StateReader::new(&data, 0x1234),
+            Err(StateError::UnsupportedVersion(StateVersionError {
+                found: 99
+            }))
+        ));
+        assert_eq!(
+            StateReader::new(&data, 0x1234).err().unwrap().to_string(),
+            format!(
+                "save state version 99 is newer than this build, which only reads version {STATE_VERSION}"
+            )
+        );
+        let data = StateWriter::new(0).finish();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(reader.u8(), Err(StateError::Truncated));
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        let mut writer = StateWriter::new(0);
+        writer.u8(3);
+        writer.u16(0x800);
+        let data = writer.finish();
+        let mut reader = StateReader::new(&data, 0).unwrap();
+        assert_eq!(reader.u8_in(0..=3, "duty"), Ok(3));
+        assert_eq!(
+            reader.u16_in(0..=0x7FF, "period"),
+            Err(StateError::InvalidValue("period"))
+        );
+    }
+}
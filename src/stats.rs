@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// NTSC frames per second
+const FRAME_RATE: f64 = 60.0988;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PlayStats {
+    pub frames: u64,
+    pub save_states: u64,
+    // seconds since the Unix epoch
+    pub last_played: Option<u64>,
+}
+
+impl PlayStats {
+    // emulated time, so fast-forward and pauses do not count as wall time
+    pub fn playtime(&self) -> Duration {
+        Duration::from_secs_f64(self.frames as f64 / FRAME_RATE)
+    }
+}
+
+// per-ROM statistics keyed by Rom::content_crc32, kept in a text file with
+// one "crc32 frames save_states last_played" line per ROM
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatsStore {
+    path: PathBuf,
+    entries: BTreeMap<u32, PlayStats>,
+}
+
+// $XDG_DATA_HOME/nes, falling back to ~/.local/share/nes
+pub fn data_dir() -> Option<PathBuf> {
+    let base = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(base.join("nes"))
+}
+
+impl StatsStore {
+    // a missing file is an empty store, lines that do not parse are dropped
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(err) => return Err(err),
+        };
+        let entries = text.lines().filter_map(parse_line).collect();
+        Ok(StatsStore {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn load_default() -> io::Result<Self> {
+        let dir = data_dir()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no data directory"))?;
+        Self::load(&dir.join("stats.txt"))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text: String = self
+            .entries
+            .iter()
+            .map(|(crc32, stats)| {
+                format!(
+                    "{:08X} {} {} {}\n",
+                    crc32,
+                    stats.frames,
+                    stats.save_states,
+                    stats.last_played.unwrap_or(0)
+                )
+            })
+            .collect();
+        fs::write(&self.path, text)
+    }
+
+    pub fn get(&self, crc32: u32) -> PlayStats {
+        self.entries.get(&crc32).copied().unwrap_or_default()
+    }
+
+    // adds a session's frames, see Nes::frames, and stamps it as played now
+    pub fn record_session(&mut self, crc32: u32, frames: u64) {
+        let stats = self.entries.entry(crc32).or_default();
+        stats.frames = stats.frames.saturating_add(frames);
+        stats.last_played = Some(now());
+    }
+
+    pub fn record_save_state(&mut self, crc32: u32) {
+        let stats = self.entries.entry(crc32).or_default();
+        stats.save_states = stats.save_states.saturating_add(1);
+    }
+}
+
+fn parse_line(line: &str) -> Option<(u32, PlayStats)> {
+    let mut fields = line.split_whitespace();
+    let crc32 = u32::from_str_radix(fields.next()?, 16).ok()?;
+    let frames = fields.next()?.parse().ok()?;
+    let save_states = fields.next()?.parse().ok()?;
+    let last_played = fields.next()?.parse().ok()?;
+    let stats = PlayStats {
+        frames,
+        save_states,
+        last_played: (last_played != 0).then_some(last_played),
+    };
+    Some((crc32, stats))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn persists_sessions_and_save_states() {
+        let path = std::env::temp_dir()
+            .join(format!("nes-stats-test-{}", std::process::id()))
+            .join("stats.txt");
+        let mut store = StatsStore::load(&path).unwrap();
+        assert_eq!(store.get(0x1234), PlayStats::default());
+        store.record_session(0x1234, 600);
+        store.record_session(0x1234, 601);
+        store.record_save_state(0x1234);
+        store.record_save_state(0xABCD);
+        store.save().unwrap();
+
+        let loaded = StatsStore::load(&path).unwrap();
+        assert_eq!(loaded, store);
+        let stats = loaded.get(0x1234);
+        assert_eq!((stats.frames, stats.save_states), (1201, 1));
+        assert!(stats.last_played.is_some());
+        assert_eq!(loaded.get(0xABCD).last_played, None);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn counters_saturate() {
+        let mut store = StatsStore::load(Path::new("/nonexistent/stats.txt")).unwrap();
+        store.entries.insert(
+            1,
+            PlayStats {
+                frames: u64::MAX - 1,
+                save_states: u64::MAX,
+                last_played: None,
+            },
+        );
+        store.record_session(1, 10);
+        store.record_save_state(1);
+        assert_eq!(store.get(1).frames, u64::MAX);
+        assert_eq!(store.get(1).save_states, u64::MAX);
+    }
+
+    #[test]
+    fn playtime_counts_emulated_frames() {
+        let stats = PlayStats {
+            frames: 60_099 * 60,
+            ..PlayStats::default()
+        };
+        assert_eq!(stats.playtime().as_secs() / 60, 1000);
+    }
+
+    #[test]
+    fn skips_malformed_lines() {
+        assert_eq!(parse_line("zz 1 2 3"), None);
+        assert_eq!(parse_line("00000001 1 2"), None);
+        assert_eq!(
+            parse_line("0000000A 5 1 99"),
+            Some((
+                10,
+                PlayStats {
+                    frames: 5,
+                    save_states: 1,
+                    last_played: Some(99)
+                }
+            ))
+        );
+    }
+}
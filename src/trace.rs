@@ -0,0 +1,123 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use crate::cpu::RegisterFile;
+
+pub const DEFAULT_TRACE_CAPACITY: usize = 100;
+
+// registers are captured before the instruction executes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u8,
+    pub registers: RegisterFile,
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let r = &self.registers;
+        write!(
+            f,
+            "{:04X}  {:02X}  A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            self.pc, self.opcode, r.accumulator, r.reg_x, r.reg_y, r.proc_status, r.stack_pointer
+        )
+    }
+}
+
+pub struct TraceRing {
+    entries: VecDeque<TraceEntry>,
+    capacity: usize,
+}
+
+impl TraceRing {
+    pub fn new(capacity: usize) -> Self {
+        TraceRing {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, entry: TraceEntry) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    pub fn set_capacity(&mut self, capacity: usize) {
+        while self.entries.len() > capacity {
+            self.entries.pop_front();
+        }
+        self.capacity = capacity;
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // oldest entry first
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.entries.iter().copied().collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+impl Default for TraceRing {
+    fn default() -> Self {
+        Self::new(DEFAULT_TRACE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(pc: u16) -> TraceEntry {
+        TraceEntry {
+            pc,
+            opcode: 0xea,
+            registers: RegisterFile::default(),
+        }
+    }
+
+    #[test]
+    fn keeps_last_entries() {
+        let mut ring = TraceRing::new(2);
+        ring.push(entry(1));
+        ring.push(entry(2));
+        ring.push(entry(3));
+        assert_eq!(ring.entries(), vec![entry(2), entry(3)]);
+    }
+
+    #[test]
+    fn shrinks_dropping_oldest() {
+        let mut ring = TraceRing::new(3);
+        ring.push(entry(1));
+        ring.push(entry(2));
+        ring.push(entry(3));
+        ring.set_capacity(1);
+        assert_eq!(ring.entries(), vec![entry(3)]);
+    }
+
+    #[test]
+    fn zero_capacity_disables() {
+        let mut ring = TraceRing::new(0);
+        ring.push(entry(1));
+        assert!(ring.entries().is_empty());
+    }
+
+    #[test]
+    fn formats_entry() {
+        let mut e = entry(0xC000);
+        e.registers.accumulator = 0x1f;
+        e.registers.proc_status = 0x24;
+        e.registers.stack_pointer = 0xfd;
+        assert_eq!(e.to_string(), "C000  EA  A:1F X:00 Y:00 P:24 SP:FD");
+    }
+}
@@ -0,0 +1,185 @@
+use crate::ppu::{Frame, HEIGHT, WIDTH};
+
+// how much of its own channel each column of the aperture grille keeps
+// from the other two, in percent
+const CRT_MASK_LEVEL: u16 = 70;
+
+// 8x is 2048x1920, past any display and small enough to allocate per frame
+pub const MAX_SCALE: usize = 8;
+
+// a frame blown up for display, packed RGB like Frame
+pub struct Scaled {
+    pub width: usize,
+    pub height: usize,
+    pub data: Vec<u8>,
+}
+
+// optional effects applied to finished frames before a frontend shows them
+pub struct PostProcess {
+    frame_blending: bool,
+    // the last unprocessed frame, kept while blending
+    previous: Option<Frame>,
+    scale: usize,
+    // percent taken off every other output row
+    scanlines: u8,
+    crt_mask: bool,
+}
+
+impl PostProcess {
+    pub fn new() -> Self {
+        PostProcess {
+            frame_blending: false,
+            previous: None,
+            scale: 1,
+            scanlines: 0,
+            crt_mask: false,
+        }
+    }
+
+    pub fn frame_blending(&self) -> bool {
+        self.frame_blending
+    }
+
+    // averages each frame with the one before, the way a slow display
+    // smears sprites that flicker at 30 Hz
+    pub fn set_frame_blending(&mut self, enabled: bool) {
+        self.frame_blending = enabled;
+        if !enabled {
+            self.previous = None;
+        }
+    }
+
+    // clamped to 1..=MAX_SCALE
+    pub fn set_scale(&mut self, scale: usize) {
+        self.scale = scale.clamp(1, MAX_SCALE);
+    }
+
+    // 0 turns scanlines off, 100 blacks out every other row
+    pub fn set_scanlines(&mut self, strength: u8) {
+        self.scanlines = strength.min(100);
+    }
+
+    // tints columns red, green and blue in turn like an aperture grille
+    pub fn set_crt_mask(&mut self, enabled: bool) {
+        self.crt_mask = enabled;
+    }
+
+    // scales a frame by whole pixels and applies the display effects
+    pub fn scale_output(&self, frame: &Frame) -> Scaled {
+        let (width, height) = (WIDTH * self.scale, HEIGHT * self.scale);
+        let mut data = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            let row_level = if y % 2 == 1 {
+                100 - self.scanlines as u16
+            } else {
+                100
+            };
+            for x in 0..width {
+                let (r, g, b) = frame.pixel(x / self.scale, y / self.scale);
+                for (channel, value) in [r, g, b].into_iter().enumerate() {
+                    let mut level = row_level;
+                    if self.crt_mask && x % 3 != channel {
+                        level = level * CRT_MASK_LEVEL / 100;
+                    }
+                    data.push((value as u16 * level / 100) as u8);
+                }
+            }
+        }
+        Scaled {
+            width,
+            height,
+            data,
+        }
+    }
+
+    pub fn apply(&mut self, frame: &Frame) -> Frame {
+        let mut output = frame.clone();
+        if self.frame_blending {
+            if let Some(previous) = &self.previous {
+                for (out, &before) in output.data.iter_mut().zip(&previous.data) {
+                    *out = ((*out as u16 + before as u16) / 2) as u8;
+                }
+            }
+            self.previous = Some(frame.clone());
+        }
+        output
+    }
+}
+
+impl Default for PostProcess {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn solid(value: u8) -> Frame {
+        let mut frame = Frame::new();
+        frame.data.fill(value);
+        frame
+    }
+
+    #[test]
+    fn passes_frames_through_by_default() {
+        let mut post = PostProcess::new();
+        post.apply(&solid(200));
+        assert_eq!(post.apply(&solid(100)).data, solid(100).data);
+    }
+
+    #[test]
+    fn blends_with_previous_frame() {
+        let mut post = PostProcess::new();
+        post.set_frame_blending(true);
+        // the first frame has nothing to blend with
+        assert_eq!(post.apply(&solid(200)).data[0], 200);
+        assert_eq!(post.apply(&solid(100)).data[0], 150);
+        // blending uses the previous input, not the previous output
+        assert_eq!(post.apply(&solid(100)).data[0], 100);
+
+        post.set_frame_blending(false);
+        post.set_frame_blending(true);
+        assert_eq!(post.apply(&solid(50)).data[0], 50);
+    }
+
+    #[test]
+    fn scales_by_whole_pixels() {
+        let mut frame = solid(0);
+        frame.set_pixel(1, 0, (9, 8, 7));
+        let mut post = PostProcess::new();
+        post.set_scale(2);
+        let scaled = post.scale_output(&frame);
+        assert_eq!((scaled.width, scaled.height), (512, 480));
+        assert_eq!(&scaled.data[2 * 3..4 * 3], &[9, 8, 7, 9, 8, 7]);
+        assert_eq!(&scaled.data[(512 + 3) * 3..][..3], &[9, 8, 7]);
+    }
+
+    #[test]
+    fn clamps_scale() {
+        let mut post = PostProcess::new();
+        post.set_scale(usize::MAX);
+        assert_eq!(post.scale_output(&solid(0)).width, WIDTH * MAX_SCALE);
+        post.set_scale(0);
+        assert_eq!(post.scale_output(&solid(0)).width, WIDTH);
+    }
+
+    #[test]
+    fn darkens_every_other_row() {
+        let mut post = PostProcess::new();
+        post.set_scale(2);
+        post.set_scanlines(50);
+        let scaled = post.scale_output(&solid(200));
+        assert_eq!(scaled.data[0], 200);
+        assert_eq!(scaled.data[512 * 3], 100);
+    }
+
+    #[test]
+    fn masks_columns_by_channel() {
+        let mut post = PostProcess::new();
+        post.set_crt_mask(true);
+        let scaled = post.scale_output(&solid(100));
+        assert_eq!(&scaled.data[..9], &[100, 70, 70, 70, 100, 70, 70, 70, 100]);
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::{self, Seek, SeekFrom, Write};
+
+const HEADER_LEN: u32 = 44;
+
+// 16-bit PCM WAV from APU samples. The sizes in the header are only known
+// once the last sample is in, finish fills them in
+pub struct WavWriter<W: Write + Seek> {
+    out: W,
+    data_len: u32,
+}
+
+impl<W: Write + Seek> WavWriter<W> {
+    pub fn new(mut out: W, sample_rate: u32, channels: u16) -> io::Result<Self> {
+        let block_align = channels * 2;
+        out.write_all(b"RIFF")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        out.write_all(b"WAVEfmt ")?;
+        out.write_all(&16u32.to_le_bytes())?;
+        // integer PCM
+        out.write_all(&1u16.to_le_bytes())?;
+        out.write_all(&channels.to_le_bytes())?;
+        out.write_all(&sample_rate.to_le_bytes())?;
+        out.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+        out.write_all(&block_align.to_le_bytes())?;
+        out.write_all(&16u16.to_le_bytes())?;
+        out.write_all(b"data")?;
+        out.write_all(&0u32.to_le_bytes())?;
+        Ok(WavWriter { out, data_len: 0 })
+    }
+
+    // interleaved samples, clipped to -1.0..=1.0
+    pub fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let len = (samples.len() as u32)
+            .checked_mul(2)
+            .and_then(|len| self.data_len.checked_add(len))
+            .filter(|&len| len <= u32::MAX - HEADER_LEN)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "WAV file too large"))?;
+        for &sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            self.out.write_all(&value.to_le_bytes())?;
+        }
+        self.data_len = len;
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<W> {
+        self.out.seek(SeekFrom::Start(4))?;
+        self.out
+            .write_all(&(HEADER_LEN - 8 + self.data_len).to_le_bytes())?;
+        self.out.seek(SeekFrom::Start(40))?;
+        self.out.write_all(&self.data_len.to_le_bytes())?;
+        self.out.seek(SeekFrom::End(0))?;
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn writes_pcm_with_sizes() {
+        let mut wav = WavWriter::new(Cursor::new(Vec::new()), 44_100, 2).unwrap();
+        wav.write(&[0.0, 1.0, -1.0, 2.0]).unwrap();
+        let data = wav.finish().unwrap().into_inner();
+        assert_eq!(data.len(), 44 + 8);
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(data[4..8], 44u32.to_le_bytes());
+        assert_eq!(data[22..24], 2u16.to_le_bytes());
+        assert_eq!(data[24..28], 44_100u32.to_le_bytes());
+        assert_eq!(data[40..44], 8u32.to_le_bytes());
+        let samples: Vec<i16> = data[44..]
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]))
+            .collect();
+        assert_eq!(samples, vec![0, i16::MAX, -i16::MAX, i16::MAX]);
+    }
+}
@@ -0,0 +1,107 @@
+use crate::input::InputDevice;
+use crate::ppu::{Ppu, HEIGHT, WIDTH};
+
+const LIGHT_NOT_SENSED: u8 = 0b0000_1000;
+const TRIGGER: u8 = 0b0001_0000;
+// the photodiode stays lit for a few lines after the beam passes the spot
+const LIGHT_LINES: u16 = 20;
+// average of the RGB channels, white and the lightest colors pass
+const BRIGHTNESS: u16 = 0xC0;
+
+// the light gun, usually in the second port
+#[derive(Debug, Clone, Default)]
+pub struct Zapper {
+    x: usize,
+    y: usize,
+    trigger: bool,
+}
+
+impl Zapper {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // screen coordinates, clamped to the picture
+    pub fn aim(&mut self, x: usize, y: usize) {
+        self.x = x.min(WIDTH - 1);
+        self.y = y.min(HEIGHT - 1);
+    }
+
+    pub fn pull_trigger(&mut self) {
+        self.trigger = true;
+    }
+
+    pub fn release_trigger(&mut self) {
+        self.trigger = false;
+    }
+
+    // the frame buffer holds this frame's line y once the PPU has drawn it
+    fn light_sensed(&self, ppu: &Ppu) -> bool {
+        let since = ppu.scanline().wrapping_sub(self.y as u16);
+        if since >= LIGHT_LINES {
+            return false;
+        }
+        let (r, g, b) = ppu.frame().pixel(self.x, self.y);
+        (r as u16 + g as u16 + b as u16) / 3 >= BRIGHTNESS
+    }
+}
+
+// the photodiode and trigger are read directly, nothing is latched
+impl InputDevice for Zapper {
+    fn strobe(&mut self, _high: bool) {}
+
+    fn read(&mut self, ppu: &Ppu) -> u8 {
+        self.peek(ppu)
+    }
+
+    fn peek(&self, ppu: &Ppu) -> u8 {
+        let mut data = 0;
+        if !self.light_sensed(ppu) {
+            data |= LIGHT_NOT_SENSED;
+        }
+        if self.trigger {
+            data |= TRIGGER;
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::mapper::Nrom;
+
+    // a white backdrop, then the PPU run to the start of line `line`
+    fn ppu_at_line(line: u32) -> Ppu {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_memory(0x3F00, 0x30, &mut mapper);
+        ppu.write_register(0x2001, 0b0000_1010, &mut mapper);
+        ppu.tick(line * 341 + 2, &mut mapper);
+        ppu
+    }
+
+    #[test]
+    fn senses_light_just_after_the_beam() {
+        let mut zapper = Zapper::new();
+        zapper.aim(100, 50);
+        assert_eq!(zapper.read(&ppu_at_line(49)), LIGHT_NOT_SENSED);
+        assert_eq!(zapper.read(&ppu_at_line(50)), 0);
+        assert_eq!(zapper.read(&ppu_at_line(69)), 0);
+        assert_eq!(zapper.read(&ppu_at_line(70)), LIGHT_NOT_SENSED);
+    }
+
+    #[test]
+    fn dark_pixels_and_trigger() {
+        let mut mapper = Nrom::filled(0);
+        let mut ppu = Ppu::new();
+        ppu.write_register(0x2001, 0b0000_1010, &mut mapper);
+        ppu.tick(60 * 341, &mut mapper);
+        let mut zapper = Zapper::new();
+        zapper.aim(300, 50);
+        zapper.pull_trigger();
+        assert_eq!(zapper.read(&ppu), LIGHT_NOT_SENSED | TRIGGER);
+        zapper.release_trigger();
+        assert_eq!(zapper.read(&ppu), LIGHT_NOT_SENSED);
+    }
+}
@@ -0,0 +1,16 @@
+use nes::cartridge::NES_TAG;
+
+// a headered iNES image, page counts follow the ROM sizes
+pub fn ines(flags_6: u8, flags_7: u8, prg_rom: &[u8], chr_rom: &[u8]) -> Vec<u8> {
+    let mut raw = NES_TAG.to_vec();
+    raw.extend([
+        (prg_rom.len() / 0x4000) as u8,
+        (chr_rom.len() / 0x2000) as u8,
+        flags_6,
+        flags_7,
+    ]);
+    raw.resize(16, 0);
+    raw.extend_from_slice(prg_rom);
+    raw.extend_from_slice(chr_rom);
+    raw
+}
@@ -1,9 +1,114 @@
+mod common;
+
 use nes::cpu::CPU;
 
 #[test]
 fn test_5_ops_working_together() {
     let mut cpu = CPU::new();
     let program = vec![0xa9, 0xc0, 0xaa, 0xe8, 0x00];
-    cpu.load_and_run(program);
-    assert_eq!(cpu.reg_x, 0xc1)
+    cpu.load_and_run(program).unwrap();
+    assert_eq!(cpu.reg_x(), 0xc1)
+}
+
+#[test]
+fn test_multiplication_loop() {
+    let mut cpu = CPU::new();
+    // multiplies 6 by 7 into $10 by repeated addition
+    let program = vec![
+        0xa9, 0x00, // LDA #$00
+        0xa2, 0x07, // LDX #$07
+        0x18, //       CLC
+        0x69, 0x06, // ADC #$06
+        0xca, //       DEX
+        0xd0, 0xfa, // BNE -6
+        0x85, 0x10, // STA $10
+        0x00,
+    ];
+    cpu.load_and_run(program).unwrap();
+    assert_eq!(cpu.accumulator(), 42);
+    assert_eq!(cpu.reg_x(), 0);
+}
+
+#[test]
+fn test_independent_instances_in_parallel() {
+    fn assert_send<T: Send>() {}
+    assert_send::<CPU>();
+
+    let handles: Vec<_> = (0..8u8)
+        .map(|i| {
+            std::thread::spawn(move || {
+                let mut cpu = CPU::new();
+                // counts X up from 0 to i * 10
+                let program = vec![0xe8, 0xe0, i * 10, 0xd0, 0xfb, 0x00];
+                cpu.load_and_run(program).unwrap();
+                (i, cpu.reg_x(), cpu.opcode_stats())
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        let (i, reg_x, stats) = handle.join().unwrap();
+        assert_eq!(reg_x, i * 10);
+        let inx_count = stats.iter().find(|&&(op, _)| op == 0xe8).unwrap().1;
+        assert_eq!(inx_count, if i == 0 { 256 } else { i as u64 * 10 });
+    }
+}
+
+#[test]
+fn test_opcode_table_is_public() {
+    use nes::cpu::opcodes::{Mnemonic, OPCODES};
+    use nes::cpu::AddressingMode;
+
+    let jmp = OPCODES[0x6c].unwrap();
+    assert_eq!(jmp.mnemonic, Mnemonic::Jmp);
+    assert_eq!(jmp.mode, AddressingMode::Indirect);
+    assert_eq!((jmp.len, jmp.cycles), (3, 5));
+}
+
+#[test]
+fn test_boots_ines_file() {
+    use nes::cartridge::Rom;
+
+    let mut prg_rom = vec![0; 0x4000];
+    // LDA #$2a; BRK, with the reset vector pointing at $C000
+    prg_rom[..3].copy_from_slice(&[0xa9, 0x2a, 0x00]);
+    prg_rom[0x3ffc..].copy_from_slice(&[0x00, 0xc0, 0x00, 0x00]);
+    let raw = common::ines(0, 0, &prg_rom, &[0; 0x2000]);
+
+    let mut cpu = CPU::new();
+    cpu.load(Rom::from_bytes(&raw).unwrap()).unwrap();
+    cpu.reset();
+    cpu.run().unwrap();
+    assert_eq!(cpu.accumulator(), 0x2a);
+}
+
+#[test]
+fn test_switches_mmc1_prg_bank() {
+    use nes::cartridge::Rom;
+
+    let mut prg_rom = Vec::new();
+    for bank in 0..3 {
+        // LDA #bank; BRK
+        let mut data = vec![0xa9, bank, 0x00];
+        data.resize(0x4000, 0);
+        prg_rom.extend(data);
+    }
+    // the last bank is fixed at $C000 and selects bank 2 through the
+    // serial port, one bit per write, then jumps into it
+    let mut last = Vec::new();
+    for bit in [0, 1, 0, 0, 0] {
+        last.extend([0xa9, bit, 0x8d, 0x00, 0xe0]);
+    }
+    last.extend([0x4c, 0x00, 0x80]);
+    last.resize(0x4000, 0);
+    last[0x3ffc..].copy_from_slice(&[0x00, 0xc0, 0x00, 0x00]);
+    prg_rom.extend(last);
+    // 4 PRG banks, CHR RAM, mapper 1
+    let raw = common::ines(0x10, 0, &prg_rom, &[]);
+
+    let mut cpu = CPU::new();
+    cpu.load(Rom::from_bytes(&raw).unwrap()).unwrap();
+    cpu.reset();
+    cpu.run().unwrap();
+    assert_eq!(cpu.accumulator(), 2);
 }
@@ -0,0 +1,301 @@
+mod common;
+
+use nes::cartridge::Rom;
+use nes::checksum::crc32;
+use nes::state::{StateError, StateVersionError, STATE_VERSION};
+use nes::{FrameMailbox, Nes};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+// NROM-128 with the program at $C000, an NMI handler at $C100 and both
+// vectors set
+fn rom(program: &[u8], nmi_handler: &[u8]) -> Rom {
+    let mut prg_rom = vec![0xea; 0x4000];
+    prg_rom[..program.len()].copy_from_slice(program);
+    prg_rom[0x100..0x100 + nmi_handler.len()].copy_from_slice(nmi_handler);
+    prg_rom[0x3ffa..].copy_from_slice(&[0x00, 0xc1, 0x00, 0xc0, 0x00, 0x00]);
+    Rom::from_bytes(&common::ines(0, 0, &prg_rom, &[0; 0x2000])).unwrap()
+}
+
+// SEI, then BIT $2002; BPL twice so the PPU is past its power-up window,
+// then LDA #$80; STA $2000 to enable NMIs. Ends at $C010
+const WAIT_FOR_PPU: [u8; 16] = [
+    0x78, 0x2c, 0x02, 0x20, 0x10, 0xfb, 0x2c, 0x02, 0x20, 0x10, 0xfb, 0xa9, 0x80, 0x8d, 0x00, 0x20,
+];
+
+#[test]
+fn test_power_up_ignores_early_ppu_writes() {
+    // LDA #$80; STA $2000; JMP * without waiting
+    let program = [0xa9, 0x80, 0x8d, 0x00, 0x20, 0x4c, 0x05, 0xc0];
+    let mut nes = Nes::new();
+    nes.load(rom(&program, &[])).unwrap();
+    for _ in 0..3 {
+        nes.run_frame().unwrap();
+    }
+    assert_eq!(nes.nmis(), 0);
+    assert_eq!(nes.cpu().bus().ppu().ctrl(), 0);
+}
+
+#[test]
+fn test_run_frame_takes_one_frame_of_cycles() {
+    // JMP $C000
+    let mut nes = Nes::new();
+    nes.load(rom(&[0x4c, 0x00, 0xc0], &[])).unwrap();
+    nes.run_frame().unwrap();
+    let first = nes.cpu().cycles();
+    nes.run_frame().unwrap();
+    // 341 * 262 dots at three per CPU cycle, give or take an instruction
+    let frame = nes.cpu().cycles() - first;
+    assert!((29_776..=29_785).contains(&frame), "{frame}");
+}
+
+#[test]
+fn test_vblank_nmi_reaches_the_cpu() {
+    // SEI; two VBlank waits; LDA #$80; STA $2000; JMP *, with the handler
+    // counting into $00. SEI keeps the APU frame IRQ out, as on a real
+    // console
+    let mut program = WAIT_FOR_PPU.to_vec();
+    program.extend([0x4c, 0x10, 0xc0]);
+    let handler = [0xe6, 0x00, 0x40];
+    let mut nes = Nes::new();
+    nes.load(rom(&program, &handler)).unwrap();
+    for _ in 0..5 {
+        nes.run_frame().unwrap();
+    }
+    // NMIs come on during the third frame, and the NMI of the fifth frame
+    // is taken after run_frame returns
+    assert_eq!(nes.nmis(), 2);
+    assert_eq!(nes.frames(), 5);
+    nes.cpu_mut().step().unwrap();
+    assert_eq!(nes.cpu().bus().peek(0x0000), 3);
+}
+
+// end-to-end smoke test against a real cartridge, which cannot be shipped:
+//   NES_SMB_ROM=smb.nes cargo test -- --ignored
+// NES_SMB_FRAME_CRC pins the CRC32 of the 300th frame once it is known to
+// be right; without it the run only has to be deterministic
+#[test]
+#[ignore]
+fn test_super_mario_bros_reaches_title_screen() {
+    let path = std::env::var("NES_SMB_ROM").expect("NES_SMB_ROM is not set");
+    let rom = || Rom::from_bytes(&std::fs::read(&path).unwrap()).unwrap();
+    let run = || {
+        let mut nes = Nes::new();
+        nes.load(rom()).unwrap();
+        for _ in 0..300 {
+            nes.run_frame().unwrap();
+        }
+        (crc32(&nes.frame().data), nes.nmis())
+    };
+
+    let (hash, nmis) = run();
+    println!("frame crc32 {hash:08x} after {nmis} NMIs");
+    // the first frame or two pass before the game enables NMIs
+    assert!((295..=300).contains(&nmis), "{nmis}");
+    match std::env::var("NES_SMB_FRAME_CRC") {
+        Ok(expected) => assert_eq!(hash, u32::from_str_radix(&expected, 16).unwrap()),
+        Err(_) => assert_eq!(run().0, hash),
+    }
+}
+
+#[test]
+fn test_save_state_replays_identically() {
+    // enables NMIs, then INC $01 forever, the handler counting frames
+    // into $00
+    let mut program = WAIT_FOR_PPU.to_vec();
+    program.extend([0xe6, 0x01, 0x4c, 0x10, 0xc0]);
+    let handler = [0xe6, 0x00, 0x40];
+    let mut nes = Nes::new();
+    nes.load(rom(&program, &handler)).unwrap();
+    for _ in 0..4 {
+        nes.run_frame().unwrap();
+    }
+    let state = nes.save_state();
+
+    let run = |nes: &mut Nes| {
+        for _ in 0..3 {
+            nes.run_frame().unwrap();
+        }
+        let bus = nes.cpu().bus();
+        (
+            nes.cpu().cycles(),
+            bus.peek(0x0000),
+            bus.peek(0x0001),
+            crc32(&nes.frame().data),
+        )
+    };
+    let expected = run(&mut nes);
+    nes.load_state(&state).unwrap();
+    assert_eq!(run(&mut nes), expected);
+
+    // a fresh console with the same cartridge picks up from the snapshot
+    let mut other = Nes::new();
+    other.load(rom(&program, &handler)).unwrap();
+    other.load_state(&state).unwrap();
+    assert_eq!(run(&mut other), expected);
+}
+
+#[test]
+fn test_bad_save_states_leave_the_console_alone() {
+    let mut nes = Nes::new();
+    nes.load(rom(&[0x4c, 0x00, 0xc0], &[])).unwrap();
+    nes.run_frame().unwrap();
+    let state = nes.save_state();
+    nes.run_frame().unwrap();
+    let cycles = nes.cpu().cycles();
+
+    assert_eq!(
+        nes.load_state(&state[..state.len() - 1]),
+        Err(StateError::Truncated)
+    );
+    assert_eq!(nes.cpu().cycles(), cycles);
+    assert_eq!(nes.load_state(b"garbage"), Err(StateError::BadMagic));
+
+    let mut other = Nes::new();
+    other.load(rom(&[0x4c, 0x01, 0xc0], &[])).unwrap();
+    assert!(matches!(
+        other.load_state(&state),
+        Err(StateError::WrongRom { .. })
+    ));
+}
+
+// plays a square wave on pulse 1 forever
+fn pulse_rom() -> Rom {
+    // LDA #$01; STA $4015; LDA #$bf; STA $4000; LDA #$fd; STA $4002;
+    // LDA #$00; STA $4003; JMP *
+    let program = [
+        0xa9, 0x01, 0x8d, 0x15, 0x40, 0xa9, 0xbf, 0x8d, 0x00, 0x40, 0xa9, 0xfd, 0x8d, 0x02, 0x40,
+        0xa9, 0x00, 0x8d, 0x03, 0x40, 0x4c, 0x14, 0xc0,
+    ];
+    rom(&program, &[])
+}
+
+fn record_audio(nes: &mut Nes, frames: usize) -> Vec<f32> {
+    let samples = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&samples);
+    nes.cpu_mut()
+        .bus_mut()
+        .apu_mut()
+        .set_sample_sink(move |sample| sink.lock().unwrap().extend_from_slice(sample));
+    for _ in 0..frames {
+        nes.run_frame().unwrap();
+    }
+    nes.cpu_mut().bus_mut().apu_mut().clear_sample_sink();
+    Arc::try_unwrap(samples).unwrap().into_inner().unwrap()
+}
+
+#[test]
+fn test_headless_audio_is_reproducible() {
+    let mut first = Nes::new();
+    first.load(pulse_rom()).unwrap();
+    let mut second = Nes::new();
+    second.load(pulse_rom()).unwrap();
+    let audio = record_audio(&mut first, 10);
+    // 44.1 kHz for ten frames at 60.1 Hz
+    assert!((7_200..=7_400).contains(&audio.len()), "{}", audio.len());
+    assert!(audio.iter().any(|&sample| sample != 0.0));
+    assert_eq!(audio, record_audio(&mut second, 10));
+
+    let state = first.save_state();
+    let after = record_audio(&mut first, 5);
+    first.load_state(&state).unwrap();
+    assert_eq!(after, record_audio(&mut first, 5));
+}
+
+#[test]
+fn test_mailbox_gets_each_complete_frame() {
+    let mut nes = Nes::new();
+    nes.load(rom(&[0x4c, 0x00, 0xc0], &[])).unwrap();
+    let mailbox = Arc::new(FrameMailbox::new());
+    nes.cpu_mut()
+        .bus_mut()
+        .ppu_mut()
+        .set_frame_mailbox(Arc::clone(&mailbox));
+    let done = Arc::new(AtomicBool::new(false));
+    let reader = {
+        let (mailbox, done) = (Arc::clone(&mailbox), Arc::clone(&done));
+        std::thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                let frame = mailbox.latest();
+                assert_eq!(frame.data.len(), 256 * 240 * 3);
+            }
+        })
+    };
+    for _ in 0..3 {
+        nes.run_frame().unwrap();
+    }
+    done.store(true, Ordering::Relaxed);
+    reader.join().unwrap();
+    // frames published while the reader held the lock were skipped, one
+    // run with nobody reading always lands
+    let published = mailbox.published();
+    nes.run_frame().unwrap();
+    assert_eq!(mailbox.published(), published + 1);
+    assert_eq!(mailbox.latest().data, nes.frame().data);
+}
+
+// tests/data holds a state in every format so far, each written by the
+// version that introduced it with pulse_rom run for ten frames
+#[test]
+fn test_loads_current_states_and_rejects_older_ones() {
+    let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/data");
+    for version in 1..=STATE_VERSION {
+        let path = dir.join(format!("state_v{version}.bin"));
+        let data = std::fs::read(&path)
+            .unwrap_or_else(|err| panic!("{}: {err}, add one for new versions", path.display()));
+        let mut nes = Nes::new();
+        nes.load(pulse_rom()).unwrap();
+        let result = nes.load_state(&data);
+        if version == STATE_VERSION {
+            result.unwrap();
+            assert_eq!(nes.save_state(), data);
+            nes.run_frame().unwrap();
+        } else {
+            let err = StateVersionError { found: version };
+            assert_eq!(result, Err(StateError::UnsupportedVersion(err)));
+        }
+    }
+}
+
+#[test]
+fn test_run_ahead_shows_the_next_frame() {
+    // the handler sets the backdrop to the NMI count: INC $00;
+    // LDA #$3F; STA $2006; LDA #$00; STA $2006; LDA $00; AND #$3F;
+    // STA $2007; LDA #$00; STA $2006; STA $2006; RTI
+    let handler = [
+        0xe6, 0x00, 0xa9, 0x3f, 0x8d, 0x06, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20, 0xa5, 0x00, 0x29,
+        0x3f, 0x8d, 0x07, 0x20, 0xa9, 0x00, 0x8d, 0x06, 0x20, 0x8d, 0x06, 0x20, 0x40,
+    ];
+    let mut program = WAIT_FOR_PPU.to_vec();
+    // start pulse 1 too, so there is sound to compare; JMP *
+    program.extend([
+        0xa9, 0x01, 0x8d, 0x15, 0x40, 0xa9, 0xbf, 0x8d, 0x00, 0x40, 0xa9, 0x00, 0x8d, 0x03, 0x40,
+        0x4c, 0x1f, 0xc0,
+    ]);
+    let mut plain = Nes::new();
+    plain.load(rom(&program, &handler)).unwrap();
+    let mut ahead = Nes::new();
+    ahead.load(rom(&program, &handler)).unwrap();
+    ahead.set_run_ahead(1);
+    let mailbox = Arc::new(FrameMailbox::new());
+    ahead
+        .cpu_mut()
+        .bus_mut()
+        .ppu_mut()
+        .set_frame_mailbox(Arc::clone(&mailbox));
+
+    for _ in 0..8 {
+        plain.run_frame().unwrap();
+        ahead.run_frame().unwrap();
+        assert_eq!(ahead.save_state(), plain.save_state());
+    }
+    assert_eq!(ahead.frames(), 8);
+    let audio = ahead.cpu().bus().apu().output().to_vec();
+    assert_eq!(audio, plain.cpu().bus().apu().output());
+
+    plain.run_frame().unwrap();
+    assert_ne!(ahead.frame().data, ahead.cpu().bus().ppu().frame().data);
+    assert_eq!(ahead.frame().data, plain.frame().data);
+    assert_eq!(mailbox.published(), 8);
+    assert_eq!(mailbox.latest().data, plain.frame().data);
+}